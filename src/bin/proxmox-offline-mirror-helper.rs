@@ -5,7 +5,9 @@ use std::{collections::HashMap, path::Path};
 
 use anyhow::{Error, bail, format_err};
 
-use proxmox_offline_mirror::types::Snapshot;
+use proxmox_offline_mirror::types::{
+    PRODUCT_TYPE_SCHEMA, Snapshot, SourcesFormat, parse_product_type,
+};
 use proxmox_subscription::{ProductType, SubscriptionInfo};
 use proxmox_sys::command::run_command;
 use proxmox_sys::fs::file_get_contents;
@@ -22,7 +24,7 @@ use proxmox_schema::{api, param_bail};
 use proxmox_offline_mirror::helpers::tty::{
     read_bool_from_tty, read_selection_from_tty, read_string_from_tty,
 };
-use proxmox_offline_mirror::medium::{self, MediumState, generate_repo_snippet};
+use proxmox_offline_mirror::medium::{self, generate_repo_snippet};
 
 fn set_subscription_key(
     product: &ProductType,
@@ -59,15 +61,69 @@ fn set_subscription_key(
 #[api(
     input: {
         properties: {
+            "write-sources-dir": {
+                type: String,
+                optional: true,
+                description: "Write one '<mirror-id>.list' file per selected mirror into this directory when generating the sources.list.d snippet.",
+            },
+            "output-dir": {
+                type: String,
+                optional: true,
+                description: "Directory to save the generated sources.list.d snippet in, instead of '/etc/apt/sources.list.d'.",
+            },
+            "sources-file": {
+                type: String,
+                optional: true,
+                description: "Write all selected repositories to this file without prompting.",
+            },
+            "dry-run": {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Only print the generated sources.list.d snippet, without writing any file.",
+            },
+            "import-keys": {
+                type: String,
+                optional: true,
+                description: "Offer subscription keys from this file (created by 'proxmox-offline-mirror key export-for-medium') in addition to those on the medium.",
+            },
+            format: {
+                type: SourcesFormat,
+                optional: true,
+            },
         },
     },
 )]
 /// Interactive setup wizard.
-async fn setup(_param: Value) -> Result<(), Error> {
+async fn setup(
+    write_sources_dir: Option<String>,
+    output_dir: Option<String>,
+    sources_file: Option<String>,
+    dry_run: bool,
+    import_keys: Option<String>,
+    format: Option<SourcesFormat>,
+    _param: Value,
+) -> Result<(), Error> {
+    let format = format.unwrap_or_default();
+
     if !std::io::stdin().is_terminal() {
         bail!("Setup wizard can only run interactively.");
     }
 
+    if [&write_sources_dir, &output_dir, &sources_file]
+        .iter()
+        .filter(|option| option.is_some())
+        .count()
+        > 1
+    {
+        bail!("'write-sources-dir', 'output-dir' and 'sources-file' are mutually exclusive.");
+    }
+
+    let output_dir = output_dir.unwrap_or_else(|| "/etc/apt/sources.list.d".to_string());
+    if !dry_run && !Path::new(&output_dir).is_dir() {
+        bail!("Output directory '{output_dir}' doesn't exist.");
+    }
+
     let mountpoint = read_string_from_tty("Path to medium mountpoint", None)?;
     let mountpoint = Path::new(&mountpoint);
     if !mountpoint.exists() {
@@ -78,13 +134,20 @@ async fn setup(_param: Value) -> Result<(), Error> {
     statefile.push(".mirror-state");
 
     println!("Loading state from {statefile:?}..");
-    let raw = file_get_contents(&statefile)?;
-    let state: MediumState = serde_json::from_slice(&raw)?;
+    let mut state = medium::read_state(mountpoint)?;
     println!(
         "Last sync timestamp: {}",
         epoch_to_rfc3339_utc(state.last_sync)?
     );
 
+    if let Some(import_keys) = import_keys {
+        let raw = file_get_contents(&import_keys)?;
+        let imported: Vec<SubscriptionInfo> = serde_json::from_slice(&raw)
+            .map_err(|err| format_err!("Failed to parse keys from {import_keys:?} - {err}"))?;
+        println!("Imported {} keys from {import_keys:?}", imported.len());
+        state.subscriptions.extend(imported);
+    }
+
     let mut selected_repos = HashMap::new();
 
     enum Action {
@@ -163,11 +226,11 @@ async fn setup(_param: Value) -> Result<(), Error> {
                     .iter()
                     .map(|(snap, string)| (snap, string.as_ref()))
                     .collect();
-                let selected_snapshot = read_selection_from_tty(
-                    "Select snapshot",
-                    &snapshots,
-                    Some(snapshots.len() - 1),
-                )?;
+                let default = medium::default_snapshot(mountpoint, selected_mirror)?
+                    .and_then(|default| snapshots.iter().position(|(snap, _)| **snap == default))
+                    .unwrap_or(snapshots.len() - 1);
+                let selected_snapshot =
+                    read_selection_from_tty("Select snapshot", &snapshots, Some(default))?;
 
                 selected_repos.insert(
                     selected_mirror.to_string(),
@@ -187,19 +250,49 @@ async fn setup(_param: Value) -> Result<(), Error> {
                     read_selection_from_tty("Deselect mirror", &mirrors, None)?.to_string();
                 selected_repos.remove(&selected_mirror);
             }
+            Action::GenerateSourcesList if write_sources_dir.is_some() && !dry_run => {
+                let dest_dir = Path::new(write_sources_dir.as_deref().unwrap());
+                let snapshots: HashMap<String, Snapshot> = selected_repos
+                    .iter()
+                    .map(|(mirror, (_info, snapshot))| (mirror.clone(), *snapshot))
+                    .collect();
+                medium::generate_apt_sources_dir(mountpoint, &state, &snapshots, dest_dir, format)?;
+                for mirror in snapshots.keys() {
+                    println!(
+                        "Wrote {:?}",
+                        dest_dir.join(format!("{mirror}.{}", format.file_extension()))
+                    );
+                }
+
+                println!("Now run 'apt update && apt full-upgrade' to upgrade system.");
+                println!();
+            }
             Action::GenerateSourcesList => {
-                let lines = generate_repo_snippet(mountpoint, &selected_repos)?;
+                let lines = generate_repo_snippet(mountpoint, &selected_repos, format)?;
                 println!("Generated sources.list.d snippet:");
-                let data = lines.join("\n");
+                let data = lines.join(format.separator());
                 println!();
                 println!("-----8<-----");
                 println!("{data}");
                 println!("----->8-----");
-                if read_bool_from_tty("Configure snippet as repository source", Some(true))? {
+                if dry_run {
+                    println!("Dry run, not writing snippet.");
+                    println!();
+                    continue;
+                } else if let Some(sources_file) = &sources_file {
+                    replace_file(
+                        sources_file,
+                        data.as_bytes(),
+                        CreateOptions::default(),
+                        true,
+                    )?;
+                    println!("Wrote {sources_file:?}");
+                } else if read_bool_from_tty("Configure snippet as repository source", Some(true))?
+                {
                     let snippet_file_name = loop {
                         let file = read_string_from_tty(
-                            "Enter filename under '/etc/apt/sources.list.d/' (will be overwritten)",
-                            Some("offline-mirror.list"),
+                            &format!("Enter filename under '{output_dir}/' (will be overwritten)"),
+                            Some(&format!("offline-mirror.{}", format.file_extension())),
                         )?;
                         if file.contains('/') {
                             eprintln!("Invalid file name.");
@@ -207,12 +300,12 @@ async fn setup(_param: Value) -> Result<(), Error> {
                             break file;
                         }
                     };
-                    let mut file = PathBuf::from("/etc/apt/sources.list.d");
+                    let mut file = PathBuf::from(&output_dir);
                     file.push(snippet_file_name);
                     replace_file(file, data.as_bytes(), CreateOptions::default(), true)?;
                 } else {
                     println!(
-                        "Add above snippet to system's repository entries (/etc/apt/sources.list.d/) manually to configure."
+                        "Add above snippet to system's repository entries ({output_dir}/) manually to configure."
                     );
                 }
 
@@ -269,7 +362,7 @@ async fn setup(_param: Value) -> Result<(), Error> {
                 description: "Path to medium mountpoint",
             },
             product: {
-                type: ProductType,
+                schema: PRODUCT_TYPE_SCHEMA,
                 optional: true,
             },
         },
@@ -278,9 +371,10 @@ async fn setup(_param: Value) -> Result<(), Error> {
 /// Configures and offline subscription key
 async fn setup_offline_key(
     mountpoint: String,
-    product: Option<ProductType>,
+    product: Option<String>,
     _param: Value,
 ) -> Result<(), Error> {
+    let product = product.as_deref().map(parse_product_type).transpose()?;
     if product == Some(ProductType::Pom) {
         param_bail!(
             "product",
@@ -297,8 +391,7 @@ async fn setup_offline_key(
     statefile.push(".mirror-state");
 
     println!("Loading state from {statefile:?}..");
-    let raw = file_get_contents(&statefile)?;
-    let state: MediumState = serde_json::from_slice(&raw)?;
+    let state = medium::read_state(mountpoint)?;
     println!(
         "Last sync timestamp: {}",
         epoch_to_rfc3339_utc(state.last_sync)?
@@ -349,6 +442,10 @@ async fn setup_offline_key(
                 type: String,
                 description: "Path to medium mountpoint",
             },
+            format: {
+                type: SourcesFormat,
+                optional: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -357,19 +454,20 @@ async fn setup_offline_key(
     },
 )]
 /// Prints status of medium
-async fn status(mountpoint: String, param: Value) -> Result<(), Error> {
+async fn status(
+    mountpoint: String,
+    format: Option<SourcesFormat>,
+    param: Value,
+) -> Result<(), Error> {
     let output_format = get_output_format(&param);
+    let format = format.unwrap_or_default();
 
     let mountpoint = Path::new(&mountpoint);
     if !mountpoint.exists() {
         bail!("Medium mountpoint doesn't exist.");
     }
 
-    let mut statefile = mountpoint.to_path_buf();
-    statefile.push(".mirror-state");
-
-    let raw = file_get_contents(&statefile)?;
-    let state: MediumState = serde_json::from_slice(&raw)?;
+    let state = medium::read_state(mountpoint)?;
 
     if output_format == "text" {
         println!("Last sync: {}", epoch_to_rfc3339_utc(state.last_sync)?);
@@ -388,11 +486,11 @@ async fn status(mountpoint: String, param: Value) -> Result<(), Error> {
                             println!("No snapshots.");
                         }
                     };
-                    if let Some(last) = snapshots.last() {
+                    if let Some(default) = medium::default_snapshot(mountpoint, mirror)? {
                         println!(
                             "repository config: {}",
-                            proxmox_offline_mirror::generate_repo_file_line(
-                                mountpoint, mirror, info, last
+                            proxmox_offline_mirror::generate_repo_file_entry(
+                                mountpoint, mirror, info, &default, format
                             )?
                         );
                     }
@@ -434,12 +532,128 @@ async fn status(mountpoint: String, param: Value) -> Result<(), Error> {
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            mountpoint: {
+                type: String,
+                description: "Path to medium mountpoint",
+            },
+            mirror: {
+                type: String,
+                optional: true,
+                description: "Only list snapshots of this mirror",
+            },
+            format: {
+                type: SourcesFormat,
+                optional: true,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Lists snapshots available on medium, including their paths and sizes
+async fn snapshot_list(
+    mountpoint: String,
+    mirror: Option<String>,
+    format: Option<SourcesFormat>,
+    param: Value,
+) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let format = format.unwrap_or_default();
+
+    let mountpoint = Path::new(&mountpoint);
+    if !mountpoint.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let state = medium::read_state(mountpoint)?;
+
+    if let Some(mirror) = &mirror {
+        if !state.mirrors.contains_key(mirror) {
+            param_bail!("mirror", "Mirror '{mirror}' not found on medium.");
+        }
+    }
+
+    let mut mirrors: Vec<_> = state
+        .mirrors
+        .iter()
+        .filter(|(id, _info)| mirror.as_ref().is_none_or(|mirror| mirror == *id))
+        .collect();
+    mirrors.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut json = serde_json::Map::new();
+    for (mirror, info) in mirrors {
+        let snapshots = medium::list_snapshots(mountpoint, mirror)?;
+
+        let mut entries = Vec::with_capacity(snapshots.len());
+        for snapshot in &snapshots {
+            let path = mountpoint.join(mirror).join(snapshot.to_string());
+            let (file_count, size_bytes) = medium::snapshot_usage(mountpoint, mirror, snapshot)?;
+            entries.push((snapshot, path, file_count, size_bytes));
+        }
+
+        let repository = medium::default_snapshot(mountpoint, mirror)?
+            .map(|default| {
+                proxmox_offline_mirror::generate_repo_file_entry(
+                    mountpoint, mirror, info, &default, format,
+                )
+            })
+            .transpose()?;
+
+        if output_format == "text" {
+            println!("Mirror {mirror}:");
+            if entries.is_empty() {
+                println!("No snapshots.");
+            }
+            for (snapshot, path, file_count, size_bytes) in &entries {
+                println!("- {snapshot}: {path:?} ({file_count} files, {size_bytes}b)");
+            }
+            if let Some(repository) = &repository {
+                println!("repository config: {repository}");
+            }
+            println!();
+        } else {
+            json.insert(
+                mirror.to_owned(),
+                serde_json::json!({
+                    "snapshots": entries
+                        .iter()
+                        .map(|(snapshot, path, file_count, size_bytes)| {
+                            serde_json::json!({
+                                "snapshot": snapshot,
+                                "path": path,
+                                "file-count": file_count,
+                                "size-bytes": size_bytes,
+                            })
+                        })
+                        .collect::<Vec<Value>>(),
+                    "repository": repository,
+                }),
+            );
+        }
+    }
+
+    if output_format != "text" {
+        format_and_print_result(&Value::Object(json), &output_format);
+    }
+
+    Ok(())
+}
+
 fn main() {
     let rpcenv = CliEnvironment::new();
 
     let cmd_def = CliCommandMap::new()
         .insert("setup", CliCommand::new(&API_METHOD_SETUP))
         .insert("status", CliCommand::new(&API_METHOD_STATUS))
+        .insert(
+            "snapshot-list",
+            CliCommand::new(&API_METHOD_SNAPSHOT_LIST).arg_param(&["mountpoint"]),
+        )
         .insert(
             "offline-key",
             CliCommand::new(&API_METHOD_SETUP_OFFLINE_KEY),
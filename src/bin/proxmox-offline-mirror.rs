@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::io::IsTerminal;
 use std::matches;
@@ -13,13 +14,16 @@ use proxmox_schema::api;
 use proxmox_section_config::SectionConfigData;
 use proxmox_subscription::ProductType;
 
+use proxmox_offline_mirror::helpers::keys::detect_repo_key_for_line;
 use proxmox_offline_mirror::helpers::tty::{
-    read_bool_from_tty, read_selection_from_tty, read_string_from_tty,
+    read_bool_from_tty, read_multiline_from_tty, read_password_with_confirmation,
+    read_selection_from_tty, read_string_from_tty, read_string_from_tty_with_validation,
 };
 use proxmox_offline_mirror::{
     config::{MediaConfig, MirrorConfig, SkipConfig, save_config},
-    mirror,
+    encode_deb822_repository, mirror,
     types::{MEDIA_ID_SCHEMA, MIRROR_ID_SCHEMA},
+    validate_repository,
 };
 
 mod proxmox_offline_mirror_cmds;
@@ -52,16 +56,6 @@ enum Release {
     Bullseye = 11,
 }
 
-impl Release {
-    fn proxmox_key_path(&self) -> String {
-        if self >= &Release::Trixie {
-            format!("/usr/share/keyrings/proxmox-release-{self}.gpg")
-        } else {
-            "/etc/apt/trusted.gpg.d/proxmox-release-bullseye.gpg".to_string()
-        }
-    }
-}
-
 impl Display for Release {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -121,6 +115,13 @@ impl ProxmoxVariant {
     }
 }
 
+// Helper to look up the keyring path of a repository line (without the leading 'deb').
+fn detected_key_path(url: &str) -> Result<String, Error> {
+    detect_repo_key_for_line(&format!("deb {url}"))
+        .map(|path| path.to_string_lossy().into_owned())
+        .ok_or_else(|| format_err!("Failed to detect key path for repository '{url}'"))
+}
+
 fn derive_debian_repo(
     release: &Release,
     variant: &DebianVariant,
@@ -153,9 +154,24 @@ fn derive_debian_repo(
                 .collect::<Vec<String>>(),
         ),
     };
+    let skip_priorities = match read_string_from_tty(
+        "\tEnter list of package priorities to be skipped, e.g. 'optional,extra' ('-' for None)",
+        Some("-"),
+    )?
+    .as_str()
+    {
+        "-" => None,
+        list => Some(
+            list.split(',')
+                .map(|v| v.trim().to_owned())
+                .collect::<Vec<String>>(),
+        ),
+    };
     let filters = SkipConfig {
         skip_packages,
         skip_sections,
+        skip_priorities,
+        skip_architectures: None,
     };
     let url = match variant {
         DebianVariant::Main => {
@@ -177,37 +193,21 @@ fn derive_debian_repo(
 
     let url = format!("{url} {components}");
 
-    let key = match (release, variant) {
-        (Release::Trixie, DebianVariant::Security) => {
-            "/usr/share/keyrings/debian-archive-trixie-security-automatic.pgp"
-        }
-        (Release::Trixie, DebianVariant::Updates) | (Release::Trixie, DebianVariant::Backports) => {
-            "/usr/share/keyrings/debian-archive-trixie-automatic.pgp"
-        }
-        (Release::Trixie, _) => "/usr/share/keyrings/debian-archive-trixie-stable.pgp",
-        (Release::Bookworm, DebianVariant::Security) => {
-            "/usr/share/keyrings/debian-archive-bookworm-security-automatic.pgp"
-        }
-        (Release::Bookworm, DebianVariant::Updates)
-        | (Release::Bookworm, DebianVariant::Backports) => {
-            "/usr/share/keyrings/debian-archive-bookworm-automatic.pgp"
-        }
-        (Release::Bookworm, _) => "/usr/share/keyrings/debian-archive-bookworm-stable.pgp",
-        (Release::Bullseye, DebianVariant::Security) => {
-            "/usr/share/keyrings/debian-archive-bullseye-security-automatic.pgp"
-        }
-        (Release::Bullseye, _) => "/usr/share/keyrings/debian-archive-bullseye-automatic.pgp",
-    };
+    let key = detected_key_path(&url)?;
 
     let suggested_id = format!("debian_{release}_{variant}");
 
-    Ok((url, key.to_string(), suggested_id, filters))
+    Ok((url, key, suggested_id, filters))
 }
 
 fn action_add_mirror(config: &SectionConfigData) -> Result<Vec<MirrorConfig>, Error> {
     let mut use_subscription = None;
     let mut extra_repos = Vec::new();
 
+    let mut extra_key_paths = None;
+    let mut http_auth = None;
+    let mut template_vars = None;
+
     let guided = read_bool_from_tty("Guided Setup", Some(true))?;
     let (repository, key_path, architectures, suggested_id, skip) = if guided {
         let distros = &[
@@ -313,8 +313,6 @@ fn action_add_mirror(config: &SectionConfigData) -> Result<Vec<MirrorConfig>, Er
                         )
                     };
 
-                let key = release.proxmox_key_path();
-
                 let ceph_release = match ceph_release {
                     CephRelease::Octopus => "octopus",
                     CephRelease::Pacific => "pacific",
@@ -323,7 +321,16 @@ fn action_add_mirror(config: &SectionConfigData) -> Result<Vec<MirrorConfig>, Er
                     CephRelease::Squid => "squid",
                 };
 
-                let url = format!("{base_url}-{ceph_release} {release} {components}");
+                let key = detected_key_path(&format!(
+                    "{base_url}-{ceph_release} {release} {components}"
+                ))?;
+
+                template_vars = Some(HashMap::from([(
+                    "ceph_release".to_string(),
+                    ceph_release.to_string(),
+                )]));
+
+                let url = format!("{base_url}-${{ceph_release}} {release} {components}");
                 let suggested_id = format!("ceph_{ceph_release}_{release}");
 
                 (url, key, suggested_id, SkipConfig::default())
@@ -353,7 +360,7 @@ fn action_add_mirror(config: &SectionConfigData) -> Result<Vec<MirrorConfig>, Er
                     _ => None,
                 };
 
-                let key = release.proxmox_key_path();
+                let key = detected_key_path(&url)?;
 
                 let suggested_id = format!("{product}_{release}_{variant}");
 
@@ -393,8 +400,53 @@ fn action_add_mirror(config: &SectionConfigData) -> Result<Vec<MirrorConfig>, Er
             skip,
         )
     } else {
-        let repo = read_string_from_tty("Enter repository line in sources.list format", None)?;
-        let key_path = read_string_from_tty("Enter (absolute) path to repository key file", None)?;
+        let formats = &[
+            (false, "Repository line in sources.list format"),
+            (true, "Custom deb822 repository"),
+        ];
+        let deb822 = *read_selection_from_tty("Select repository format", formats, Some(0))?;
+
+        let repo = loop {
+            let repo = if deb822 {
+                let stanza = read_multiline_from_tty(
+                    "Enter deb822 repository stanza, starting with 'Types:'",
+                    "",
+                )?;
+                encode_deb822_repository(&stanza)
+            } else {
+                read_string_from_tty("Enter repository line in sources.list format", None)?
+            };
+            match validate_repository(&repo) {
+                Ok(()) => break repo,
+                Err(err) => eprintln!("Invalid repository definition - {err}"),
+            }
+        };
+        let detected_key = detect_repo_key_for_line(&repo);
+        let key_path = read_string_from_tty_with_validation(
+            "Enter (absolute) path to repository key file",
+            detected_key.as_deref().and_then(Path::to_str),
+            |path| {
+                if Path::new(path).exists() {
+                    Ok(())
+                } else {
+                    Err(format!("Keyfile '{path}' doesn't exist"))
+                }
+            },
+        )?;
+        extra_key_paths = match read_string_from_tty(
+            "Enter list of (absolute) paths to additional key files, tried in order ('-' for None)",
+            Some("-"),
+        )?
+        .as_str()
+        {
+            "-" => None,
+            list => Some(
+                list.split(',')
+                    .map(|v| v.trim().to_owned())
+                    .filter(|v| !v.is_empty())
+                    .collect::<Vec<String>>(),
+            ),
+        };
         let architectures =
             read_string_from_tty("Enter list of architectures to mirror", Some("amd64,all"))?;
         let architectures: Vec<String> = architectures
@@ -419,13 +471,26 @@ fn action_add_mirror(config: &SectionConfigData) -> Result<Vec<MirrorConfig>, Er
             None,
         )?);
 
+        if use_subscription.is_none()
+            && read_bool_from_tty(
+                "Does this repository require HTTP basic authentication",
+                Some(false),
+            )?
+        {
+            let user = read_string_from_tty("Enter username", None)?;
+            let password = read_password_with_confirmation("Enter password", "Confirm password")?;
+            http_auth = Some(proxmox_base64::encode(format!("{user}:{password}")));
+        }
+
         (repo, key_path, architectures, None, SkipConfig::default())
     };
 
-    if !Path::new(&key_path).exists() {
-        eprintln!(
-            "Keyfile '{key_path}' doesn't exist - make sure to install relevant keyring packages or update config to provide correct path!"
-        );
+    for key_path in std::iter::once(&key_path).chain(extra_key_paths.iter().flatten()) {
+        if !Path::new(key_path).exists() {
+            eprintln!(
+                "Keyfile '{key_path}' doesn't exist - make sure to install relevant keyring packages or update config to provide correct path!"
+            );
+        }
     }
 
     let id = loop {
@@ -477,11 +542,22 @@ fn action_add_mirror(config: &SectionConfigData) -> Result<Vec<MirrorConfig>, Er
                 repository,
                 architectures: architectures.clone(),
                 key_path,
+                extra_key_paths: None,
+                sig_type: None,
+                extra_components: None,
+                exclude_components: None,
+                template_vars: None,
+                hash_preference: None,
+                gc_io_limit_ops_per_sec: None,
+                snapshot_name_template: None,
                 verify,
                 sync,
                 base_dir: base_dir.clone(),
                 use_subscription: None,
+                http_auth: None,
+                pool_dir: None,
                 ignore_errors: false,
+                repair: false,
                 skip,
                 weak_crypto: None,
             });
@@ -493,11 +569,22 @@ fn action_add_mirror(config: &SectionConfigData) -> Result<Vec<MirrorConfig>, Er
         repository,
         architectures,
         key_path,
+        extra_key_paths,
+        sig_type: None,
+        extra_components: None,
+        exclude_components: None,
+        template_vars,
+        hash_preference: None,
+        gc_io_limit_ops_per_sec: None,
+        snapshot_name_template: None,
         verify,
         sync,
         base_dir,
         use_subscription,
+        http_auth,
+        pool_dir: None,
         ignore_errors: false,
+        repair: false,
         skip,
         weak_crypto: None,
     };
@@ -669,6 +756,9 @@ fn action_add_medium(config: &SectionConfigData) -> Result<MediaConfig, Error> {
         mirrors: selected_mirrors,
         verify,
         sync,
+        sync_strategy: None,
+        max_mirrors: None,
+        max_snapshots_per_mirror: None,
     })
 }
 
@@ -717,11 +807,12 @@ fn action_add_key(config: &SectionConfigData) -> Result<SubscriptionKey, Error>
         info: None,
     };
 
-    if data.product() != *product {
+    let key_product = data.product()?;
+    if key_product != *product {
         bail!(
             "Selected product and product in subscription key don't match: {} != {}",
             product,
-            data.product()
+            key_product
         );
     }
 
@@ -735,7 +826,9 @@ fn action_add_key(config: &SectionConfigData) -> Result<SubscriptionKey, Error>
                 mirror_key,
                 vec![data.clone()],
                 public_key()?,
-            )?;
+                None,
+            )?
+            .into_result()?;
 
             refreshed
                 .pop()
@@ -830,7 +923,9 @@ async fn setup(config: Option<String>, _param: Value) -> Result<(), Error> {
             Action::AddMirror => {
                 for mirror_config in action_add_mirror(&config)? {
                     let id = mirror_config.id.clone();
-                    mirror::init(&mirror_config)?;
+                    let mut expanded = mirror_config.clone();
+                    proxmox_offline_mirror::config::expand_env_vars(&mut expanded)?;
+                    mirror::init(&expanded)?;
                     config.set_data(&id, "mirror", mirror_config)?;
                     save_config(&config_file, &config)?;
                     println!("Config entry '{id}' added");
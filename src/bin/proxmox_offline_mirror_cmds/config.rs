@@ -1,18 +1,24 @@
-use std::{env, fs::remove_dir_all, path::Path};
+use std::{collections::HashMap, env, fs::remove_dir_all, path::Path};
 
 use anyhow::{Error, bail};
 use serde_json::Value;
 
 use proxmox_router::cli::{
     CliCommand, CliCommandMap, ColumnConfig, CommandLineInterface, OUTPUT_FORMAT,
-    default_table_format_options, format_and_print_result_full, get_output_format,
+    default_table_format_options, format_and_print_result, format_and_print_result_full,
+    get_output_format,
 };
 use proxmox_schema::{ApiType, ArraySchema, ReturnType, api, param_bail};
 
 use proxmox_offline_mirror::{
-    config::{MediaConfig, MediaConfigUpdater, MirrorConfig, MirrorConfigUpdater},
+    config::{
+        MediaConfig, MediaConfigUpdater, MirrorConfig, MirrorConfigUpdater, parse_template_vars,
+    },
+    encode_deb822_repository,
+    helpers::{KeyStatus, keys::detect_repo_key_for_line, validate_all_keys},
     mirror,
     types::{MEDIA_ID_SCHEMA, MIRROR_ID_SCHEMA},
+    validate_repository,
 };
 
 pub fn get_config_path() -> String {
@@ -115,6 +121,32 @@ async fn show_mirror(config: Option<String>, id: String, param: Value) -> Result
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+        }
+    },
+ )]
+/// Show the repository line of a mirror with all template variables expanded
+async fn render_mirror(config: Option<String>, id: String) -> Result<Value, Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+
+    println!("{}", config.render_repository()?);
+
+    Ok(Value::Null)
+}
+
 #[api(
     protected: true,
     input: {
@@ -138,11 +170,23 @@ async fn show_mirror(config: Option<String>, id: String, param: Value) -> Result
 /// Create new mirror config entry.
 async fn add_mirror(
     config: Option<String>,
-    data: MirrorConfig,
+    mut data: MirrorConfig,
     _param: Value,
 ) -> Result<Value, Error> {
     let config = config.unwrap_or_else(get_config_path);
 
+    data.repository = check_repository(data.repository)?;
+
+    if data.key_path.is_empty() {
+        data.key_path = match detect_repo_key_for_line(&data.render_repository()?) {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None => param_bail!(
+                "key-path",
+                "no key path given and none known for this repository."
+            ),
+        };
+    }
+
     let _lock = proxmox_offline_mirror::config::lock_config(&config)?;
 
     let (mut section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
@@ -151,7 +195,9 @@ async fn add_mirror(
         param_bail!("name", "mirror config entry '{}' already exists.", data.id);
     }
 
-    mirror::init(&data)?;
+    let mut expanded = data.clone();
+    proxmox_offline_mirror::config::expand_env_vars(&mut expanded)?;
+    mirror::init(&expanded)?;
 
     section_config.set_data(&data.id, "mirror", &data)?;
     proxmox_offline_mirror::config::save_config(&config, &section_config)?;
@@ -195,8 +241,9 @@ async fn remove_mirror(
     // TODO (optionally?) remove media entries?
     let (mut section_config, _digest) = proxmox_offline_mirror::config::config(&config_file)?;
     match section_config.lookup::<MirrorConfig>("mirror", &id) {
-        Ok(config) => {
+        Ok(mut config) => {
             if remove_data {
+                proxmox_offline_mirror::config::expand_env_vars(&mut config)?;
                 mirror::destroy(&config)?;
             }
 
@@ -212,6 +259,21 @@ async fn remove_mirror(
     Ok(Value::Null)
 }
 
+// Helper to validate a repository definition, encoding multi-line deb822 stanzas for storage.
+fn check_repository(repository: String) -> Result<String, Error> {
+    let repository = if repository.contains('\n') {
+        encode_deb822_repository(&repository)
+    } else {
+        repository
+    };
+
+    if let Err(err) = validate_repository(&repository) {
+        param_bail!("repository", "invalid repository definition - {err}");
+    }
+
+    Ok(repository)
+}
+
 #[api(
     input: {
         properties: {
@@ -227,6 +289,60 @@ async fn remove_mirror(
                 type: MirrorConfigUpdater,
                 flatten: true,
             },
+            "add-key-path": {
+                type: Array,
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Path to additional public key file.",
+                },
+                description: "Append key file(s) to the list of extra key paths.",
+            },
+            "remove-key-path": {
+                type: Array,
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Path to additional public key file.",
+                },
+                description: "Remove key file(s) from the list of extra key paths.",
+            },
+            "add-architecture": {
+                type: Array,
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Architecture.",
+                },
+                description: "Append architecture(s) to the list of mirrored architectures.",
+            },
+            "remove-architecture": {
+                type: Array,
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Architecture.",
+                },
+                description: "Remove architecture(s) from the list of mirrored architectures.",
+            },
+            "set-template-var": {
+                type: Array,
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Template variable in 'NAME=VALUE' format.",
+                },
+                description: "Add or replace template variable(s) referenced in the repository.",
+            },
+            "remove-template-var": {
+                type: Array,
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Template variable name.",
+                },
+                description: "Remove template variable(s).",
+            },
         },
     },
 )]
@@ -235,6 +351,12 @@ pub fn update_mirror(
     update: MirrorConfigUpdater,
     config: Option<String>,
     id: String,
+    add_key_path: Option<Vec<String>>,
+    remove_key_path: Option<Vec<String>>,
+    add_architecture: Option<Vec<String>>,
+    remove_architecture: Option<Vec<String>>,
+    set_template_var: Option<Vec<String>>,
+    remove_template_var: Option<Vec<String>>,
 ) -> Result<(), Error> {
     let config_file = config.unwrap_or_else(get_config_path);
 
@@ -247,8 +369,62 @@ pub fn update_mirror(
     if let Some(key_path) = update.key_path {
         data.key_path = key_path
     }
+    if let Some(sig_type) = update.sig_type {
+        data.sig_type = Some(sig_type);
+    }
+    if let Some(extra_key_paths) = update.extra_key_paths {
+        data.extra_key_paths = Some(extra_key_paths);
+    }
+    if let Some(add_key_path) = add_key_path {
+        let extra_key_paths = data.extra_key_paths.get_or_insert_with(Vec::new);
+        for path in add_key_path {
+            if !extra_key_paths.contains(&path) {
+                extra_key_paths.push(path);
+            }
+        }
+    }
+    if let Some(remove_key_path) = remove_key_path {
+        if let Some(extra_key_paths) = data.extra_key_paths.as_mut() {
+            extra_key_paths.retain(|path| !remove_key_path.contains(path));
+            if extra_key_paths.is_empty() {
+                data.extra_key_paths = None;
+            }
+        }
+    }
     if let Some(repository) = update.repository {
-        data.repository = repository
+        data.repository = check_repository(repository)?
+    }
+    if let Some(set_template_var) = set_template_var {
+        let template_vars = data.template_vars.get_or_insert_with(HashMap::new);
+        for var in set_template_var {
+            match parse_template_vars(&var) {
+                Ok(vars) => template_vars.extend(vars),
+                Err(err) => param_bail!("set-template-var", "{err}"),
+            }
+        }
+    }
+    if let Some(remove_template_var) = remove_template_var {
+        if let Some(template_vars) = data.template_vars.as_mut() {
+            template_vars.retain(|name, _| !remove_template_var.contains(name));
+            if template_vars.is_empty() {
+                data.template_vars = None;
+            }
+        }
+    }
+    if let Some(extra_components) = update.extra_components {
+        data.extra_components = Some(extra_components);
+    }
+    if let Some(exclude_components) = update.exclude_components {
+        data.exclude_components = Some(exclude_components);
+    }
+    if let Some(hash_preference) = update.hash_preference {
+        data.hash_preference = Some(hash_preference);
+    }
+    if let Some(gc_io_limit_ops_per_sec) = update.gc_io_limit_ops_per_sec {
+        data.gc_io_limit_ops_per_sec = Some(gc_io_limit_ops_per_sec);
+    }
+    if let Some(snapshot_name_template) = update.snapshot_name_template {
+        data.snapshot_name_template = Some(snapshot_name_template);
     }
     if let Some(base_dir) = update.base_dir {
         data.base_dir = base_dir
@@ -256,6 +432,23 @@ pub fn update_mirror(
     if let Some(architectures) = update.architectures {
         data.architectures = architectures
     }
+    if let Some(add_architecture) = add_architecture {
+        for arch in add_architecture {
+            if !data.architectures.contains(&arch) {
+                data.architectures.push(arch);
+            }
+        }
+    }
+    if let Some(remove_architecture) = remove_architecture {
+        data.architectures
+            .retain(|arch| !remove_architecture.contains(arch));
+        if data.architectures.is_empty() {
+            param_bail!(
+                "remove-architecture",
+                "at least one architecture must remain configured"
+            );
+        }
+    }
     if let Some(sync) = update.sync {
         data.sync = sync
     }
@@ -265,6 +458,15 @@ pub fn update_mirror(
     if let Some(ignore_errors) = update.ignore_errors {
         data.ignore_errors = ignore_errors
     }
+    if let Some(repair) = update.repair {
+        data.repair = repair
+    }
+    if let Some(http_auth) = update.http_auth {
+        data.http_auth = Some(http_auth)
+    }
+    if let Some(pool_dir) = update.pool_dir {
+        data.pool_dir = Some(pool_dir)
+    }
 
     if let Some(skip_packages) = update.skip.skip_packages {
         data.skip.skip_packages = Some(skip_packages);
@@ -274,6 +476,14 @@ pub fn update_mirror(
         data.skip.skip_sections = Some(skip_sections);
     }
 
+    if let Some(skip_priorities) = update.skip.skip_priorities {
+        data.skip.skip_priorities = Some(skip_priorities);
+    }
+
+    if let Some(skip_architectures) = update.skip.skip_architectures {
+        data.skip.skip_architectures = Some(skip_architectures);
+    }
+
     if let Some(weak_crypto) = update.weak_crypto {
         data.weak_crypto = Some(weak_crypto);
     }
@@ -500,9 +710,18 @@ pub fn update_medium(
     if let Some(sync) = update.sync {
         data.sync = sync
     }
+    if let Some(sync_strategy) = update.sync_strategy {
+        data.sync_strategy = Some(sync_strategy)
+    }
     if let Some(verify) = update.verify {
         data.verify = verify
     }
+    if let Some(max_mirrors) = update.max_mirrors {
+        data.max_mirrors = Some(max_mirrors)
+    }
+    if let Some(max_snapshots_per_mirror) = update.max_snapshots_per_mirror {
+        data.max_snapshots_per_mirror = Some(max_snapshots_per_mirror)
+    }
 
     config.set_data(&id, "medium", &data)?;
     proxmox_offline_mirror::config::save_config(&config_file, &config)?;
@@ -510,13 +729,121 @@ pub fn update_medium(
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+        },
+    },
+)]
+/// Mark a config file written before the format was versioned as version 1.
+pub fn migrate_v0_to_v1(config: Option<String>) -> Result<(), Error> {
+    let config_file = config.unwrap_or_else(get_config_path);
+
+    let _lock = proxmox_offline_mirror::config::lock_config(&config_file)?;
+
+    let (config, _digest, version) =
+        proxmox_offline_mirror::config::config_with_version(&config_file)?;
+    if version != 0 {
+        bail!("Config file is already at version {version}.");
+    }
+
+    // saving always writes the current version
+    proxmox_offline_mirror::config::save_config(&config_file, &config)?;
+    println!("Migrated config file to version 1.");
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Validate the keys configured for all mirrors.
+async fn keys_status(config: Option<String>, param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let mut mirrors: Vec<MirrorConfig> = config.convert_to_typed_array("mirror")?;
+    mirrors.retain_mut(
+        |mirror| match proxmox_offline_mirror::config::expand_env_vars(mirror) {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("Skipping mirror '{}' - {err}", mirror.id);
+                false
+            }
+        },
+    );
+    let results = validate_all_keys(&mirrors);
+
+    if output_format == "text" {
+        let id_width = results
+            .iter()
+            .map(|result| result.mirror_id.len())
+            .max()
+            .unwrap_or_default();
+        for result in &results {
+            let status = match &result.status {
+                KeyStatus::Valid {
+                    fingerprint,
+                    expires_at: Some(expires_at),
+                } => format!(
+                    "valid, {fingerprint}, expires {}",
+                    proxmox_time::epoch_to_rfc3339_utc(*expires_at)?
+                ),
+                KeyStatus::Valid {
+                    fingerprint,
+                    expires_at: None,
+                } => format!("valid, {fingerprint}, never expires"),
+                KeyStatus::Expired {
+                    fingerprint,
+                    expired_at,
+                } => format!(
+                    "EXPIRED, {fingerprint}, expired {}",
+                    proxmox_time::epoch_to_rfc3339_utc(*expired_at)?
+                ),
+                KeyStatus::Invalid(err) => format!("INVALID - {err}"),
+                KeyStatus::FileNotFound => "NOT FOUND".to_string(),
+            };
+            println!(
+                "{:id_width$}  {}: {status}",
+                result.mirror_id, result.key_path
+            );
+        }
+    } else {
+        format_and_print_result(&serde_json::json!(results), &output_format);
+    }
+
+    Ok(())
+}
+
 pub fn config_commands() -> CommandLineInterface {
     let mirror_cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_MIRROR))
         .insert("add", CliCommand::new(&API_METHOD_ADD_MIRROR))
         .insert("show", CliCommand::new(&API_METHOD_SHOW_MIRROR))
         .insert("remove", CliCommand::new(&API_METHOD_REMOVE_MIRROR))
-        .insert("update", CliCommand::new(&API_METHOD_UPDATE_MIRROR));
+        .insert("update", CliCommand::new(&API_METHOD_UPDATE_MIRROR))
+        .insert(
+            "render",
+            CliCommand::new(&API_METHOD_RENDER_MIRROR).arg_param(&["id"]),
+        );
 
     let media_cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_MEDIA))
@@ -527,7 +854,12 @@ pub fn config_commands() -> CommandLineInterface {
 
     let cmd_def = CliCommandMap::new()
         .insert("media", media_cmd_def)
-        .insert("mirror", mirror_cmd_def);
+        .insert("mirror", mirror_cmd_def)
+        .insert("keys-status", CliCommand::new(&API_METHOD_KEYS_STATUS))
+        .insert(
+            "migrate-v0-to-v1",
+            CliCommand::new(&API_METHOD_MIGRATE_V0_TO_V1),
+        );
 
     cmd_def.into()
 }
@@ -1,20 +1,31 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+};
 
-use anyhow::Error;
+use anyhow::{Error, bail, format_err};
 use serde_json::Value;
 
-use proxmox_router::cli::{CliCommand, CliCommandMap, CommandLineInterface, OUTPUT_FORMAT};
+use proxmox_router::cli::{
+    CliCommand, CliCommandMap, CommandLineInterface, OUTPUT_FORMAT, format_and_print_result,
+    get_output_format,
+};
 use proxmox_schema::api;
 use proxmox_section_config::SectionConfigData;
 use proxmox_subscription::{ProductType, SubscriptionInfo};
-use proxmox_time::epoch_to_rfc3339_utc;
+use proxmox_time::{epoch_i64, epoch_to_rfc3339_utc};
 
 use proxmox_offline_mirror::{
     config::{MediaConfig, MirrorConfig, SubscriptionKey},
-    generate_repo_file_line,
-    medium::{self},
+    generate_repo_file_entry,
+    helpers::{
+        metrics::{MirrorMetrics, write_metrics_file},
+        pkg_path::{PackageDiff, PackagePath, package_diff},
+    },
+    medium::{self, MirrorSyncReport, SyncEvent},
     mirror,
-    types::{MEDIA_ID_SCHEMA, Snapshot},
+    types::{Diff, DiffSummary, MEDIA_ID_SCHEMA, MIRROR_ID_SCHEMA, Snapshot, SourcesFormat},
 };
 
 use super::get_config_path;
@@ -38,17 +49,23 @@ use super::get_config_path;
     },
  )]
 /// Garbage collect all mirrors on a medium.
-async fn garbage_collect(
-    config: Option<String>,
-    id: String,
-    _param: Value,
-) -> Result<Value, Error> {
+async fn garbage_collect(config: Option<String>, id: String, param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
     let config = config.unwrap_or_else(get_config_path);
 
     let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
     let config: MediaConfig = section_config.lookup("medium", &id)?;
 
-    medium::gc(&config)?;
+    let report = medium::gc(&config)?;
+
+    if output_format == "text" {
+        println!(
+            "Removed {} orphaned links, {} files remaining in pools",
+            report.orphaned_links_removed, report.pool_files_remaining
+        );
+    } else {
+        format_and_print_result(&serde_json::json!(report), &output_format);
+    }
 
     Ok(Value::Null)
 }
@@ -64,6 +81,10 @@ async fn garbage_collect(
             id: {
                 schema: MEDIA_ID_SCHEMA,
             },
+            format: {
+                type: SourcesFormat,
+                optional: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -72,8 +93,14 @@ async fn garbage_collect(
     },
  )]
 /// Print status of a medium
-async fn status(config: Option<String>, id: String, _param: Value) -> Result<Value, Error> {
+async fn status(
+    config: Option<String>,
+    id: String,
+    format: Option<SourcesFormat>,
+    _param: Value,
+) -> Result<Value, Error> {
     let config = config.unwrap_or_else(get_config_path);
+    let format = format.unwrap_or_default();
 
     let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
     let medium_config: MediaConfig = section_config.lookup("medium", &id)?;
@@ -85,10 +112,48 @@ async fn status(config: Option<String>, id: String, _param: Value) -> Result<Val
         epoch_to_rfc3339_utc(state.last_sync)?
     );
 
+    match medium::available_space(&medium_config) {
+        Ok(space) => println!("Available space: {space}b"),
+        Err(err) => eprintln!("Failed to determine available space - {err}"),
+    }
+    match medium::used_space(&medium_config) {
+        Ok(space) => println!("Used space: {space}b"),
+        Err(err) => eprintln!("Failed to determine used space - {err}"),
+    }
+    let pool_file_counts = match medium::approximate_usage(&medium_config) {
+        Ok((used, file_counts)) => {
+            println!("Used space on file system: ~{used}b");
+            file_counts
+        }
+        Err(err) => {
+            eprintln!("Failed to determine approximate pool usage - {err}");
+            HashMap::new()
+        }
+    };
+    let dedup_stats = match medium::dedup_stats(&medium_config) {
+        Ok(stats) => stats,
+        Err(err) => {
+            eprintln!("Failed to determine deduplication stats - {err}");
+            HashMap::new()
+        }
+    };
+
+    if let Some(luks_uuid) = &state.luks_uuid {
+        println!("LUKS UUID: {luks_uuid}");
+    }
+
     println!("Already synced mirrors: {:?}", mirror_state.synced);
 
-    if !mirror_state.source_only.is_empty() {
-        println!("Missing mirrors: {:?}", mirror_state.source_only);
+    if !mirror_state.skipped.is_empty() {
+        println!("Skipped during last sync: {:?}", mirror_state.skipped);
+    }
+
+    let missing: HashSet<&String> = mirror_state
+        .source_only
+        .difference(&mirror_state.skipped)
+        .collect();
+    if !missing.is_empty() {
+        println!("Missing mirrors: {missing:?}");
     }
 
     if !mirror_state.target_only.is_empty() {
@@ -97,7 +162,7 @@ async fn status(config: Option<String>, id: String, _param: Value) -> Result<Val
 
     for (ref id, ref mirror) in state.mirrors {
         println!("\nMirror '{}'", id);
-        let mirror_config: MirrorConfig = section_config.lookup("mirror", id)?;
+        let mirror_config = proxmox_offline_mirror::config::lookup_mirror(&section_config, id)?;
         let print_snapshots = |snapshots: &[Snapshot]| {
             match (snapshots.first(), snapshots.last()) {
                 (Some(first), Some(last)) if first == last => {
@@ -112,7 +177,7 @@ async fn status(config: Option<String>, id: String, _param: Value) -> Result<Val
             };
         };
 
-        let mut source_snapshots = mirror::list_snapshots(&mirror_config)?;
+        let mut source_snapshots = mirror::list_complete_snapshots(&mirror_config)?;
         source_snapshots.sort();
         println!("Source:");
         print_snapshots(&source_snapshots);
@@ -126,15 +191,121 @@ async fn status(config: Option<String>, id: String, _param: Value) -> Result<Val
         if let Some(last) = snapshots.last() {
             println!(
                 "\trepository config: {}",
-                generate_repo_file_line(path, id, mirror, last)?
+                generate_repo_file_entry(path, id, mirror, last, format)?
             );
         }
+        if let Some(count) = pool_file_counts.get(id) {
+            println!("\tpool: ~{count} files");
+        }
+        if let Some(stats) = dedup_stats.get(id) {
+            println!(
+                "\tdeduplication: {}b in {} links, {}b in {} unique files (ratio {:.2})",
+                stats.logical_bytes,
+                stats.link_count,
+                stats.physical_bytes,
+                stats.unique_file_count,
+                stats.ratio
+            );
+        }
+    }
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MEDIA_ID_SCHEMA,
+            },
+        }
+    },
+ )]
+/// Print the recorded and the currently detected LUKS UUID of a medium
+async fn format_info(config: Option<String>, id: String, _param: Value) -> Result<Value, Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let medium_config: MediaConfig = section_config.lookup("medium", &id)?;
+
+    let (recorded, current) = medium::format_info(&medium_config)?;
+
+    println!(
+        "Recorded LUKS UUID: {}",
+        recorded.as_deref().unwrap_or("none")
+    );
+    println!(
+        "Detected LUKS UUID: {}",
+        current.as_deref().unwrap_or("none (not a LUKS volume)")
+    );
+
+    match (recorded, current) {
+        (Some(recorded), Some(current)) if recorded == current => {
+            println!("Medium matches recorded LUKS volume.")
+        }
+        (Some(_), _) => eprintln!("Medium does NOT match recorded LUKS volume!"),
+        (None, Some(_)) => println!("LUKS UUID will be recorded on next sync."),
+        (None, None) => {}
     }
 
     Ok(Value::Null)
 }
 
-fn get_subscription_keys(
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MEDIA_ID_SCHEMA,
+            },
+            "mirror-id": {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            snapshot: {
+                type: Snapshot,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Roll back a mirror on a medium to the given snapshot, which will then be used instead of the
+/// newest one when generating repository lines. Without snapshot, the newest one is used again.
+async fn set_snapshot(
+    config: Option<String>,
+    id: String,
+    mirror_id: String,
+    snapshot: Option<Snapshot>,
+    _param: Value,
+) -> Result<Value, Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let medium_config: MediaConfig = section_config.lookup("medium", &id)?;
+
+    match snapshot {
+        Some(snapshot) => {
+            medium::rollback_to_snapshot(&medium_config, &mirror_id, &snapshot)?;
+            println!("Mirror '{mirror_id}' on medium '{id}' now uses snapshot {snapshot}.");
+        }
+        None => {
+            medium::clear_rollback(&medium_config, &mirror_id)?;
+            println!("Mirror '{mirror_id}' on medium '{id}' now uses its newest snapshot.");
+        }
+    }
+
+    Ok(Value::Null)
+}
+
+pub(crate) fn get_subscription_keys(
     section_config: &SectionConfigData,
 ) -> Result<Vec<SubscriptionInfo>, Error> {
     let config_subscriptions: Vec<SubscriptionKey> =
@@ -142,7 +313,7 @@ fn get_subscription_keys(
 
     let mut subscription_infos = Vec::new();
     for subscription in config_subscriptions {
-        if subscription.product() == ProductType::Pom {
+        if subscription.product()? == ProductType::Pom {
             continue;
         }
 
@@ -152,6 +323,13 @@ fn get_subscription_keys(
                     "Including key '{}' for server '{}' with status '{}'",
                     subscription.key, subscription.server_id, info.status
                 );
+                // offline systems can only be as current as the transferred state
+                if subscription.needs_refresh(24 * 7) {
+                    eprintln!(
+                        "Subscription info of '{}' is older than a week - run `refresh`.",
+                        subscription.key
+                    );
+                }
                 subscription_infos.push(info)
             }
             Ok(None) => eprintln!(
@@ -188,6 +366,25 @@ fn get_subscription_keys(
                 description: "Only sync offline subscription keys, skip repository contents",
                 optional: true,
             },
+            "dry-run": {
+                type: bool,
+                default: false,
+                description: "Only report the amount of data that would be transferred, without writing anything to the medium.",
+                optional: true,
+            },
+            "metrics-file": {
+                type: String,
+                optional: true,
+                description: "Write Prometheus metrics to this file after completion.",
+            },
+            "exclude-mirror": {
+                type: Array,
+                optional: true,
+                items: {
+                    schema: MIRROR_ID_SCHEMA,
+                },
+                description: "Skip mirror(s) for this sync, leaving their data on the medium untouched.",
+            },
         }
     },
  )]
@@ -196,6 +393,9 @@ async fn sync(
     config: Option<String>,
     id: String,
     keys_only: bool,
+    dry_run: bool,
+    metrics_file: Option<String>,
+    exclude_mirror: Option<Vec<String>>,
     _param: Value,
 ) -> Result<Value, Error> {
     let config = config.unwrap_or_else(get_config_path);
@@ -203,23 +403,222 @@ async fn sync(
     let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
     let config: MediaConfig = section_config.lookup("medium", &id)?;
 
-    let subscription_infos = get_subscription_keys(&section_config)?;
-
-    if keys_only {
-        medium::sync_keys(&config, subscription_infos)?;
-    } else {
+    if dry_run {
         let mut mirrors = Vec::with_capacity(config.mirrors.len());
         for mirror in &config.mirrors {
-            let mirror: MirrorConfig = section_config.lookup("mirror", mirror)?;
+            let mirror = proxmox_offline_mirror::config::lookup_mirror(&section_config, mirror)?;
             mirrors.push(mirror);
         }
 
-        medium::sync(&config, mirrors, subscription_infos)?;
+        let report = medium::sync_dry_run(&config, mirrors)?;
+        let mut ids: Vec<&String> = report.mirrors.keys().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let entry = &report.mirrors[id];
+            println!(
+                "Mirror '{id}': {} file(s) to transfer, {}b",
+                entry.files, entry.bytes
+            );
+        }
+        println!(
+            "Total: {} file(s) to transfer, {}b",
+            report.total_files, report.total_bytes
+        );
+
+        return Ok(Value::Null);
+    }
+
+    if keys_only {
+        let subscription_infos = get_subscription_keys(&section_config)?;
+        medium::sync_keys(&config, subscription_infos)?;
+    } else {
+        let reports = sync_medium(
+            &section_config,
+            &config,
+            exclude_mirror.as_deref().unwrap_or_default(),
+            "",
+        )?;
+
+        if let Some(metrics_file) = metrics_file {
+            let last_sync = epoch_i64();
+            let mut ids: Vec<&String> = reports.keys().collect();
+            ids.sort_unstable();
+            let metrics: Vec<MirrorMetrics> = ids
+                .into_iter()
+                .map(|id| {
+                    let report = &reports[id];
+                    MirrorMetrics {
+                        mirror: id,
+                        progress: &report.progress,
+                        pool: &report.pool,
+                        snapshot_count: report.snapshot_count,
+                        last_sync,
+                    }
+                })
+                .collect();
+            write_metrics_file(Path::new(&metrics_file), &metrics)?;
+        }
     }
 
     Ok(Value::Null)
 }
 
+// Helper to sync mirrors and subscription keys to a medium, prefixing progress messages with
+// `prefix`.
+fn sync_medium(
+    section_config: &SectionConfigData,
+    medium: &MediaConfig,
+    exclude_mirrors: &[String],
+    prefix: &str,
+) -> Result<HashMap<String, MirrorSyncReport>, Error> {
+    let subscription_infos = get_subscription_keys(section_config)?;
+
+    let mut mirrors = Vec::with_capacity(medium.mirrors.len());
+    for mirror in &medium.mirrors {
+        let mirror = proxmox_offline_mirror::config::lookup_mirror(&section_config, mirror)?;
+        mirrors.push(mirror);
+    }
+
+    // skip per-file events, the sync already prints periodic progress updates
+    let prefix = prefix.to_string();
+    let on_progress = Box::new(move |event: SyncEvent| match event {
+        SyncEvent::Starting {
+            mirror_id,
+            estimated_bytes,
+        } => println!(
+            "{prefix}Starting sync of '{mirror_id}', estimated {estimated_bytes}b to transfer"
+        ),
+        SyncEvent::FileTransferred { .. } => {}
+        SyncEvent::MirrorComplete {
+            mirror_id,
+            files_added,
+            bytes_added,
+        } => println!(
+            "{prefix}Finished sync of '{mirror_id}', added {files_added} files ({bytes_added}b)"
+        ),
+        SyncEvent::Complete {
+            total_files,
+            total_bytes,
+        } => println!("{prefix}Sync complete, added {total_files} files ({total_bytes}b) in total"),
+    });
+
+    medium::sync(
+        medium,
+        mirrors,
+        exclude_mirrors,
+        subscription_infos,
+        Some(on_progress),
+    )
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            parallel: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Sync all media at the same time instead of one after another.",
+            },
+            "exclude-medium": {
+                type: Array,
+                optional: true,
+                items: {
+                    schema: MEDIA_ID_SCHEMA,
+                },
+                description: "Skip medium/media for this sync.",
+            },
+        }
+    },
+ )]
+/// Sync all configured media
+async fn sync_all(
+    config: Option<String>,
+    parallel: bool,
+    exclude_medium: Option<Vec<String>>,
+    _param: Value,
+) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let exclude_medium = exclude_medium.unwrap_or_default();
+    let media: Vec<MediaConfig> = section_config
+        .convert_to_typed_array::<MediaConfig>("medium")?
+        .into_iter()
+        .filter(|medium| !exclude_medium.contains(&medium.id))
+        .collect();
+
+    let mut results = Vec::with_capacity(media.len());
+
+    if parallel {
+        let section_config = Arc::new(section_config);
+        let mut tasks = Vec::with_capacity(media.len());
+        for medium in media {
+            let section_config = Arc::clone(&section_config);
+            let id = medium.id.clone();
+            let task = tokio::task::spawn_blocking(move || {
+                sync_medium(&section_config, &medium, &[], &format!("[{}] ", medium.id))
+            });
+            tasks.push((id, task));
+        }
+
+        for (id, task) in tasks {
+            let res = match task.await {
+                Ok(res) => res,
+                Err(err) => Err(format_err!("sync task failed - {err}")),
+            };
+            if let Err(err) = &res {
+                eprintln!("[{id}] Failed to sync medium - {err}");
+            }
+            results.push((id, res));
+        }
+    } else {
+        for medium in media {
+            println!("\nSYNCING MEDIUM '{}'..", medium.id);
+            let res = sync_medium(&section_config, &medium, &[], "");
+            if let Err(err) = &res {
+                eprintln!("Failed to sync medium '{}' - {err}", medium.id);
+            }
+            results.push((medium.id, res));
+        }
+    }
+
+    println!("\nSUMMARY:");
+    let mut fail = false;
+    for (id, res) in &results {
+        match res {
+            Ok(reports) => {
+                let (new_files, new_bytes) =
+                    reports.values().fold((0, 0), |(files, bytes), report| {
+                        (
+                            files + report.progress.new_files,
+                            bytes + report.progress.new_bytes,
+                        )
+                    });
+                println!(
+                    "{id}: OK - {} mirrors, {new_files} new files ({new_bytes}b)",
+                    reports.len()
+                );
+            }
+            Err(err) => {
+                fail = true;
+                eprintln!("{id}: ERR - {err}");
+            }
+        }
+    }
+
+    if fail {
+        bail!("Failed to sync all configured media.");
+    }
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -237,6 +636,24 @@ async fn sync(
                 default: false,
                 description: "Verbose output (print paths in addition to summary)."
             },
+            "package-level": {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Additionally print differing packages (name and version) derived from .deb paths."
+            },
+            summary: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Only print the total sizes of differences over all mirrors, including a transfer estimate."
+            },
+            "env-format": {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Print the summary as shell variable assignments (requires '--summary')."
+            },
         }
     },
  )]
@@ -245,25 +662,55 @@ async fn diff(
     config: Option<String>,
     id: String,
     verbose: bool,
+    package_level: bool,
+    summary: bool,
+    env_format: bool,
     _param: Value,
 ) -> Result<Value, Error> {
+    if env_format && !summary {
+        bail!("'--env-format' requires '--summary'.");
+    }
+
     let config = config.unwrap_or_else(get_config_path);
 
     let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
     let config: MediaConfig = section_config.lookup("medium", &id)?;
     let mut mirrors = Vec::with_capacity(config.mirrors.len());
     for mirror in &config.mirrors {
-        let mirror: MirrorConfig = section_config.lookup("mirror", mirror)?;
+        let mirror = proxmox_offline_mirror::config::lookup_mirror(&section_config, mirror)?;
         mirrors.push(mirror);
     }
 
     let mut diffs = medium::diff(&config, mirrors)?;
+
+    if summary {
+        let total = diffs
+            .values()
+            .flatten()
+            .fold(DiffSummary::default(), |mut total, diff| {
+                total.merge(&diff.summary());
+                total
+            });
+
+        if env_format {
+            println!("SOURCE_TO_MEDIUM_BYTES={}", total.source_to_medium_bytes);
+            println!("MEDIUM_TO_SOURCE_BYTES={}", total.medium_to_source_bytes);
+            println!("CHANGED_BYTES={}", total.changed_bytes);
+            println!("ESTIMATED_SYNC_BYTES={}", total.estimated_sync_bytes);
+        } else {
+            println!("Missing on medium: {}b", total.source_to_medium_bytes);
+            println!("Only on medium: {}b", total.medium_to_source_bytes);
+            println!("Changed: {}b", total.changed_bytes);
+            println!("Estimated sync transfer: {}b", total.estimated_sync_bytes);
+        }
+
+        return Ok(Value::Null);
+    }
+
     let mut mirrors: Vec<String> = diffs.keys().cloned().collect();
     mirrors.sort_unstable();
 
-    let sort_paths =
-        |(path, _): &(PathBuf, u64), (other_path, _): &(PathBuf, u64)| path.cmp(other_path);
-
+    let mut total = Diff::default();
     let mut first = true;
     for mirror in mirrors {
         if first {
@@ -274,51 +721,134 @@ async fn diff(
 
         println!("Mirror '{mirror}'");
         if let Some(Some(mut diff)) = diffs.remove(&mirror) {
-            let mut total_size = 0;
-            println!("\t{} file(s) only on medium:", diff.added.paths.len());
+            let packages = package_level.then(|| package_diff(&diff));
             if verbose {
-                diff.added.paths.sort_unstable_by(sort_paths);
-                diff.changed.paths.sort_unstable_by(sort_paths);
-                diff.removed.paths.sort_unstable_by(sort_paths);
+                diff.sort();
             }
-            for (path, size) in diff.added.paths {
-                if verbose {
+
+            println!(
+                "\t{} file(s) only on medium ({} packages, {} index files):",
+                diff.added.paths.len(),
+                diff.added.package_count(),
+                diff.added.index_file_count(),
+            );
+            if verbose {
+                for (path, size) in &diff.added.paths {
                     println!("\t\t{path:?}: +{size}b");
                 }
-                total_size += size;
             }
-            println!("\tTotal size: +{total_size}b");
+            println!("\tTotal size: +{}b", diff.total_bytes_added());
 
-            total_size = 0;
             println!(
-                "\n\t{} file(s) missing on medium:",
-                diff.removed.paths.len()
+                "\n\t{} file(s) missing on medium ({} packages, {} index files):",
+                diff.removed.paths.len(),
+                diff.removed.package_count(),
+                diff.removed.index_file_count(),
             );
-            for (path, size) in diff.removed.paths {
-                if verbose {
+            if verbose {
+                for (path, size) in &diff.removed.paths {
                     println!("\t\t{path:?}: -{size}b");
                 }
-                total_size += size;
             }
-            println!("\tTotal size: -{total_size}b");
+            println!("\tTotal size: -{}b", diff.total_bytes_removed());
 
-            total_size = 0;
             println!(
                 "\n\t{} file(s) diff between source and medium:",
                 diff.changed.paths.len()
             );
-            for (path, size) in diff.changed.paths {
-                if verbose {
-                    println!("\t\t{path:?}: +-{size}b");
+            if verbose {
+                for (path, size) in &diff.changed.paths {
+                    println!("\t\t{path:?}: {size}b");
                 }
             }
-            println!("\tSum of size differences: +-{total_size}b");
+            println!("\tTotal size: {}b", diff.changed.unique_bytes);
+
+            if let Some(packages) = packages {
+                print_package_diff(&packages);
+            }
+
+            total += diff;
         } else {
             // TODO
             println!("\tNot yet synced or no longer available on source side.");
         }
     }
 
+    println!(
+        "\nTotal across all mirrors: added {}; removed {}",
+        total.added, total.removed
+    );
+
+    Ok(Value::Null)
+}
+
+fn print_package_diff(packages: &BTreeMap<String, PackageDiff>) {
+    let format_versions = |list: &[PackagePath]| -> String {
+        list.iter()
+            .map(|package| format!("{} ({})", package.version, package.architecture))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+
+    println!("\n\t{} package(s) differ:", packages.len());
+    for (name, package) in packages {
+        println!("\t\t{name}");
+        if !package.added.is_empty() {
+            println!("\t\t\tonly on medium: {}", format_versions(&package.added));
+        }
+        if !package.removed.is_empty() {
+            println!(
+                "\t\t\tmissing on medium: {}",
+                format_versions(&package.removed)
+            );
+        }
+        if !package.changed.is_empty() {
+            println!(
+                "\t\t\tdiffering content: {}",
+                format_versions(&package.changed)
+            );
+        }
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MEDIA_ID_SCHEMA,
+            },
+            output: {
+                type: String,
+                description: "Path of the generated HTML file.",
+            },
+        }
+    },
+ )]
+/// Generate an HTML catalog of the mirrors, snapshots and packages on a medium
+async fn catalog(
+    config: Option<String>,
+    id: String,
+    output: String,
+    _param: Value,
+) -> Result<Value, Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let medium_config: MediaConfig = section_config.lookup("medium", &id)?;
+
+    let (state, _mirror_state) = medium::status(&medium_config)?;
+    medium::generate_catalog(
+        Path::new(&medium_config.mountpoint),
+        &state,
+        Path::new(&output),
+    )?;
+    println!("Wrote catalog to {output:?}");
+
     Ok(Value::Null)
 }
 
@@ -332,8 +862,21 @@ pub fn medium_commands() -> CommandLineInterface {
             "status",
             CliCommand::new(&API_METHOD_STATUS).arg_param(&["id"]),
         )
+        .insert(
+            "format-info",
+            CliCommand::new(&API_METHOD_FORMAT_INFO).arg_param(&["id"]),
+        )
+        .insert(
+            "set-snapshot",
+            CliCommand::new(&API_METHOD_SET_SNAPSHOT).arg_param(&["id", "mirror-id", "snapshot"]),
+        )
         .insert("sync", CliCommand::new(&API_METHOD_SYNC).arg_param(&["id"]))
-        .insert("diff", CliCommand::new(&API_METHOD_DIFF).arg_param(&["id"]));
+        .insert("sync-all", CliCommand::new(&API_METHOD_SYNC_ALL))
+        .insert("diff", CliCommand::new(&API_METHOD_DIFF).arg_param(&["id"]))
+        .insert(
+            "catalog",
+            CliCommand::new(&API_METHOD_CATALOG).arg_param(&["id"]),
+        );
 
     cmd_def.into()
 }
@@ -1,11 +1,13 @@
 use anyhow::{Error, bail, format_err};
 
 use proxmox_section_config::SectionConfigData;
-use proxmox_subscription::SubscriptionStatus;
 use serde_json::Value;
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
-    path::PathBuf,
+    io::Write,
+    path::Path,
+    sync::Arc,
+    time::Duration,
 };
 
 use proxmox_router::cli::{
@@ -16,8 +18,14 @@ use proxmox_schema::api;
 
 use proxmox_offline_mirror::{
     config::{MirrorConfig, SubscriptionKey},
-    mirror,
-    types::{MIRROR_ID_SCHEMA, Snapshot},
+    helpers::{
+        keys::check_key_expiry,
+        metrics::{MirrorMetrics, write_metrics_file},
+        pkg_path::snapshot_package_diff,
+        progress_socket::{ProgressSocket, watch_progress_socket},
+    },
+    mirror::{self, SnapshotEvent},
+    types::{GcReport, MIRROR_ID_SCHEMA, PreflightSeverity, Snapshot, SnapshotState},
 };
 
 use super::get_config_path;
@@ -30,13 +38,7 @@ fn get_subscription_key(
         let subscriptions: Vec<SubscriptionKey> = config.convert_to_typed_array("subscription")?;
         let key = subscriptions
             .iter()
-            .find(|key| {
-                if let Ok(Some(info)) = key.info() {
-                    info.status == SubscriptionStatus::Active && key.product() == *product
-                } else {
-                    false
-                }
-            })
+            .find(|key| key.is_active() && key.product().is_ok_and(|p| p == *product))
             .ok_or_else(|| {
                 format_err!(
                     "Need matching active subscription key for product {product}, but none found."
@@ -65,7 +67,59 @@ fn get_subscription_key(
                 optional: true,
                 default: false,
                 description: "Only fetch indices and print summary of missing package files, don't store anything.",
-            }
+            },
+            "metrics-file": {
+                type: String,
+                optional: true,
+                description: "Write Prometheus metrics to this file after completion.",
+            },
+            at: {
+                type: Snapshot,
+                optional: true,
+                description: "Use this snapshot name instead of the current time.",
+            },
+            overwrite: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Replace an existing snapshot with the name given via '--at' or generated from the 'snapshot-name-template', once the new one was created.",
+            },
+            since: {
+                type: Snapshot,
+                optional: true,
+                description: "Only create a snapshot if the upstream release file is newer than this snapshot.",
+            },
+            quick: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Clone the latest snapshot instead of fetching indices if the upstream release file is unchanged.",
+            },
+            "lock-timeout": {
+                type: Integer,
+                optional: true,
+                default: 0,
+                minimum: 0,
+                description: "Seconds to wait for another snapshot creation of this mirror to finish, instead of failing immediately.",
+            },
+            component: {
+                type: Array,
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Component name.",
+                },
+                description: "Only fetch the given component(s), marking the snapshot as partial.",
+            },
+            "daemon-progress-socket": {
+                type: String,
+                optional: true,
+                description: "Stream progress events to clients connected to a Unix socket at this path, see 'snapshot watch'.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
         },
     },
  )]
@@ -74,21 +128,133 @@ async fn create_snapshot(
     config: Option<String>,
     id: String,
     dry_run: bool,
-    _param: Value,
+    metrics_file: Option<String>,
+    at: Option<Snapshot>,
+    overwrite: bool,
+    since: Option<Snapshot>,
+    quick: bool,
+    lock_timeout: u64,
+    component: Option<Vec<String>>,
+    daemon_progress_socket: Option<String>,
+    param: Value,
 ) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
     let config = config.unwrap_or_else(get_config_path);
 
     let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
-    let config: MirrorConfig = section_config.lookup("mirror", &id)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&section_config, &id)?;
+
+    let snapshot = match at {
+        Some(snapshot) => {
+            if mirror::list_complete_snapshots(&config)?.contains(&snapshot) {
+                if !overwrite {
+                    bail!(
+                        "Snapshot {snapshot} of mirror '{id}' already exists, pass '--overwrite' to replace it."
+                    );
+                }
+            }
+            snapshot
+        }
+        None if overwrite && config.snapshot_name_template.is_none() => {
+            bail!("'--overwrite' requires '--at' or a 'snapshot-name-template'.")
+        }
+        None => {
+            let snapshot = mirror::snapshot_name(&config, proxmox_time::epoch_i64())?;
+            if config.snapshot_name_template.is_some()
+                && mirror::list_complete_snapshots(&config)?.contains(&snapshot)
+            {
+                if !overwrite {
+                    println!("Snapshot {snapshot} of mirror '{id}' already exists, nothing to do.");
+                    return Ok(());
+                }
+            }
+            snapshot
+        }
+    };
 
     let subscription = get_subscription_key(&section_config, &config)?;
 
-    proxmox_offline_mirror::mirror::create_snapshot(
-        config,
-        &Snapshot::now(),
-        subscription,
-        dry_run,
-    )?;
+    if let Some(since) = since {
+        let release_date = mirror::upstream_release_date(config.clone(), subscription.clone())?;
+        if release_date <= since.to_epoch()? {
+            println!(
+                "Upstream release ({}) is not newer than snapshot {since}, nothing to do.",
+                proxmox_time::epoch_to_rfc3339_utc(release_date)?
+            );
+            return Ok(());
+        }
+    }
+
+    let unchanged = if quick {
+        if component.is_some() {
+            bail!("'--quick' can't be combined with '--component'.");
+        }
+        mirror::unchanged_snapshot(&config, subscription.clone())?
+    } else {
+        None
+    };
+
+    let progress_socket = daemon_progress_socket
+        .map(|path| ProgressSocket::bind(Path::new(&path)))
+        .transpose()?
+        .map(Arc::new);
+    let on_progress = progress_socket.clone().map(|socket| {
+        Box::new(move |event: SnapshotEvent| {
+            if let Err(err) = socket.send(&event) {
+                eprintln!("Failed to send progress event - {err}");
+            }
+        }) as Box<dyn Fn(SnapshotEvent) + Send>
+    });
+
+    let res = match unchanged {
+        Some(latest) => mirror::clone_snapshot(
+            &config,
+            &latest,
+            &snapshot,
+            dry_run,
+            overwrite,
+            Duration::from_secs(lock_timeout),
+            on_progress,
+        ),
+        None => proxmox_offline_mirror::mirror::create_snapshot(
+            config.clone(),
+            &snapshot,
+            subscription,
+            dry_run,
+            overwrite,
+            Duration::from_secs(lock_timeout),
+            component,
+            on_progress,
+        ),
+    };
+    let summary = match res {
+        Ok(summary) => summary,
+        Err(err) => {
+            if let Some(socket) = &progress_socket {
+                let _ = socket.send(&SnapshotEvent::Failed {
+                    error: err.to_string(),
+                });
+            }
+            return Err(err.into());
+        }
+    };
+
+    if let Some(metrics_file) = metrics_file {
+        let pool = mirror::pool_stats(&config)?;
+        let snapshot_count = mirror::list_complete_snapshots(&config)?.len();
+        let metrics = MirrorMetrics {
+            mirror: &config.id,
+            progress: &summary.progress,
+            pool: &pool,
+            snapshot_count,
+            last_sync: proxmox_time::epoch_i64(),
+        };
+        write_metrics_file(Path::new(&metrics_file), &[metrics])?;
+    }
+
+    if output_format != "text" {
+        format_and_print_result(&serde_json::json!(summary), &output_format);
+    }
 
     Ok(())
 }
@@ -106,7 +272,14 @@ async fn create_snapshot(
                 optional: true,
                 default: false,
                 description: "Only fetch indices and print summary of missing package files, don't store anything.",
-            }
+            },
+            "lock-timeout": {
+                type: Integer,
+                optional: true,
+                default: 0,
+                minimum: 0,
+                description: "Seconds to wait for another snapshot creation of a mirror to finish, instead of failing immediately.",
+            },
         },
     },
  )]
@@ -115,6 +288,7 @@ async fn create_snapshot(
 async fn create_snapshots(
     config: Option<String>,
     dry_run: bool,
+    lock_timeout: u64,
     _param: Value,
 ) -> Result<(), Error> {
     let config = config.unwrap_or_else(get_config_path);
@@ -124,9 +298,14 @@ async fn create_snapshots(
 
     let mut results = HashMap::new();
 
-    for mirror in mirrors {
+    for mut mirror in mirrors {
         let mirror_id = mirror.id.clone();
         println!("\nCREATING SNAPSHOT FOR '{mirror_id}'..");
+        if let Err(err) = proxmox_offline_mirror::config::expand_env_vars(&mut mirror) {
+            eprintln!("Skipping mirror '{mirror_id}' - {err}");
+            results.insert(mirror_id, Err(err));
+            continue;
+        }
         let subscription = match get_subscription_key(&section_config, &mirror) {
             Ok(opt_key) => opt_key,
             Err(err) => {
@@ -135,12 +314,43 @@ async fn create_snapshots(
                 continue;
             }
         };
+        let snapshot =
+            match proxmox_offline_mirror::mirror::snapshot_name(&mirror, proxmox_time::epoch_i64())
+            {
+                Ok(snapshot) => snapshot,
+                Err(err) => {
+                    eprintln!("Skipping mirror '{mirror_id}' - {err}");
+                    results.insert(mirror_id, Err(err));
+                    continue;
+                }
+            };
+        if mirror.snapshot_name_template.is_some() {
+            match proxmox_offline_mirror::mirror::list_complete_snapshots(&mirror) {
+                Ok(snapshots) if snapshots.contains(&snapshot) => {
+                    println!(
+                        "Snapshot {snapshot} of mirror '{mirror_id}' already exists, skipping."
+                    );
+                    continue;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!("Skipping mirror '{mirror_id}' - {err}");
+                    results.insert(mirror_id, Err(err.into()));
+                    continue;
+                }
+            }
+        }
         let res = proxmox_offline_mirror::mirror::create_snapshot(
             mirror,
-            &Snapshot::now(),
+            &snapshot,
             subscription,
             dry_run,
-        );
+            false,
+            Duration::from_secs(lock_timeout),
+            None,
+            None,
+        )
+        .map_err(Error::from);
         if let Err(err) = &res {
             eprintln!("Failed to create snapshot for '{mirror_id}' - {err}");
         }
@@ -149,8 +359,16 @@ async fn create_snapshots(
     }
 
     println!("\nSUMMARY:");
-    for (mirror_id, _res) in results.iter().filter(|(_, res)| res.is_ok()) {
-        println!("{mirror_id}: OK"); // TODO update once we have a proper return value
+    for (mirror_id, res) in results.iter() {
+        if let Ok(summary) = res {
+            println!(
+                "{mirror_id}: OK - {} new files ({}b), {} re-used files, {} warnings",
+                summary.progress.new_files,
+                summary.progress.new_bytes,
+                summary.progress.reused_files,
+                summary.warnings.len(),
+            );
+        }
     }
 
     let mut fail = false;
@@ -179,6 +397,30 @@ async fn create_snapshots(
                 schema: MIRROR_ID_SCHEMA,
                 optional: true,
             },
+            detailed: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Include number and size of files per snapshot (requires walking each snapshot).",
+            },
+            oldest: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Only print the oldest snapshot of the given mirror.",
+            },
+            newest: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Only print the newest snapshot of the given mirror.",
+            },
+            count: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Only print the number of snapshots of the given mirror.",
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -190,16 +432,49 @@ async fn create_snapshots(
 async fn list_snapshots(
     config: Option<String>,
     id: Option<String>,
+    detailed: bool,
+    oldest: bool,
+    newest: bool,
+    count: bool,
     param: Value,
 ) -> Result<(), Error> {
     let output_format = get_output_format(&param);
     let config = config.unwrap_or_else(get_config_path);
 
     let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+
+    if oldest || newest || count {
+        if [oldest, newest, count]
+            .into_iter()
+            .filter(|flag| *flag)
+            .count()
+            > 1
+        {
+            bail!("'--oldest', '--newest' and '--count' are mutually exclusive.");
+        }
+        let id = match id {
+            Some(id) => id,
+            None => bail!("'--oldest', '--newest' and '--count' require a mirror ID."),
+        };
+        let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+        let list = mirror::list_complete_snapshots(&config)?;
+
+        if count {
+            println!("{}", list.len());
+        } else {
+            let snapshot = if oldest { list.first() } else { list.last() };
+            match snapshot {
+                Some(snapshot) => println!("{snapshot}"),
+                None => bail!("Mirror '{id}' has no snapshots."),
+            }
+        }
+        return Ok(());
+    }
+
     let res = if let Some(id) = id {
-        let config: MirrorConfig = config.lookup("mirror", &id)?;
+        let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
 
-        let list = mirror::list_snapshots(&config)?;
+        let list = mirror::list_snapshot_summaries(&config, detailed)?;
         let mut map = BTreeMap::new();
         map.insert(config.id, list);
         map
@@ -207,8 +482,10 @@ async fn list_snapshots(
         let mirrors: Vec<MirrorConfig> = config.convert_to_typed_array("mirror")?;
         mirrors
             .into_iter()
-            .fold(BTreeMap::new(), |mut map, mirror| {
-                match mirror::list_snapshots(&mirror) {
+            .fold(BTreeMap::new(), |mut map, mut mirror| {
+                let list = proxmox_offline_mirror::config::expand_env_vars(&mut mirror)
+                    .and_then(|()| mirror::list_snapshot_summaries(&mirror, detailed));
+                match list {
                     Ok(list) => {
                         map.insert(mirror.id, list);
                     }
@@ -226,9 +503,27 @@ async fn list_snapshots(
             } else {
                 println!();
             }
-            println!("{mirror} ({} snapshots):", list.len());
-            for snap in &list {
-                println!("- {snap}");
+            let complete = list
+                .iter()
+                .filter(|summary| summary.state == SnapshotState::Complete)
+                .count();
+            println!("{mirror} ({complete} snapshots):");
+            for summary in &list {
+                let mut marker = match (summary.state, summary.is_pinned) {
+                    (SnapshotState::Incomplete, _) => " [incomplete]",
+                    (SnapshotState::Complete, true) => " (pinned)",
+                    (SnapshotState::Complete, false) => "",
+                }
+                .to_string();
+                if summary.is_partial {
+                    marker.push_str(" (partial)");
+                }
+                match (summary.file_count, summary.size_bytes) {
+                    (Some(count), Some(size)) => {
+                        println!("- {}{marker}: {count} files, {size}b", summary.snapshot)
+                    }
+                    _ => println!("- {}{marker}", summary.snapshot),
+                }
             }
         }
     } else {
@@ -238,6 +533,43 @@ async fn list_snapshots(
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+        }
+    },
+ )]
+/// Remove leftover temporary directories of interrupted snapshot creations of a mirror. Must not
+/// be run while a snapshot of this mirror is being created.
+async fn cleanup_incomplete(
+    config: Option<String>,
+    id: String,
+    _param: Value,
+) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+
+    let removed = mirror::cleanup_incomplete_snapshots(&config)?;
+    if removed.is_empty() {
+        println!("No incomplete snapshots found.");
+    }
+    for snapshot in removed {
+        println!("Removed incomplete snapshot {snapshot}.");
+    }
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -252,6 +584,12 @@ async fn list_snapshots(
             snapshot: {
                 type: Snapshot,
             },
+            force: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Remove snapshot even if it is pinned.",
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -261,6 +599,73 @@ async fn list_snapshots(
  )]
 /// Remove a single snapshot dir from a mirror. To actually removed the referenced data a garbage collection is required.
 async fn remove_snapshot(
+    config: Option<String>,
+    id: String,
+    snapshot: Snapshot,
+    force: bool,
+    _param: Value,
+) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    mirror::remove_snapshot(&config, &snapshot, force)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            snapshot: {
+                type: Snapshot,
+            },
+        }
+    },
+ )]
+/// Pin a snapshot, protecting it from removal.
+async fn pin_snapshot(
+    config: Option<String>,
+    id: String,
+    snapshot: Snapshot,
+    _param: Value,
+) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    mirror::pin_snapshot(&config, &snapshot)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            snapshot: {
+                type: Snapshot,
+            },
+        }
+    },
+ )]
+/// Unpin a previously pinned snapshot.
+async fn unpin_snapshot(
     config: Option<String>,
     id: String,
     snapshot: Snapshot,
@@ -269,8 +674,8 @@ async fn remove_snapshot(
     let config = config.unwrap_or_else(get_config_path);
 
     let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
-    let config: MirrorConfig = config.lookup("mirror", &id)?;
-    mirror::remove_snapshot(&config, &snapshot)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    mirror::unpin_snapshot(&config, &snapshot)?;
 
     Ok(())
 }
@@ -287,6 +692,12 @@ async fn remove_snapshot(
                 schema: MIRROR_ID_SCHEMA,
                 optional: true,
             },
+            all: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "GC the pools of all configured mirrors (default if no `id` is given).",
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -299,35 +710,45 @@ async fn remove_snapshot(
 async fn garbage_collect(
     config: Option<String>,
     id: Option<String>,
-    _param: Value,
+    all: bool,
+    param: Value,
 ) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
     let config = config.unwrap_or_else(get_config_path);
 
     let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
 
-    let (count, size) = if let Some(id) = id {
-        let config: MirrorConfig = config.lookup("mirror", &id)?;
+    if all && id.is_some() {
+        bail!("'all' and 'id' are mutually exclusive.");
+    }
+
+    let report = if let Some(id) = id {
+        let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
         mirror::gc(&config)?
     } else {
-        let mut total_count = 0;
-        let mut total_size = 0;
-        let mut error_count = 0;
+        let mut total = GcReport::default();
+        let mut errors = Vec::new();
         let mut base_dirs = HashSet::new();
 
-        for mirror_config in config.convert_to_typed_array::<MirrorConfig>("mirror")? {
+        for mut mirror_config in config.convert_to_typed_array::<MirrorConfig>("mirror")? {
+            if let Err(err) = proxmox_offline_mirror::config::expand_env_vars(&mut mirror_config) {
+                eprintln!("Skipping mirror '{}' - {err}", mirror_config.id);
+                errors.push((mirror_config.id, err));
+                println!();
+                continue;
+            }
             if base_dirs.insert(mirror_config.base_dir.clone()) {
                 match mirror::gc(&mirror_config) {
-                    Ok((count, size)) => {
+                    Ok(report) => {
                         println!(
-                            "{}: removed {count} files totalling {size}b",
-                            mirror_config.id
+                            "{}: removed {} files totalling {}b",
+                            mirror_config.id, report.files_removed, report.bytes_freed
                         );
-                        total_count += count;
-                        total_size += size;
+                        total.merge(&report);
                     }
                     Err(err) => {
-                        error_count += 1;
                         eprintln!("{}: failed to run GC - {err}", mirror_config.id);
+                        errors.push((mirror_config.id, err));
                     }
                 }
             } else {
@@ -338,13 +759,24 @@ async fn garbage_collect(
             }
             println!();
         }
-        if error_count > 0 {
-            eprintln!("Encountered {error_count} errors, please check log.");
+        if !errors.is_empty() {
+            eprintln!("Encountered {} errors:", errors.len());
+            for (id, err) in &errors {
+                eprintln!("{id}: ERR - {err}");
+            }
         }
-        (total_count, total_size)
+        total
     };
 
-    println!("Removed {} files totalling {}b", count, size);
+    if output_format == "text" {
+        println!(
+            "Removed {} files totalling {}b, including {} orphaned links",
+            report.files_removed, report.bytes_freed, report.orphaned_links_removed
+        );
+        println!("{} files remaining in pool", report.pool_files_remaining);
+    } else {
+        format_and_print_result(&serde_json::json!(report), &output_format);
+    }
 
     Ok(())
 }
@@ -366,6 +798,12 @@ async fn garbage_collect(
             other_snapshot: {
                 type: Snapshot,
             },
+            "package-diff": {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Print added, removed and upgraded packages (name, version and architecture) instead of file paths.",
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -379,28 +817,58 @@ async fn diff_snapshots(
     id: String,
     snapshot: Snapshot,
     other_snapshot: Snapshot,
+    package_diff: bool,
     _param: Value,
 ) -> Result<(), Error> {
     let config = config.unwrap_or_else(get_config_path);
 
     let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
-    let config: MirrorConfig = config.lookup("mirror", &id)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
     let mut diff = mirror::diff_snapshots(&config, &snapshot, &other_snapshot)?;
-    let sort = |(path, _): &(PathBuf, u64), (other_path, _): &(PathBuf, u64)| path.cmp(other_path);
-    diff.added.paths.sort_unstable_by(sort);
-    diff.changed.paths.sort_unstable_by(sort);
-    diff.removed.paths.sort_unstable_by(sort);
+    if diff.is_empty() {
+        println!("No differences between {snapshot} and {other_snapshot}");
+        return Ok(());
+    }
+    diff.sort();
+
+    if package_diff {
+        let packages = snapshot_package_diff(&diff);
+        println!("--- {snapshot}");
+        println!("+++ {other_snapshot}");
+        for package in &packages.removed {
+            println!(
+                "- {} {} {}",
+                package.name, package.version, package.architecture
+            );
+        }
+        for package in &packages.added {
+            println!(
+                "+ {} {} {}",
+                package.name, package.version, package.architecture
+            );
+        }
+        for (old, new) in &packages.upgraded {
+            println!(
+                "~ {} {} -> {} {}",
+                old.name, old.version, new.version, new.architecture
+            );
+        }
+        println!(
+            "\nSummary: {} added, {} removed, {} upgraded packages",
+            packages.added.len(),
+            packages.removed.len(),
+            packages.upgraded.len()
+        );
+        return Ok(());
+    }
 
-    println!("{other_snapshot} added {} file(s)", diff.added.paths.len());
-    for (path, size) in diff.added.paths {
+    println!("{other_snapshot} added {}", diff.added);
+    for (path, size) in &diff.added.paths {
         println!("\t{path:?}: +{size}b");
     }
 
-    println!(
-        "\n{other_snapshot} removed {} file(s)",
-        diff.removed.paths.len()
-    );
-    for (path, size) in diff.removed.paths {
+    println!("\n{other_snapshot} removed {}", diff.removed);
+    for (path, size) in &diff.removed.paths {
         println!("\t{path:?}: -{size}b");
     }
 
@@ -408,42 +876,938 @@ async fn diff_snapshots(
         "\n {} file(s) diff between {snapshot} and {other_snapshot}",
         diff.changed.paths.len()
     );
-    for (path, size) in diff.changed.paths {
-        println!("\t{path:?}: +-{size}b");
+    for (path, size) in &diff.changed.paths {
+        println!("\t{path:?}: {size}b");
     }
 
+    println!("\nSummary: {diff}");
+
     Ok(())
 }
 
-pub fn mirror_commands() -> CommandLineInterface {
-    let snapshot_cmds = CliCommandMap::new()
-        .insert(
-            "create",
-            CliCommand::new(&API_METHOD_CREATE_SNAPSHOT).arg_param(&["id"]),
-        )
-        .insert("create-all", CliCommand::new(&API_METHOD_CREATE_SNAPSHOTS))
-        .insert(
-            "list",
-            CliCommand::new(&API_METHOD_LIST_SNAPSHOTS).arg_param(&["id"]),
-        )
-        .insert(
-            "remove",
-            CliCommand::new(&API_METHOD_REMOVE_SNAPSHOT).arg_param(&["id", "snapshot"]),
-        )
-        .insert(
-            "diff",
-            CliCommand::new(&API_METHOD_DIFF_SNAPSHOTS).arg_param(&[
-                "id",
-                "snapshot",
-                "other_snapshot",
-            ]),
-        );
-
-    let cmd_def = CliCommandMap::new()
-        .insert("snapshot", snapshot_cmds)
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            snapshot: {
+                type: Snapshot,
+            },
+            "repo-path": {
+                type: String,
+                description: "Path of the file relative to the repository root, e.g. 'pool/main/p/proxmox-ve/proxmox-ve_8.0-1_amd64.deb'.",
+            },
+            dest: {
+                type: String,
+                description: "Path to copy the file to.",
+            },
+            overwrite: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Overwrite destination if it already exists.",
+            },
+        }
+    },
+ )]
+/// Extract a single file from a snapshot.
+async fn restore_file(
+    config: Option<String>,
+    id: String,
+    snapshot: Snapshot,
+    repo_path: String,
+    dest: String,
+    overwrite: bool,
+    _param: Value,
+) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    mirror::restore_file(&config, &snapshot, &repo_path, Path::new(&dest), overwrite)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            package: {
+                type: String,
+                description: "Package name.",
+            },
+            version: {
+                type: String,
+                optional: true,
+                description: "Only search for this package version.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Find snapshots containing a package.
+async fn search_package(
+    config: Option<String>,
+    id: String,
+    package: String,
+    version: Option<String>,
+    param: Value,
+) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let matches = mirror::search_package_in_snapshots(&config, &package, version.as_deref())?;
+
+    if output_format == "text" {
+        if matches.is_empty() {
+            println!("Package '{package}' not found in any snapshot.");
+        }
+        for (snapshot, found) in &matches {
+            println!(
+                "{snapshot}: {package} {} ({}) - {}",
+                found.version, found.architecture, found.file
+            );
+        }
+    } else {
+        let list: Vec<_> = matches.into_iter().map(|(_, found)| found).collect();
+        format_and_print_result(&serde_json::json!(list), &output_format);
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Merge SHA-256 and SHA-512 checksum files of the same content in a mirror's pool.
+async fn compact(config: Option<String>, id: String, param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let report = mirror::compact(&config)?;
+
+    if output_format == "text" {
+        println!(
+            "Merged {} files, saved {}b, skipped {} files referenced from outside the mirror",
+            report.inodes_merged, report.bytes_saved, report.inodes_skipped
+        );
+    } else {
+        format_and_print_result(&serde_json::json!(report), &output_format);
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            fix: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Remove files with mismatching contents from the pool, so that they are fetched again on the next snapshot.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Verify the contents of all files in a mirror's pool against their checksums.
+async fn scrub(config: Option<String>, id: String, fix: bool, param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let report = mirror::scrub(&config, fix)?;
+
+    if output_format == "text" {
+        println!(
+            "Checked {} files: {} ok, {} mismatched, {} removed",
+            report.checked, report.ok, report.mismatched, report.removed
+        );
+    } else {
+        format_and_print_result(&serde_json::json!(report), &output_format);
+    }
+
+    if report.mismatched > 0 && !fix {
+        bail!(
+            "Found {} files with mismatching contents.",
+            report.mismatched
+        );
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Show how much space is saved by sharing files between snapshots (and mirrors sharing a pool).
+async fn pool_stats(config: Option<String>, id: String, param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let stats = mirror::dedup_stats(&config)?;
+
+    if output_format == "text" {
+        println!("Links: {} ({}b)", stats.link_count, stats.logical_bytes);
+        println!(
+            "Unique pool files: {} ({}b)",
+            stats.unique_file_count, stats.physical_bytes
+        );
+        println!("Deduplication ratio: {:.2}", stats.ratio);
+    } else {
+        format_and_print_result(&serde_json::json!(stats), &output_format);
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Show expiry information of a mirror's configured key.
+async fn key_info(config: Option<String>, id: String, param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let info = check_key_expiry(Path::new(&config.key_path))?;
+
+    if output_format == "text" {
+        println!("Key file: {}", config.key_path);
+        println!("Fingerprint: {}", info.fingerprint);
+        match info.expires_at {
+            Some(expires_at) => {
+                let expires_at = proxmox_time::epoch_to_rfc3339_utc(expires_at)?;
+                if info.is_expired {
+                    println!("Expired: {expires_at}");
+                } else {
+                    println!("Expires: {expires_at}");
+                }
+            }
+            None => println!("Expires: never"),
+        }
+    } else {
+        format_and_print_result(&serde_json::json!(info), &output_format);
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Estimate the size of a new snapshot using the repository's package indices only, without
+/// fetching any packages.
+async fn estimate_size(config: Option<String>, id: String, param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&section_config, &id)?;
+    let subscription = get_subscription_key(&section_config, &config)?;
+
+    let estimate = mirror::estimate_snapshot_size(&config, subscription)?;
+
+    if output_format == "text" {
+        println!("Index files: {}b", estimate.index_bytes);
+        println!(
+            "Packages (upper bound): {}b",
+            estimate.package_bytes_upper_bound
+        );
+        println!("Already in pool: {}b", estimate.already_in_pool_estimate);
+        if !estimate.warnings.is_empty() {
+            eprintln!("Warnings:");
+            for msg in &estimate.warnings {
+                eprintln!("- {msg}");
+            }
+        }
+    } else {
+        format_and_print_result(&serde_json::json!(estimate), &output_format);
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Check a mirror's config for common problems (key files, repository, reachability, base
+/// directory) before creating the first snapshot.
+async fn preflight_check(config: Option<String>, id: String, param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let issues = mirror::preflight_check(&config)?;
+
+    if output_format == "text" {
+        if issues.is_empty() {
+            println!("No issues found.");
+        }
+        for issue in &issues {
+            println!("{}: {}", issue.severity, issue.message);
+        }
+    } else {
+        format_and_print_result(&serde_json::json!(issues), &output_format);
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == PreflightSeverity::Error)
+        .count();
+    if errors > 0 {
+        bail!("Preflight check of mirror '{id}' found {errors} error(s).");
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            fix: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Remove files not referencing any file in the pool.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Check that all files in a mirror's snapshots reference a file in the pool.
+async fn verify_links(
+    config: Option<String>,
+    id: String,
+    fix: bool,
+    param: Value,
+) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let report = mirror::verify_links(&config, fix)?;
+
+    if output_format == "text" {
+        println!(
+            "Checked {} files: {} dangling{}",
+            report.total_checked,
+            report.dangling.len(),
+            if fix && !report.dangling.is_empty() {
+                " (removed)"
+            } else {
+                ""
+            }
+        );
+    } else {
+        format_and_print_result(&serde_json::json!(report), &output_format);
+    }
+
+    if !report.dangling.is_empty() && !fix {
+        bail!("Found {} dangling files.", report.dangling.len());
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Check a mirror's pool for links without pool backing and pool files without links.
+async fn pool_check(config: Option<String>, id: String, param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let report = mirror::pool_check(&config)?;
+
+    if output_format == "text" {
+        println!(
+            "Links with no pool backing: {}",
+            report.orphaned_links.len()
+        );
+        for path in &report.orphaned_links {
+            println!("  {path:?}");
+        }
+        println!(
+            "Pool files with no links: {}",
+            report.unlinked_pool_files.len()
+        );
+        for path in &report.unlinked_pool_files {
+            println!("  {path:?}");
+        }
+    } else {
+        format_and_print_result(&serde_json::json!(report), &output_format);
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            output: {
+                type: String,
+                optional: true,
+                description: "Path to write the CSV index to, instead of stdout.",
+            },
+        }
+    },
+ )]
+/// Export a CSV index of all files in a mirror's snapshots, including their checksums and sizes.
+async fn export_index(
+    config: Option<String>,
+    id: String,
+    output: Option<String>,
+    _param: Value,
+) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+    let rows = mirror::export_index(&config, output.as_deref().map(Path::new))?;
+
+    if let Some(output) = output {
+        eprintln!("Wrote index of {rows} files to {output:?}");
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            "src-id": {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "src-snapshot": {
+                type: Snapshot,
+            },
+            "dest-id": {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "dest-snapshot": {
+                type: Snapshot,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Copy a snapshot to another mirror, re-using files already contained in its pool.
+async fn copy_snapshot(
+    config: Option<String>,
+    src_id: String,
+    src_snapshot: Snapshot,
+    dest_id: String,
+    dest_snapshot: Snapshot,
+    param: Value,
+) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let src = proxmox_offline_mirror::config::lookup_mirror(&config, &src_id)?;
+    let dest = proxmox_offline_mirror::config::lookup_mirror(&config, &dest_id)?;
+
+    let report = mirror::copy_snapshot(&src, &src_snapshot, &dest, &dest_snapshot)?;
+
+    if output_format == "text" {
+        println!(
+            "Copied {src_id}/{src_snapshot} to {dest_id}/{dest_snapshot}: {} files copied ({}b), {} files shared",
+            report.files_copied, report.bytes_copied, report.files_shared
+        );
+    } else {
+        format_and_print_result(&serde_json::json!(report), &output_format);
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "source-dir": {
+                type: String,
+                description: "Directory containing the repository tree to import.",
+            },
+            snapshot: {
+                type: Snapshot,
+            },
+            verify: {
+                type: bool,
+                optional: true,
+                default: false,
+                description: "Re-read files already contained in the pool to verify their checksums.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    },
+ )]
+/// Import a directory as snapshot, re-using files already contained in the mirror's pool.
+async fn import_snapshot(
+    config: Option<String>,
+    id: String,
+    source_dir: String,
+    snapshot: Snapshot,
+    verify: bool,
+    param: Value,
+) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+
+    let stats = mirror::import_snapshot(&config, Path::new(&source_dir), &snapshot, verify)?;
+
+    if output_format == "text" {
+        println!(
+            "Imported {source_dir:?} as {id}/{snapshot}: {} files added ({}b), {} files re-used",
+            stats.added_files, stats.added_bytes, stats.reused_files
+        );
+    } else {
+        format_and_print_result(&serde_json::json!(stats), &output_format);
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            "base-dir": {
+                type: String,
+                description: "New base directory of the mirror.",
+            },
+            "pool-dir": {
+                type: String,
+                optional: true,
+                description: "New absolute path to the pool directory, defaults to `<base-dir>/.pool`.",
+            },
+        }
+    },
+ )]
+/// Copy a mirror's pool to a new location and update the config to use it. The old pool is kept.
+async fn move_pool(
+    config: Option<String>,
+    id: String,
+    base_dir: String,
+    pool_dir: Option<String>,
+) -> Result<(), Error> {
+    let config_file = config.unwrap_or_else(get_config_path);
+
+    let _lock = proxmox_offline_mirror::config::lock_config(&config_file)?;
+
+    let (mut config, _digest) = proxmox_offline_mirror::config::config(&config_file)?;
+    let mirror_config = proxmox_offline_mirror::config::lookup_mirror(&config, &id)?;
+
+    let new_config = mirror::move_pool(&mirror_config, base_dir.clone(), pool_dir.clone())?;
+
+    let mut data: MirrorConfig = config.lookup("mirror", &id)?;
+    data.base_dir = base_dir;
+    data.pool_dir = pool_dir;
+    config.set_data(&id, "mirror", &data)?;
+    proxmox_offline_mirror::config::save_config(&config_file, &config)?;
+
+    println!(
+        "Moved pool of mirror '{id}' to {:?} - the old data below {:?} can be removed once no other mirror uses it.",
+        new_config.base_dir, mirror_config.base_dir
+    );
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            id: {
+                schema: MIRROR_ID_SCHEMA,
+            },
+            socket: {
+                type: String,
+                description: "Path of the socket passed to 'snapshot create --daemon-progress-socket'.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+ )]
+/// Watch the progress of a snapshot creation running in another process.
+async fn watch_snapshot(
+    config: Option<String>,
+    id: String,
+    socket: String,
+    param: Value,
+) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let _config = proxmox_offline_mirror::config::lookup_mirror(&section_config, &id)?;
+
+    let mut complete = false;
+    let mut progress_line = false;
+
+    watch_progress_socket(Path::new(&socket), |event: SnapshotEvent| {
+        if let SnapshotEvent::Starting { mirror, .. } = &event {
+            if *mirror != id {
+                bail!("Progress socket belongs to mirror '{mirror}', not '{id}'.");
+            }
+        }
+
+        if output_format != "text" {
+            format_and_print_result(&serde_json::json!(event), &output_format);
+        } else {
+            if progress_line && !matches!(event, SnapshotEvent::Progress { .. }) {
+                println!();
+                progress_line = false;
+            }
+            match &event {
+                SnapshotEvent::Starting {
+                    mirror,
+                    snapshot,
+                    dry_run,
+                } => {
+                    let dry_run = if *dry_run { " (dry-run)" } else { "" };
+                    println!("Creating snapshot {snapshot} of mirror '{mirror}'{dry_run}");
+                }
+                SnapshotEvent::Phase { name } => println!("Phase: {name}"),
+                SnapshotEvent::Progress {
+                    index,
+                    files,
+                    total,
+                    bytes,
+                } => {
+                    print!("\r\t{index}: {files}/{total} entries, {bytes}b new");
+                    let _ = std::io::stdout().flush();
+                    progress_line = true;
+                }
+                SnapshotEvent::Complete {
+                    files,
+                    bytes,
+                    warnings,
+                } => println!(
+                    "Snapshot creation complete - {files} files, {bytes}b new, {warnings} warnings"
+                ),
+                SnapshotEvent::Failed { .. } => {}
+            }
+        }
+
+        match event {
+            SnapshotEvent::Complete { .. } => {
+                complete = true;
+                Ok(false)
+            }
+            SnapshotEvent::Failed { error } => bail!("Snapshot creation failed - {error}"),
+            _ => Ok(true),
+        }
+    })?;
+
+    if !complete {
+        bail!("Progress socket closed before snapshot creation finished.");
+    }
+
+    Ok(())
+}
+
+pub fn mirror_commands() -> CommandLineInterface {
+    let snapshot_cmds = CliCommandMap::new()
+        .insert(
+            "create",
+            CliCommand::new(&API_METHOD_CREATE_SNAPSHOT).arg_param(&["id"]),
+        )
+        .insert("create-all", CliCommand::new(&API_METHOD_CREATE_SNAPSHOTS))
+        .insert(
+            "watch",
+            CliCommand::new(&API_METHOD_WATCH_SNAPSHOT).arg_param(&["id"]),
+        )
+        .insert(
+            "list",
+            CliCommand::new(&API_METHOD_LIST_SNAPSHOTS).arg_param(&["id"]),
+        )
+        .insert(
+            "remove",
+            CliCommand::new(&API_METHOD_REMOVE_SNAPSHOT).arg_param(&["id", "snapshot"]),
+        )
+        .insert(
+            "cleanup-incomplete",
+            CliCommand::new(&API_METHOD_CLEANUP_INCOMPLETE).arg_param(&["id"]),
+        )
+        .insert(
+            "search-package",
+            CliCommand::new(&API_METHOD_SEARCH_PACKAGE).arg_param(&["id", "package"]),
+        )
+        .insert(
+            "restore-file",
+            CliCommand::new(&API_METHOD_RESTORE_FILE).arg_param(&[
+                "id",
+                "snapshot",
+                "repo-path",
+                "dest",
+            ]),
+        )
+        .insert(
+            "pin",
+            CliCommand::new(&API_METHOD_PIN_SNAPSHOT).arg_param(&["id", "snapshot"]),
+        )
+        .insert(
+            "unpin",
+            CliCommand::new(&API_METHOD_UNPIN_SNAPSHOT).arg_param(&["id", "snapshot"]),
+        )
+        .insert(
+            "diff",
+            CliCommand::new(&API_METHOD_DIFF_SNAPSHOTS).arg_param(&[
+                "id",
+                "snapshot",
+                "other_snapshot",
+            ]),
+        )
+        .insert(
+            "copy",
+            CliCommand::new(&API_METHOD_COPY_SNAPSHOT).arg_param(&[
+                "src-id",
+                "src-snapshot",
+                "dest-id",
+                "dest-snapshot",
+            ]),
+        )
+        .insert(
+            "import",
+            CliCommand::new(&API_METHOD_IMPORT_SNAPSHOT).arg_param(&[
+                "id",
+                "source-dir",
+                "snapshot",
+            ]),
+        );
+
+    let cmd_def = CliCommandMap::new()
+        .insert("snapshot", snapshot_cmds)
         .insert(
             "gc",
             CliCommand::new(&API_METHOD_GARBAGE_COLLECT).arg_param(&["id"]),
+        )
+        .insert(
+            "pool-stats",
+            CliCommand::new(&API_METHOD_POOL_STATS).arg_param(&["id"]),
+        )
+        .insert(
+            "key-info",
+            CliCommand::new(&API_METHOD_KEY_INFO).arg_param(&["id"]),
+        )
+        .insert(
+            "preflight-check",
+            CliCommand::new(&API_METHOD_PREFLIGHT_CHECK).arg_param(&["id"]),
+        )
+        .insert(
+            "estimate-size",
+            CliCommand::new(&API_METHOD_ESTIMATE_SIZE).arg_param(&["id"]),
+        )
+        .insert(
+            "scrub",
+            CliCommand::new(&API_METHOD_SCRUB).arg_param(&["id"]),
+        )
+        .insert(
+            "compact",
+            CliCommand::new(&API_METHOD_COMPACT).arg_param(&["id"]),
+        )
+        .insert(
+            "verify-links",
+            CliCommand::new(&API_METHOD_VERIFY_LINKS).arg_param(&["id"]),
+        )
+        .insert(
+            "pool-check",
+            CliCommand::new(&API_METHOD_POOL_CHECK).arg_param(&["id"]),
+        )
+        .insert(
+            "export-index",
+            CliCommand::new(&API_METHOD_EXPORT_INDEX).arg_param(&["id"]),
+        )
+        .insert(
+            "move-pool",
+            CliCommand::new(&API_METHOD_MOVE_POOL).arg_param(&["id", "base-dir"]),
         );
 
     cmd_def.into()
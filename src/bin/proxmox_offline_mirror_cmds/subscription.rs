@@ -2,15 +2,18 @@ use anyhow::{Error, bail, format_err};
 
 use serde::Serialize;
 use serde_json::Value;
-use std::convert::TryFrom;
+use std::{convert::TryFrom, path::Path};
 
 use proxmox_offline_mirror::{
-    config::{SubscriptionKey, SubscriptionKeyUpdater},
+    config::{
+        MediaConfig, SubscriptionKey, SubscriptionKeyUpdater, export_subscription_keys,
+        import_subscription_keys_from_file,
+    },
     subscription::{extract_mirror_key, refresh_mirror_key, refresh_offline_keys},
-    types::PROXMOX_SUBSCRIPTION_KEY_SCHEMA,
+    types::{MEDIA_ID_SCHEMA, PROXMOX_SUBSCRIPTION_KEY_SCHEMA},
 };
 use proxmox_subscription::{ProductType, SubscriptionStatus, files::DEFAULT_SIGNING_KEY};
-use proxmox_sys::fs::file_get_contents;
+use proxmox_sys::fs::{CreateOptions, file_get_contents, replace_file};
 use proxmox_time::epoch_to_rfc3339_utc;
 
 use proxmox_router::cli::{
@@ -20,6 +23,7 @@ use proxmox_router::cli::{
 use proxmox_schema::{ApiType, ArraySchema, ReturnType, api, param_bail};
 
 use super::get_config_path;
+use super::medium::get_subscription_keys;
 
 #[api]
 #[derive(Default, Serialize)]
@@ -226,7 +230,7 @@ async fn add_mirror_key(config: Option<String>, key: String, _param: Value) -> R
         info: None,
     };
 
-    if data.product() != ProductType::Pom {
+    if data.product()? != ProductType::Pom {
         param_bail!(
             "key",
             format_err!(
@@ -297,7 +301,7 @@ async fn add_key(
         );
     }
 
-    if data.product() == ProductType::Pom {
+    if data.product()? == ProductType::Pom {
         param_bail!(
             "key",
             format_err!("Proxmox Offline Mirror keys must be added with 'add-mirror-key' command.")
@@ -313,7 +317,9 @@ async fn add_key(
             mirror_key,
             vec![data.clone()],
             public_key()?,
-        )?;
+            None,
+        )?
+        .into_result()?;
 
         if let Some(info) = refreshed.pop() {
             if info.key.as_ref() == Some(&data.key) {
@@ -439,7 +445,7 @@ pub async fn refresh_keys(config: Option<String>, key: Option<String>) -> Result
 
     let mut keys: Vec<SubscriptionKey> = config.convert_to_typed_array("subscription")?;
     for key in &mut keys {
-        if key.product() == ProductType::Pom {
+        if key.product()? == ProductType::Pom {
             match refresh_mirror_key(key.clone()) {
                 Ok(info) => {
                     eprintln!(
@@ -461,16 +467,24 @@ pub async fn refresh_keys(config: Option<String>, key: Option<String>) -> Result
 
     let mirror_key = extract_mirror_key(&keys)?;
 
+    let on_key_refreshed: Box<dyn Fn(&str, &SubscriptionStatus)> =
+        Box::new(|key, status| eprintln!("Checked subscription key {key} - status: {status}"));
+
     let refreshed = if let Some(key) = key {
         match keys.iter().find(|k| k.key == key) {
-            Some(key) => refresh_offline_keys(mirror_key, vec![key.to_owned()], public_key()?)?,
+            Some(key) => refresh_offline_keys(
+                mirror_key,
+                vec![key.to_owned()],
+                public_key()?,
+                Some(on_key_refreshed),
+            )?,
             None => bail!("Subscription key '{key}' not configured."),
         }
     } else {
-        refresh_offline_keys(mirror_key, keys, public_key()?)?
+        refresh_offline_keys(mirror_key, keys, public_key()?, Some(on_key_refreshed))?
     };
 
-    for info in refreshed {
+    for info in refreshed.successful {
         match info.clone().key {
             Some(key) => {
                 eprintln!(
@@ -490,6 +504,16 @@ pub async fn refresh_keys(config: Option<String>, key: Option<String>) -> Result
 
     proxmox_offline_mirror::config::save_config(&config_file, &config)?;
 
+    if !refreshed.failed.is_empty() {
+        for (key, err) in &refreshed.failed {
+            eprintln!("Failed to refresh subscription key {key} - {err}");
+        }
+        bail!(
+            "Failed to refresh {} subscription key(s) - see above.",
+            refreshed.failed.len()
+        );
+    }
+
     Ok(())
 }
 
@@ -532,6 +556,151 @@ async fn remove_key(config: Option<String>, key: String, _param: Value) -> Resul
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            "medium-id": {
+                schema: MEDIA_ID_SCHEMA,
+            },
+            output: {
+                type: String,
+                optional: true,
+                description: "Write the keys to this file instead of printing them.",
+            },
+        }
+    },
+ )]
+/// Export the subscription info synced to a medium, for importing it via
+/// 'proxmox-offline-mirror-helper setup --import-keys'.
+async fn export_for_medium(
+    config: Option<String>,
+    medium_id: String,
+    output: Option<String>,
+    _param: Value,
+) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    // all keys are synced to every medium, but make sure the medium exists
+    let _medium: MediaConfig = section_config.lookup("medium", &medium_id)?;
+
+    let subscription_infos = get_subscription_keys(&section_config)?;
+    let data = serde_json::to_string_pretty(&subscription_infos)?;
+
+    match output {
+        Some(output) => {
+            replace_file(&output, data.as_bytes(), CreateOptions::default(), true)?;
+            eprintln!("Wrote {} keys to {output:?}", subscription_infos.len());
+        }
+        None => println!("{data}"),
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            file: {
+                type: String,
+                description: "JSON file containing an array of objects with 'key', 'serverid' and optional 'description'.",
+            },
+        }
+    },
+ )]
+/// Import subscription keys from a file, skipping keys that are already configured.
+async fn import_keys(config: Option<String>, file: String, _param: Value) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let keys = import_subscription_keys_from_file(Path::new(&file))?;
+
+    let _lock = proxmox_offline_mirror::config::lock_config(&config)?;
+
+    let (mut section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+
+    let mut imported = 0;
+    for key in keys {
+        if section_config.sections.contains_key(&key.key) {
+            println!("Skipping '{}' - already configured.", key.key);
+            continue;
+        }
+        if key.product()? == ProductType::Pom {
+            println!(
+                "Skipping '{}' - Proxmox Offline Mirror keys must be added with 'add-mirror-key' command.",
+                key.key
+            );
+            continue;
+        }
+
+        section_config.set_data(&key.key, "subscription", &key)?;
+        imported += 1;
+    }
+
+    proxmox_offline_mirror::config::save_config(&config, &section_config)?;
+
+    println!("Imported {imported} subscription key(s).");
+    if imported > 0 {
+        println!("Run 'proxmox-offline-mirror key refresh' to retrieve their subscription info.");
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            config: {
+                type: String,
+                optional: true,
+                description: "Path to mirroring config file.",
+            },
+            output: {
+                type: String,
+                optional: true,
+                description: "Write the keys to this file instead of printing them.",
+            },
+        }
+    },
+ )]
+/// Export subscription keys (without the Proxmox Offline Mirror key) for importing them via
+/// 'key import'.
+async fn export_keys(
+    config: Option<String>,
+    output: Option<String>,
+    _param: Value,
+) -> Result<(), Error> {
+    let config = config.unwrap_or_else(get_config_path);
+
+    let (section_config, _digest) = proxmox_offline_mirror::config::config(&config)?;
+    let keys: Vec<SubscriptionKey> = section_config
+        .convert_to_typed_array::<SubscriptionKey>("subscription")?
+        .into_iter()
+        .filter(|key| !matches!(key.product(), Ok(ProductType::Pom)))
+        .collect();
+
+    let data = export_subscription_keys(&keys)?;
+
+    match output {
+        Some(output) => {
+            replace_file(&output, data.as_bytes(), CreateOptions::default(), true)?;
+            eprintln!("Wrote {} keys to {output:?}", keys.len());
+        }
+        None => println!("{data}"),
+    }
+
+    Ok(())
+}
+
 pub fn key_commands() -> CommandLineInterface {
     CliCommandMap::new()
         .insert(
@@ -556,5 +725,14 @@ pub fn key_commands() -> CommandLineInterface {
             CliCommand::new(&API_METHOD_REMOVE_KEY).arg_param(&["key"]),
         )
         .insert("list", CliCommand::new(&API_METHOD_LIST_KEYS))
+        .insert(
+            "import",
+            CliCommand::new(&API_METHOD_IMPORT_KEYS).arg_param(&["file"]),
+        )
+        .insert("export", CliCommand::new(&API_METHOD_EXPORT_KEYS))
+        .insert(
+            "export-for-medium",
+            CliCommand::new(&API_METHOD_EXPORT_FOR_MEDIUM).arg_param(&["medium-id"]),
+        )
         .into()
 }
@@ -1,9 +1,10 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::LazyLock;
 
-use anyhow::{Error, bail};
-use proxmox_subscription::{SubscriptionInfo, sign::ServerBlob};
-use serde::{Deserialize, Serialize};
+use anyhow::{Error, bail, format_err};
+use proxmox_subscription::{SubscriptionInfo, SubscriptionStatus, sign::ServerBlob};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use proxmox_schema::{ApiStringFormat, ApiType, Updater, api};
 use proxmox_section_config::{SectionConfig, SectionConfigData, SectionConfigPlugin};
@@ -11,7 +12,9 @@ use proxmox_subscription::ProductType;
 use proxmox_sys::fs::{CreateOptions, replace_file};
 
 use crate::types::{
-    MEDIA_ID_SCHEMA, MIRROR_ID_SCHEMA, PROXMOX_SERVER_ID_SCHEMA, PROXMOX_SUBSCRIPTION_KEY_SCHEMA,
+    ABSOLUTE_PATH_FORMAT, MEDIA_ID_SCHEMA, MIRROR_ID_SCHEMA, PACKAGE_PRIORITY_FORMAT,
+    PROXMOX_SERVER_ID_SCHEMA, PROXMOX_SUBSCRIPTION_KEY_SCHEMA, TEMPLATE_VARS_FORMAT,
+    parse_product_type,
 };
 
 /// Skip Configuration
@@ -33,6 +36,23 @@ use crate::types::{
                 description: "Package name",
             },
         },
+        "skip-priorities": {
+            type: Array,
+            optional: true,
+            items: {
+                type: String,
+                description: "Package priority",
+                format: &PACKAGE_PRIORITY_FORMAT,
+            },
+        },
+        "skip-architectures": {
+            type: Array,
+            optional: true,
+            items: {
+                type: String,
+                description: "Architecture name",
+            },
+        },
     },
 )]
 #[derive(Default, Serialize, Deserialize, Updater, Clone, Debug)]
@@ -44,6 +64,12 @@ pub struct SkipConfig {
     /// Packages which should be skipped, supports globbing
     #[serde(skip_serializing_if = "Option::is_none")]
     pub skip_packages: Option<Vec<String>>,
+    /// Package priorities which should be skipped, e.g. `optional` or `extra`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_priorities: Option<Vec<String>>,
+    /// Architectures of binary packages which should be skipped, e.g. `all` or `i386`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_architectures: Option<Vec<String>>,
 }
 
 #[api(
@@ -61,6 +87,11 @@ pub struct SkipConfig {
             type: u64,
             optional: true,
         },
+        "allow-rsa-2048": {
+            type: bool,
+            default: false,
+            optional: true,
+        },
     },
 )]
 #[derive(Default, Serialize, Deserialize, Updater, Clone, Debug)]
@@ -71,11 +102,57 @@ pub struct WeakCryptoConfig {
     #[serde(default)]
     pub allow_sha1: bool,
     /// Whether to lower the key size cutoff for DSA-based signatures
+    ///
+    /// Only values of 1024 or less have an effect, DSA keys should be at least 2048 bits.
     #[serde(default)]
     pub min_dsa_key_size: Option<u64>,
     /// Whether to lower the key size cutoff for RSA-based signatures
+    ///
+    /// Only values of 1024 or less have an effect, RSA keys should be at least 3072 bits.
     #[serde(default)]
     pub min_rsa_key_size: Option<u64>,
+    /// Whether to accept 2048-bit RSA keys even if the policy rejects them
+    #[serde(default)]
+    pub allow_rsa_2048: bool,
+}
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Type of signature used to sign a repository's release file.
+pub enum SigType {
+    /// OpenPGP signature (`InRelease` or `Release` + `Release.gpg`).
+    #[default]
+    Gpg,
+    /// minisign signature (`Release` + `Release.minisig`).
+    Minisign,
+    /// signify signature (`Release` + `Release.sig`).
+    Signify,
+}
+
+impl SigType {
+    /// Name of the detached signature file next to `Release`.
+    pub fn detached_file_name(&self) -> &'static str {
+        match self {
+            SigType::Gpg => "Release.gpg",
+            SigType::Minisign => "Release.minisig",
+            SigType::Signify => "Release.sig",
+        }
+    }
+}
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Which checksums to use for storing and looking up files in a pool.
+pub enum HashPreference {
+    /// Use SHA-512 if available, SHA-256 otherwise.
+    #[default]
+    Sha512First,
+    /// Only use SHA-256, skipping SHA-512 lookups.
+    Sha256Only,
+    /// Only use SHA-512, refusing files lacking a SHA-512 checksum.
+    Sha512Only,
 }
 
 #[api(
@@ -96,8 +173,60 @@ pub struct WeakCryptoConfig {
         "base-dir": {
             type: String,
         },
+        "pool-dir": {
+            type: String,
+            optional: true,
+            format: &ABSOLUTE_PATH_FORMAT,
+        },
         "key-path": {
             type: String,
+            optional: true,
+        },
+        "extra-key-paths": {
+            type: Array,
+            optional: true,
+            items: {
+                type: String,
+                description: "Path to additional public key file.",
+            },
+        },
+        "sig-type": {
+            type: SigType,
+            optional: true,
+        },
+        "extra-components": {
+            type: Array,
+            optional: true,
+            items: {
+                type: String,
+                description: "Repository component.",
+            },
+        },
+        "exclude-components": {
+            type: Array,
+            optional: true,
+            items: {
+                type: String,
+                description: "Repository component.",
+            },
+        },
+        "template-vars": {
+            type: String,
+            optional: true,
+            format: &TEMPLATE_VARS_FORMAT,
+        },
+        "hash-preference": {
+            type: HashPreference,
+            optional: true,
+        },
+        "gc-io-limit-ops-per-sec": {
+            type: u32,
+            minimum: 1,
+            optional: true,
+        },
+        "snapshot-name-template": {
+            type: String,
+            optional: true,
         },
         verify: {
             type: bool,
@@ -105,11 +234,20 @@ pub struct WeakCryptoConfig {
         sync: {
             type: bool,
         },
+        "http-auth": {
+            type: String,
+            optional: true,
+        },
         "ignore-errors": {
             type: bool,
             optional: true,
             default: false,
         },
+        repair: {
+            type: bool,
+            optional: true,
+            default: false,
+        },
         "skip": {
             type: SkipConfig,
         },
@@ -133,8 +271,44 @@ pub struct MirrorConfig {
     pub architectures: Vec<String>,
     /// Path to directory containg mirrored repository pool. Can be shared by multiple mirrors.
     pub base_dir: String,
+    /// Absolute path to the pool directory, defaults to `<base_dir>/.pool`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_dir: Option<String>,
     /// Path to public key file for verifying repository integrity.
+    #[serde(default)]
     pub key_path: String,
+    /// Additional public key files, tried in order if `key_path` fails to verify the repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extra_key_paths: Option<Vec<String>>,
+    /// Type of repository signature, defaults to GPG.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sig_type: Option<SigType>,
+    /// Components to mirror in addition to those listed in `repository`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extra_components: Option<Vec<String>>,
+    /// Components listed in `repository` that should not be mirrored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_components: Option<Vec<String>>,
+    /// Variables referenced as `${NAME}` in `repository`, as comma-separated `NAME=VALUE` list.
+    #[updater(skip)]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_template_vars",
+        deserialize_with = "deserialize_template_vars"
+    )]
+    pub template_vars: Option<HashMap<String, String>>,
+    /// Which checksums to use for the pool, defaults to SHA-512 with SHA-256 fallback.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash_preference: Option<HashPreference>,
+    /// Maximum number of file removals per second during GC, to limit its impact on concurrent
+    /// operations. Unlimited by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gc_io_limit_ops_per_sec: Option<u32>,
+    /// Template for names of new snapshots, see [crate::mirror::snapshot_name]. Defaults to the
+    /// current time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_name_template: Option<String>,
     /// Whether to verify existing files or assume they are valid (IO-intensive).
     pub verify: bool,
     /// Whether to write new files using FSYNC.
@@ -142,9 +316,16 @@ pub struct MirrorConfig {
     /// Use subscription key to access (required for Proxmox Enterprise repositories).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub use_subscription: Option<ProductType>,
+    /// Base64-encoded `user:password` for HTTP basic authentication, ignored if
+    /// `use_subscription` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_auth: Option<String>,
     /// Whether to downgrade download errors to warnings
     #[serde(default)]
     pub ignore_errors: bool,
+    /// Whether to re-download existing pool files failing verification instead of aborting
+    #[serde(default)]
+    pub repair: bool,
     /// Skip package files using these criteria
     #[serde(default, flatten)]
     pub skip: SkipConfig,
@@ -153,6 +334,105 @@ pub struct MirrorConfig {
     pub weak_crypto: Option<String>,
 }
 
+impl MirrorConfig {
+    /// Check the values which are not covered by the schema, but required for creating snapshots.
+    pub fn validate(&self) -> Result<(), Error> {
+        let repository = self
+            .render_repository()
+            .map_err(|err| format_err!("Invalid 'repository' - {err}"))?;
+        crate::convert_repo_line(repository)
+            .map_err(|err| format_err!("Invalid 'repository' - {err}"))?;
+
+        if self.architectures.is_empty() {
+            bail!("Invalid 'architectures' - at least one architecture is required.");
+        }
+
+        if self.key_path.trim().is_empty() {
+            bail!("Invalid 'key-path' - must not be empty.");
+        }
+
+        if self.snapshot_name_template.is_some() {
+            crate::mirror::snapshot_name(self, proxmox_time::epoch_i64())
+                .map_err(|err| format_err!("Invalid 'snapshot-name-template' - {err}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns `repository` with `${NAME}` and `$NAME` references to `template-vars` expanded.
+    ///
+    /// Fails if a referenced variable is not defined.
+    pub fn render_repository(&self) -> Result<String, Error> {
+        expand_string(&self.repository, |name| {
+            self.template_vars
+                .as_ref()
+                .and_then(|vars| vars.get(name))
+                .cloned()
+                .ok_or_else(|| format_err!("template variable '{name}' is not defined"))
+        })
+    }
+}
+
+/// Parse a comma-separated `NAME=VALUE` list of template variables.
+pub fn parse_template_vars(value: &str) -> Result<HashMap<String, String>, Error> {
+    value
+        .split(',')
+        .map(|var| match var.split_once('=') {
+            Some((name, value))
+                if !name.is_empty()
+                    && !name.starts_with(|c: char| c.is_ascii_digit())
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    && !value.is_empty() =>
+            {
+                Ok((name.to_string(), value.to_string()))
+            }
+            _ => bail!("invalid template variable '{var}' - expected 'NAME=VALUE'"),
+        })
+        .collect()
+}
+
+/// Format template variables as comma-separated `NAME=VALUE` list, sorted by name.
+pub fn format_template_vars(vars: &HashMap<String, String>) -> String {
+    vars.iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn serialize_template_vars<S: Serializer>(
+    vars: &Option<HashMap<String, String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match vars {
+        Some(vars) => serializer.serialize_str(&format_template_vars(vars)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_template_vars<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<HashMap<String, String>>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_template_vars(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Strategy for persisting newly written files on a medium.
+pub enum SyncStrategy {
+    /// FSYNC every file after writing it.
+    #[default]
+    PerFile,
+    /// FSYNC all newly written files and links once after syncing a mirror.
+    Batch,
+    /// Don't FSYNC at all.
+    None,
+}
+
 #[api(
     properties: {
         id: {
@@ -167,12 +447,26 @@ pub struct MirrorConfig {
         sync: {
             type: bool,
         },
+        "sync-strategy": {
+            type: SyncStrategy,
+            optional: true,
+        },
         mirrors: {
             type: Array,
             items: {
                 schema: MIRROR_ID_SCHEMA,
             },
         },
+        "max-mirrors": {
+            type: usize,
+            minimum: 1,
+            optional: true,
+        },
+        "max-snapshots-per-mirror": {
+            type: usize,
+            minimum: 1,
+            optional: true,
+        },
     }
 )]
 #[derive(Debug, Serialize, Deserialize, Updater)]
@@ -190,6 +484,41 @@ pub struct MediaConfig {
     pub verify: bool,
     /// Whether to write new files using FSYNC.
     pub sync: bool,
+    /// When to FSYNC new files, overrides `sync` if set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_strategy: Option<SyncStrategy>,
+    /// Maximum number of mirrors that can be synced to this medium.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_mirrors: Option<usize>,
+    /// Maximum number of snapshots kept per mirror on this medium, older snapshots are pruned
+    /// from the medium when syncing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_snapshots_per_mirror: Option<usize>,
+}
+
+impl MediaConfig {
+    /// Check the values which are not covered by the schema.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(max_mirrors) = self.max_mirrors {
+            if self.mirrors.len() > max_mirrors {
+                bail!(
+                    "Invalid 'mirrors' - {} mirrors configured, but 'max-mirrors' is {max_mirrors}.",
+                    self.mirrors.len()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Effective [SyncStrategy], falling back to `sync` if no strategy is configured.
+    pub fn sync_strategy(&self) -> SyncStrategy {
+        match self.sync_strategy {
+            Some(strategy) => strategy,
+            None if self.sync => SyncStrategy::PerFile,
+            None => SyncStrategy::None,
+        }
+    }
 }
 
 #[api(
@@ -239,14 +568,12 @@ impl From<SubscriptionKey> for ServerBlob {
 }
 
 impl SubscriptionKey {
-    pub fn product(&self) -> ProductType {
-        match &self.key[..3] {
-            "pve" => ProductType::Pve,
-            "pmg" => ProductType::Pmg,
-            "pbs" => ProductType::Pbs,
-            "pom" => ProductType::Pom,
-            _ => unimplemented!(),
-        }
+    /// Product type of the key, derived from its prefix.
+    pub fn product(&self) -> Result<ProductType, Error> {
+        self.key
+            .get(..3)
+            .and_then(|prefix| parse_product_type(prefix).ok())
+            .ok_or_else(|| format_err!("Unknown product type of subscription key '{}'", self.key))
     }
 
     pub fn info(&self) -> Result<Option<SubscriptionInfo>, Error> {
@@ -259,6 +586,114 @@ impl SubscriptionKey {
             None => Ok(None),
         }
     }
+
+    /// Whether the last known subscription state is active.
+    pub fn is_active(&self) -> bool {
+        matches!(self.info(), Ok(Some(info)) if info.status == SubscriptionStatus::Active)
+    }
+
+    /// Epoch of the next due date according to the last known subscription state.
+    pub fn expires_at(&self) -> Option<i64> {
+        let info = self.info().ok()??;
+        let due_date = info.nextduedate?;
+        proxmox_time::parse_rfc3339(&format!("{due_date}T00:00:00Z")).ok()
+    }
+
+    /// Number of (full) days until the next due date, negative if already passed.
+    pub fn days_until_expiry(&self) -> Option<i64> {
+        self.expires_at()
+            .map(|expires_at| (expires_at - proxmox_time::epoch_i64()).div_euclid(86400))
+    }
+
+    /// Whether the subscription state is missing or was last checked more than `max_age_hours`
+    /// ago.
+    pub fn needs_refresh(&self, max_age_hours: u64) -> bool {
+        match self.info() {
+            Ok(Some(SubscriptionInfo {
+                checktime: Some(checktime),
+                ..
+            })) => proxmox_time::epoch_i64() - checktime > (max_age_hours * 3600) as i64,
+            _ => true,
+        }
+    }
+}
+
+// Entry of a subscription key export, using the field names of Proxmox products.
+#[derive(Serialize, Deserialize)]
+struct SubscriptionKeyExport {
+    key: String,
+    serverid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+/// Parse a JSON array of `{"key": .., "serverid": .., "description": ..}` objects, e.g. collected
+/// from the nodes of a cluster, into subscription keys without subscription info.
+pub fn import_subscription_keys_from_file(path: &Path) -> Result<Vec<SubscriptionKey>, Error> {
+    let raw = proxmox_sys::fs::file_get_contents(path)?;
+    let entries: Vec<SubscriptionKeyExport> = serde_json::from_slice(&raw)
+        .map_err(|err| format_err!("Failed to parse subscription keys from {path:?} - {err}"))?;
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            PROXMOX_SUBSCRIPTION_KEY_SCHEMA
+                .unwrap_string_schema()
+                .check_constraints(&entry.key)
+                .map_err(|err| format_err!("Invalid subscription key '{}' - {err}", entry.key))?;
+            PROXMOX_SERVER_ID_SCHEMA
+                .unwrap_string_schema()
+                .check_constraints(&entry.serverid)
+                .map_err(|err| format_err!("Invalid server ID for key '{}' - {err}", entry.key))?;
+
+            Ok(SubscriptionKey {
+                key: entry.key,
+                server_id: entry.serverid,
+                description: entry.description,
+                info: None,
+            })
+        })
+        .collect()
+}
+
+/// Serialize `keys` as JSON array in the format read by [import_subscription_keys_from_file].
+pub fn export_subscription_keys(keys: &[SubscriptionKey]) -> Result<String, Error> {
+    let entries: Vec<SubscriptionKeyExport> = keys
+        .iter()
+        .map(|key| SubscriptionKeyExport {
+            key: key.key.clone(),
+            serverid: key.server_id.clone(),
+            description: key.description.clone(),
+        })
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+/// Version of the config file format written by this version of the tool.
+pub const CONFIG_VERSION: u32 = 1;
+
+// Section ID and type of the section storing the config file format version.
+const GLOBAL_SECTION: &str = "global";
+
+#[api(
+    properties: {
+        id: {
+            schema: MIRROR_ID_SCHEMA,
+        },
+        version: {
+            type: Integer,
+        },
+    },
+)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Global section of the config file.
+struct GlobalConfig {
+    /// Always `global`.
+    id: String,
+    /// Version of the config file format.
+    version: u32,
 }
 
 pub static CONFIG: LazyLock<SectionConfig> = LazyLock::new(init);
@@ -287,6 +722,13 @@ fn init() -> SectionConfig {
     );
     config.register_plugin(key_plugin);
 
+    let global_plugin = SectionConfigPlugin::new(
+        GLOBAL_SECTION.to_string(),
+        Some(String::from("id")),
+        const { GlobalConfig::API_SCHEMA.unwrap_any_object_schema() },
+    );
+    config.register_plugin(global_plugin);
+
     config
 }
 
@@ -315,17 +757,455 @@ pub fn lock_config(path: &str) -> Result<ConfigLockGuard, Error> {
     Ok(ConfigLockGuard(file))
 }
 
+/// Expand variable references in a single config value, using `lookup` to resolve names.
+///
+/// Both `${VAR}` and `$VAR` are supported, a `$` not followed by a variable name is kept as is.
+fn expand_string(
+    value: &str,
+    lookup: impl Fn(&str) -> Result<String, Error>,
+) -> Result<String, Error> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let is_valid_name = |name: &str| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(is_name_char)
+    };
+
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        let (name, remaining) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| format_err!("unterminated variable reference '${{{braced}'"))?;
+            let name = &braced[..end];
+            if !is_valid_name(name) {
+                bail!("invalid variable name '{name}'");
+            }
+            (name, &braced[end + 1..])
+        } else {
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            let name = &rest[..end];
+            if !is_valid_name(name) {
+                expanded.push('$');
+                continue;
+            }
+            (name, &rest[end..])
+        };
+
+        expanded.push_str(&lookup(name)?);
+        rest = remaining;
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Expand `${VAR}` and `$VAR` references to environment variables in the `key-path`, `base-dir`
+/// and `repository` values of a mirror config.
+///
+/// References to `template-vars` in `repository` are kept, those are expanded separately by
+/// [MirrorConfig::render_repository]. Fails if a referenced variable is not defined.
+pub fn expand_env_vars(config: &mut MirrorConfig) -> Result<(), Error> {
+    expand_vars_with(config, |name| std::env::var(name).ok())
+}
+
+// Helper for [expand_env_vars] with variable values provided by `env`.
+fn expand_vars_with(
+    config: &mut MirrorConfig,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<(), Error> {
+    let template_vars = config.template_vars.clone().unwrap_or_default();
+
+    for (field, value) in [
+        ("key-path", &mut config.key_path),
+        ("base-dir", &mut config.base_dir),
+        ("repository", &mut config.repository),
+    ] {
+        let lookup = |name: &str| {
+            if field == "repository" && template_vars.contains_key(name) {
+                return Ok(format!("${{{name}}}"));
+            }
+            env(name).ok_or_else(|| format_err!("environment variable '{name}' is not defined"))
+        };
+        *value = expand_string(value, lookup).map_err(|err| {
+            format_err!(
+                "Failed to expand '{field}' of mirror '{}' - {err}",
+                config.id
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Look up a mirror config entry and expand references to environment variables in it.
+///
+/// Only fails for an undefined variable if it is referenced by this mirror, see
+/// [expand_env_vars].
+pub fn lookup_mirror(data: &SectionConfigData, id: &str) -> Result<MirrorConfig, Error> {
+    let mut mirror: MirrorConfig = data.lookup("mirror", id)?;
+    expand_env_vars(&mut mirror)?;
+    Ok(mirror)
+}
+
+/// Comment blocks of a config file, together with the type and ID of the section they precede.
+type ConfigComments = Vec<(Option<(String, String)>, Vec<String>)>;
+
+/// Parse a section header line (`<type>: <id>`) into its type and ID.
+fn section_header(line: &str) -> Option<(String, String)> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (section_type, id) = line.split_once(':')?;
+    let id = id.trim();
+    if section_type.is_empty() || section_type.contains(char::is_whitespace) || id.is_empty() {
+        return None;
+    }
+    Some((section_type.to_string(), id.to_string()))
+}
+
+/// Remove comment lines (starting with `#` after optional whitespace) from a config file.
+///
+/// Returns the remaining content together with the removed comments, grouped into blocks anchored
+/// to the header of the following section. Comments after the last section header and its
+/// properties are not anchored.
+fn strip_comments(content: &str) -> (String, ConfigComments) {
+    let mut stripped = String::with_capacity(content.len());
+    let mut comments = Vec::new();
+    let mut block = Vec::new();
+
+    for line in content.lines() {
+        if line.trim_start().starts_with('#') {
+            block.push(line.to_string());
+            continue;
+        }
+        if let Some(header) = section_header(line) {
+            if !block.is_empty() {
+                comments.push((Some(header), std::mem::take(&mut block)));
+            }
+        }
+        stripped.push_str(line);
+        stripped.push('\n');
+    }
+
+    if !block.is_empty() {
+        comments.push((None, block));
+    }
+
+    (stripped, comments)
+}
+
+/// Re-insert comments previously removed by [strip_comments] in front of their section.
+///
+/// Comments which are not anchored or whose section no longer exists are appended.
+fn merge_comments(raw: &str, comments: &ConfigComments) -> String {
+    let mut merged = String::with_capacity(raw.len());
+    let mut emitted = vec![false; comments.len()];
+
+    for line in raw.lines() {
+        if let Some(header) = section_header(line) {
+            for (index, (anchor, block)) in comments.iter().enumerate() {
+                if anchor.as_ref() == Some(&header) {
+                    for comment in block {
+                        merged.push_str(comment);
+                        merged.push('\n');
+                    }
+                    emitted[index] = true;
+                }
+            }
+        }
+        merged.push_str(line);
+        merged.push('\n');
+    }
+
+    for (index, (_, block)) in comments.iter().enumerate() {
+        if !emitted[index] {
+            for comment in block {
+                merged.push_str(comment);
+                merged.push('\n');
+            }
+        }
+    }
+
+    merged
+}
+
 /// Read config
+///
+/// Fails if the config file was written using a newer, unknown format version. References to
+/// environment variables in mirror configs are kept as they are, use [lookup_mirror] to get a
+/// mirror config with those expanded.
 pub fn config(path: &str) -> Result<(SectionConfigData, [u8; 32]), Error> {
+    let (data, digest, version) = config_with_version(path)?;
+    if version > CONFIG_VERSION {
+        bail!(
+            "Config file {path:?} uses format version {version}, but only versions up to {CONFIG_VERSION} are supported - please upgrade."
+        );
+    }
+    Ok((data, digest))
+}
+
+/// Read config and its format version, without checking whether the version is supported.
+///
+/// Config files written before the format was versioned are reported as version 0. The section
+/// containing the version is not part of the returned data. Comment lines are ignored.
+pub fn config_with_version(path: &str) -> Result<(SectionConfigData, [u8; 32], u32), Error> {
     let content = proxmox_sys::fs::file_read_optional_string(path)?.unwrap_or_default();
 
     let digest = openssl::sha::sha256(content.as_bytes());
-    let data = CONFIG.parse(path, &content)?;
-    Ok((data, digest))
+    let (content, _comments) = strip_comments(&content);
+    let mut data = CONFIG.parse(path, &content)?;
+
+    let version = match data.sections.get(GLOBAL_SECTION) {
+        Some((section_type, _)) if section_type == GLOBAL_SECTION => {
+            let global: GlobalConfig = data.lookup(GLOBAL_SECTION, GLOBAL_SECTION)?;
+            data.sections.remove(GLOBAL_SECTION);
+            data.order.retain(|id| id != GLOBAL_SECTION);
+            global.version
+        }
+        _ => 0,
+    };
+
+    Ok((data, digest, version))
 }
 
-/// Write config (and verify data matches schema!)
+/// Write config (and verify data matches schema!), including the current format version.
+///
+/// Mirror entries are additionally checked using [MirrorConfig::validate], medium entries using
+/// [MediaConfig::validate]. References to environment variables are written as they are, mirror
+/// entries referencing undefined variables are only validated once those are defined. Comment
+/// lines of the existing config file are preserved in front of the section they precede.
 pub fn save_config(path: &str, data: &SectionConfigData) -> Result<(), Error> {
-    let raw = CONFIG.write(path, data)?;
+    if data.sections.contains_key(GLOBAL_SECTION) {
+        bail!("Config entry ID '{GLOBAL_SECTION}' is reserved.");
+    }
+
+    for (id, (section_type, _)) in data.sections.iter() {
+        if section_type == "mirror" {
+            let mut mirror: MirrorConfig = data.lookup("mirror", id)?;
+            if expand_env_vars(&mut mirror).is_ok() {
+                mirror
+                    .validate()
+                    .map_err(|err| format_err!("Mirror '{id}': {err}"))?;
+            }
+        } else if section_type == "medium" {
+            let medium: MediaConfig = data.lookup("medium", id)?;
+            medium
+                .validate()
+                .map_err(|err| format_err!("Medium '{id}': {err}"))?;
+        }
+    }
+
+    let mut data = data.clone();
+    data.set_data(
+        GLOBAL_SECTION,
+        GLOBAL_SECTION,
+        GlobalConfig {
+            id: GLOBAL_SECTION.to_string(),
+            version: CONFIG_VERSION,
+        },
+    )?;
+    data.order.retain(|id| id != GLOBAL_SECTION);
+    data.order.insert(0, GLOBAL_SECTION.to_string());
+
+    let raw = CONFIG.write(path, &data)?;
+
+    let existing = proxmox_sys::fs::file_read_optional_string(path)?.unwrap_or_default();
+    let (_, comments) = strip_comments(&existing);
+    let raw = if comments.is_empty() {
+        raw
+    } else {
+        merge_comments(&raw, &comments)
+    };
+
     replace_file(path, raw.as_bytes(), CreateOptions::default(), true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Helper to create a key with the given subscription state.
+    fn key_with_info(info: Option<SubscriptionInfo>) -> SubscriptionKey {
+        SubscriptionKey {
+            key: "pve4b-1234567890".to_string(),
+            server_id: "0123456789ABCDEF0123456789ABCDEF".to_string(),
+            description: None,
+            info: info.map(|info| proxmox_base64::encode(serde_json::to_vec(&info).unwrap())),
+        }
+    }
+
+    // Helper to create a mirror config with the given values referencing environment variables.
+    fn mirror_with(repository: &str, base_dir: &str, key_path: &str) -> MirrorConfig {
+        MirrorConfig {
+            id: "test".to_string(),
+            repository: repository.to_string(),
+            architectures: vec!["amd64".to_string()],
+            base_dir: base_dir.to_string(),
+            pool_dir: None,
+            key_path: key_path.to_string(),
+            extra_key_paths: None,
+            sig_type: None,
+            extra_components: None,
+            exclude_components: None,
+            template_vars: None,
+            hash_preference: None,
+            gc_io_limit_ops_per_sec: None,
+            snapshot_name_template: None,
+            verify: true,
+            sync: false,
+            use_subscription: None,
+            http_auth: None,
+            ignore_errors: false,
+            repair: false,
+            skip: SkipConfig::default(),
+            weak_crypto: None,
+        }
+    }
+
+    // Helper to format the date `days` from now as `YYYY-MM-DD`.
+    fn date_in_days(days: i64) -> String {
+        let epoch = proxmox_time::epoch_i64() + days * 86400;
+        proxmox_time::epoch_to_rfc3339_utc(epoch).unwrap()[..10].to_string()
+    }
+
+    #[test]
+    fn subscription_key_without_info() {
+        let key = key_with_info(None);
+        assert!(!key.is_active());
+        assert_eq!(key.expires_at(), None);
+        assert_eq!(key.days_until_expiry(), None);
+        assert!(key.needs_refresh(24));
+    }
+
+    #[test]
+    fn subscription_key_active() {
+        let key = key_with_info(Some(SubscriptionInfo {
+            status: SubscriptionStatus::Active,
+            checktime: Some(proxmox_time::epoch_i64()),
+            nextduedate: Some(date_in_days(10)),
+            ..Default::default()
+        }));
+        assert!(key.is_active());
+        assert!(key.expires_at().is_some());
+        // the due date is at midnight, so less than 10 full days remain
+        assert_eq!(key.days_until_expiry(), Some(9));
+        assert!(!key.needs_refresh(24));
+    }
+
+    #[test]
+    fn subscription_key_expired() {
+        let key = key_with_info(Some(SubscriptionInfo {
+            status: SubscriptionStatus::Invalid,
+            checktime: Some(proxmox_time::epoch_i64() - 48 * 3600),
+            nextduedate: Some(date_in_days(-3)),
+            ..Default::default()
+        }));
+        assert!(!key.is_active());
+        // partial days are rounded down
+        assert_eq!(key.days_until_expiry(), Some(-4));
+        assert!(key.needs_refresh(24));
+        assert!(!key.needs_refresh(72));
+    }
+
+    #[test]
+    fn subscription_key_invalid_info() {
+        let mut key = key_with_info(None);
+        key.info = Some("not base64!".to_string());
+        assert!(!key.is_active());
+        assert_eq!(key.expires_at(), None);
+        assert!(key.needs_refresh(24));
+    }
+
+    // Helper to look up variables in a fixed list instead of the process environment.
+    fn test_env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn expand_env_vars_in_fields() {
+        let mut mirror = mirror_with(
+            "deb http://$OFFLINE_MIRROR_TEST_ROOT/debian bookworm main",
+            "${OFFLINE_MIRROR_TEST_ROOT}/debian",
+            "$OFFLINE_MIRROR_TEST_ROOT/keys/debian.gpg",
+        );
+        expand_vars_with(
+            &mut mirror,
+            test_env(&[("OFFLINE_MIRROR_TEST_ROOT", "/srv/mirror")]),
+        )
+        .unwrap();
+        assert_eq!(
+            mirror.repository,
+            "deb http:///srv/mirror/debian bookworm main"
+        );
+        assert_eq!(mirror.base_dir, "/srv/mirror/debian");
+        assert_eq!(mirror.key_path, "/srv/mirror/keys/debian.gpg");
+    }
+
+    #[test]
+    fn expand_env_vars_keeps_template_vars() {
+        let mut mirror = mirror_with(
+            "deb http://download.proxmox.com/debian/ceph-${ceph_release} $OFFLINE_MIRROR_TEST_RELEASE no-subscription",
+            "/srv/mirror/ceph",
+            "/etc/apt/trusted.gpg.d/proxmox-release-bookworm.gpg",
+        );
+        mirror.template_vars = Some(HashMap::from([(
+            "ceph_release".to_string(),
+            "reef".to_string(),
+        )]));
+        expand_vars_with(
+            &mut mirror,
+            test_env(&[("OFFLINE_MIRROR_TEST_RELEASE", "bookworm")]),
+        )
+        .unwrap();
+        assert_eq!(
+            mirror.repository,
+            "deb http://download.proxmox.com/debian/ceph-${ceph_release} bookworm no-subscription"
+        );
+        assert_eq!(
+            mirror.render_repository().unwrap(),
+            "deb http://download.proxmox.com/debian/ceph-reef bookworm no-subscription"
+        );
+    }
+
+    #[test]
+    fn expand_env_vars_undefined() {
+        let mut mirror = mirror_with(
+            "deb http://deb.debian.org/debian bookworm main",
+            "/srv/mirror/debian",
+            "${OFFLINE_MIRROR_TEST_UNDEFINED}/debian.gpg",
+        );
+        let err = expand_vars_with(&mut mirror, test_env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'key-path'"), "{err}");
+        assert!(err.contains("'OFFLINE_MIRROR_TEST_UNDEFINED'"), "{err}");
+    }
+
+    #[test]
+    fn comments_follow_their_section() {
+        let existing = "# all mirrors\nmirror: a\n\tkey-path /a\n\n# second mirror\n  # indented\nmirror: b\n\tkey-path /b\n# trailing\n";
+        let (stripped, comments) = strip_comments(existing);
+        assert_eq!(
+            stripped,
+            "mirror: a\n\tkey-path /a\n\nmirror: b\n\tkey-path /b\n"
+        );
+
+        // 'a' was removed, 'c' added in front of 'b'
+        let raw =
+            "global: global\n\tversion 1\n\nmirror: c\n\tkey-path /c\n\nmirror: b\n\tkey-path /b\n";
+        assert_eq!(
+            merge_comments(raw, &comments),
+            "global: global\n\tversion 1\n\nmirror: c\n\tkey-path /c\n\n# second mirror\n  # indented\nmirror: b\n\tkey-path /b\n# all mirrors\n# trailing\n"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Error, format_err};
+use serde::Serialize;
+
+use sequoia_openpgp::{cert::CertParser, parse::Parse, policy::StandardPolicy};
+
+use proxmox_apt_api_types::APTRepository;
+use proxmox_sys::fs::file_get_contents;
+use proxmox_time::epoch_i64;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Expiry information of an OpenPGP key.
+pub struct KeyExpiryInfo {
+    /// Fingerprint of the primary key
+    pub fingerprint: String,
+    /// Whether the key has already expired
+    pub is_expired: bool,
+    /// Expiry as epoch, if the key expires at all
+    pub expires_at: Option<i64>,
+}
+
+/// Reads the first certificate contained in `key_path` (binary or ASCII-armored) and returns
+/// information about its expiry.
+pub fn check_key_expiry(key_path: &Path) -> Result<KeyExpiryInfo, Error> {
+    let data = file_get_contents(key_path)?;
+    let cert = CertParser::from_bytes(&data)?
+        .next()
+        .ok_or_else(|| format_err!("No key found in {key_path:?}"))??;
+
+    let policy = StandardPolicy::new();
+    let valid = cert
+        .with_policy(&policy, None)
+        .map_err(|err| format_err!("Failed to check key {key_path:?} - {err}"))?;
+
+    let expires_at = match valid.primary_key().key_expiration_time() {
+        Some(time) => Some(time.duration_since(UNIX_EPOCH)?.as_secs() as i64),
+        None => None,
+    };
+
+    Ok(KeyExpiryInfo {
+        fingerprint: cert.fingerprint().to_hex(),
+        is_expired: expires_at.is_some_and(|expires_at| expires_at <= epoch_i64()),
+        expires_at,
+    })
+}
+
+/// Returns the path of the keyring usually used for verifying `repository`, for Debian and
+/// Proxmox repositories of known releases.
+///
+/// Only the path is derived, it is not checked whether the keyring actually exists.
+pub fn detect_repo_key(repository: &APTRepository) -> Option<PathBuf> {
+    let uri = repository.uris.first()?;
+    let suite = repository.suites.first()?;
+
+    let (release, variant) = match suite.split_once('-') {
+        Some((release, variant)) => (release, Some(variant)),
+        None => (suite.as_str(), None),
+    };
+    if !matches!(release, "trixie" | "bookworm" | "bullseye") {
+        return None;
+    }
+
+    let host = uri.split("://").nth(1)?.split('/').next()?;
+
+    let path = if host == "debian.org" || host.ends_with(".debian.org") {
+        match (release, variant) {
+            (_, Some("security")) => {
+                format!("/usr/share/keyrings/debian-archive-{release}-security-automatic.pgp")
+            }
+            ("bullseye", _) | (_, Some("updates" | "backports")) => {
+                format!("/usr/share/keyrings/debian-archive-{release}-automatic.pgp")
+            }
+            _ => format!("/usr/share/keyrings/debian-archive-{release}-stable.pgp"),
+        }
+    } else if host == "proxmox.com" || host.ends_with(".proxmox.com") {
+        match release {
+            "bookworm" | "bullseye" => {
+                "/etc/apt/trusted.gpg.d/proxmox-release-bullseye.gpg".to_string()
+            }
+            _ => format!("/usr/share/keyrings/proxmox-release-{release}.gpg"),
+        }
+    } else {
+        return None;
+    };
+
+    Some(PathBuf::from(path))
+}
+
+/// Like [detect_repo_key], but for a repository definition in one-line or deb822 format.
+pub fn detect_repo_key_for_line(repository: &str) -> Option<PathBuf> {
+    let repository = crate::convert_repo_line(repository.to_string()).ok()?;
+    detect_repo_key(&repository)
+}
@@ -0,0 +1,87 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::Error;
+use proxmox_sys::fs::{CreateOptions, replace_file};
+
+use crate::{ProgressReport, types::PoolStats};
+
+/// Metrics of a single mirror after a snapshot or sync operation.
+pub struct MirrorMetrics<'a> {
+    /// Mirror ID, used as `mirror` label
+    pub mirror: &'a str,
+    /// Newly fetched/added vs. re-used files
+    pub progress: &'a ProgressReport,
+    /// Statistics of the (target) pool
+    pub pool: &'a PoolStats,
+    /// Number of snapshots
+    pub snapshot_count: usize,
+    /// Timestamp of the operation
+    pub last_sync: i64,
+}
+
+/// Format `metrics` in the Prometheus text exposition format.
+pub fn format_metrics(metrics: &[MirrorMetrics]) -> String {
+    let mut out = String::new();
+
+    let mut metric = |name: &str, help: &str, value: fn(&MirrorMetrics) -> u64| {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        for entry in metrics {
+            let _ = writeln!(
+                out,
+                "{name}{{mirror=\"{}\"}} {}",
+                entry.mirror,
+                value(entry)
+            );
+        }
+    };
+
+    metric(
+        "proxmox_mirror_new_files_total",
+        "Number of files newly added by the last operation.",
+        |entry| entry.progress.new_files as u64,
+    );
+    metric(
+        "proxmox_mirror_new_bytes_total",
+        "Size of files newly added by the last operation.",
+        |entry| entry.progress.new_bytes as u64,
+    );
+    metric(
+        "proxmox_mirror_reused_files_total",
+        "Number of already existing files re-used by the last operation.",
+        |entry| entry.progress.reused_files as u64,
+    );
+    metric(
+        "proxmox_mirror_snapshot_count",
+        "Number of snapshots.",
+        |entry| entry.snapshot_count as u64,
+    );
+    metric(
+        "proxmox_mirror_pool_files_total",
+        "Number of unique files in the pool.",
+        |entry| entry.pool.files as u64,
+    );
+    metric(
+        "proxmox_mirror_pool_bytes_total",
+        "Size of unique files in the pool.",
+        |entry| entry.pool.bytes,
+    );
+    metric(
+        "proxmox_mirror_last_sync_timestamp",
+        "Unix timestamp of the last operation.",
+        |entry| entry.last_sync.max(0) as u64,
+    );
+
+    out
+}
+
+/// Atomically write `metrics` to `path`, e.g., for consumption by the node exporter's textfile
+/// collector.
+pub fn write_metrics_file(path: &Path, metrics: &[MirrorMetrics]) -> Result<(), Error> {
+    replace_file(
+        path,
+        format_metrics(metrics).as_bytes(),
+        CreateOptions::default(),
+        true,
+    )
+}
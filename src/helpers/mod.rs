@@ -1,3 +1,11 @@
+pub mod keys;
+pub mod metrics;
+pub mod pkg_path;
+pub mod progress_socket;
 pub mod tty;
 mod verifier;
-pub(crate) use verifier::verify_signature;
+pub use verifier::{
+    KeyStatus, KeyValidationResult, describe_policy, validate_all_keys, verify_minisign_signature,
+    verify_signify_signature,
+};
+pub(crate) use verifier::{verify_ed25519_signature, verify_signature};
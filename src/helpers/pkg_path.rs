@@ -0,0 +1,205 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::types::Diff;
+
+/// Package information derived from the path of a `.deb` file in a repository pool.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PackagePath {
+    /// Package name
+    pub name: String,
+    /// Package version (with epoch, if encoded in the file name)
+    pub version: String,
+    /// Package architecture
+    pub architecture: String,
+}
+
+/// Parses a path following the Debian pool conventions, e.g.
+/// `pool/main/p/proxmox-ve/proxmox-ve_8.0-1_amd64.deb`.
+///
+/// Only the file name is considered, it must be of the form `<name>_<version>_<arch>.deb` (or
+/// `.udeb`). Returns `None` for all other paths.
+pub fn parse_deb_path(path: &Path) -> Option<PackagePath> {
+    let file_name = path.file_name()?.to_str()?;
+
+    let stem = file_name
+        .strip_suffix(".deb")
+        .or_else(|| file_name.strip_suffix(".udeb"))?;
+
+    let mut parts = stem.split('_');
+    let (name, version, architecture) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(name), Some(version), Some(arch)) if parts.next().is_none() => (name, version, arch),
+        _ => return None,
+    };
+
+    if name.is_empty() || version.is_empty() || architecture.is_empty() {
+        return None;
+    }
+
+    // epochs are URL-encoded in file names
+    let version = version.replace("%3a", ":").replace("%3A", ":");
+
+    Some(PackagePath {
+        name: name.to_string(),
+        version,
+        architecture: architecture.to_string(),
+    })
+}
+
+/// Package-level view of the differences for a single package.
+#[derive(Default, Debug)]
+pub struct PackageDiff {
+    pub added: Vec<PackagePath>,
+    pub changed: Vec<PackagePath>,
+    pub removed: Vec<PackagePath>,
+}
+
+/// Groups all `.deb` paths contained in `diff` by package name. Other paths are ignored.
+pub fn package_diff(diff: &Diff) -> BTreeMap<String, PackageDiff> {
+    let mut packages: BTreeMap<String, PackageDiff> = BTreeMap::new();
+
+    let mut insert = |paths: &[(PathBuf, u64)],
+                      select: fn(&mut PackageDiff) -> &mut Vec<PackagePath>| {
+        for (path, _size) in paths {
+            if let Some(package) = parse_deb_path(path) {
+                let entry = packages.entry(package.name.clone()).or_default();
+                select(entry).push(package);
+            }
+        }
+    };
+
+    insert(&diff.added.paths, |entry| &mut entry.added);
+    insert(&diff.changed.paths, |entry| &mut entry.changed);
+    insert(&diff.removed.paths, |entry| &mut entry.removed);
+
+    for entry in packages.values_mut() {
+        entry.added.sort();
+        entry.changed.sort();
+        entry.removed.sort();
+    }
+
+    packages
+}
+
+/// Package-level view of the differences between two snapshots.
+#[derive(Default, Debug)]
+pub struct SnapshotPackageDiff {
+    /// Packages only contained in the newer snapshot
+    pub added: Vec<PackagePath>,
+    /// Packages only contained in the older snapshot
+    pub removed: Vec<PackagePath>,
+    /// Packages contained in both snapshots with a different version, as (old, new) pairs
+    pub upgraded: Vec<(PackagePath, PackagePath)>,
+}
+
+/// Converts the added and removed `.deb` paths in `diff` into a package-level diff. A package
+/// (identified by name and architecture) which was both removed and added is reported as
+/// upgraded. Other paths are ignored.
+pub fn snapshot_package_diff(diff: &Diff) -> SnapshotPackageDiff {
+    let mut result = SnapshotPackageDiff::default();
+
+    let group = |paths: &[(PathBuf, u64)]| {
+        let mut packages: BTreeMap<(String, String), Vec<PackagePath>> = BTreeMap::new();
+        for (path, _size) in paths {
+            if let Some(package) = parse_deb_path(path) {
+                packages
+                    .entry((package.name.clone(), package.architecture.clone()))
+                    .or_default()
+                    .push(package);
+            }
+        }
+        for list in packages.values_mut() {
+            list.sort();
+        }
+        packages
+    };
+
+    let mut added = group(&diff.added.paths);
+    let removed = group(&diff.removed.paths);
+
+    for (key, old) in removed {
+        let new = added.remove(&key).unwrap_or_default();
+        let mut new = new.into_iter();
+        for old in old {
+            match new.next() {
+                Some(new) => result.upgraded.push((old, new)),
+                None => result.removed.push(old),
+            }
+        }
+        result.added.extend(new);
+    }
+    result.added.extend(added.into_values().flatten());
+
+    result.added.sort();
+    result.removed.sort();
+    result.upgraded.sort();
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str, architecture: &str) -> PackagePath {
+        PackagePath {
+            name: name.to_string(),
+            version: version.to_string(),
+            architecture: architecture.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_deb() {
+        assert_eq!(
+            parse_deb_path(Path::new(
+                "pool/main/p/proxmox-ve/proxmox-ve_8.0-1_amd64.deb"
+            )),
+            Some(package("proxmox-ve", "8.0-1", "amd64"))
+        );
+        assert_eq!(
+            parse_deb_path(Path::new("libc6_2.36-9+deb12u4_all.deb")),
+            Some(package("libc6", "2.36-9+deb12u4", "all"))
+        );
+    }
+
+    #[test]
+    fn parse_deb_epoch() {
+        assert_eq!(
+            parse_deb_path(Path::new("pool/main/v/vim/vim_2%3a9.0.1378-2_amd64.deb")),
+            Some(package("vim", "2:9.0.1378-2", "amd64"))
+        );
+        assert_eq!(
+            parse_deb_path(Path::new("pool/main/v/vim/vim_2%3A9.0.1378-2_amd64.deb")),
+            Some(package("vim", "2:9.0.1378-2", "amd64"))
+        );
+    }
+
+    #[test]
+    fn parse_udeb() {
+        assert_eq!(
+            parse_deb_path(Path::new(
+                "pool/main/b/busybox/busybox-udeb_1%3a1.35.0-4_amd64.udeb"
+            )),
+            Some(package("busybox-udeb", "1:1.35.0-4", "amd64"))
+        );
+    }
+
+    #[test]
+    fn parse_malformed() {
+        for path in [
+            "pool/main/p/proxmox-ve/proxmox-ve_8.0-1_amd64.dsc",
+            "pool/main/p/proxmox-ve/proxmox-ve_8.0-1.deb",
+            "pool/main/p/proxmox-ve/proxmox-ve_8.0-1_amd64_extra.deb",
+            "pool/main/p/proxmox-ve/_8.0-1_amd64.deb",
+            "pool/main/p/proxmox-ve/proxmox-ve__amd64.deb",
+            "pool/main/p/proxmox-ve/proxmox-ve_8.0-1_.deb",
+            "pool/main/p/proxmox-ve/",
+            ".deb",
+        ] {
+            assert_eq!(parse_deb_path(Path::new(path)), None, "{path}");
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Error, format_err};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Unix domain socket streaming newline-delimited JSON events to all connected clients.
+///
+/// New clients are accepted whenever an event is sent, so clients connecting in between only
+/// receive events sent after they connected. The socket file is removed on drop.
+pub struct ProgressSocket {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Mutex<Vec<UnixStream>>,
+}
+
+impl ProgressSocket {
+    /// Listen on `path`, replacing a stale socket file left over by a previous run.
+    pub fn bind(path: &Path) -> Result<Self, Error> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(format_err!(
+                    "Progress socket {path:?} is already in use by another process."
+                ));
+            }
+            std::fs::remove_file(path).map_err(|err| {
+                format_err!("Failed to remove stale progress socket {path:?} - {err}")
+            })?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .map_err(|err| format_err!("Failed to bind progress socket {path:?} - {err}"))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            listener,
+            clients: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Send `event` to all connected clients, dropping clients that disconnected.
+    ///
+    /// Clients which don't keep up with reading events, i.e., whose socket buffer is full, are
+    /// dropped as well, as they would only receive a partial event.
+    pub fn send<T: Serialize>(&self, event: &T) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+
+        let mut clients = self.clients.lock().unwrap();
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _addr)) => {
+                    // never block the caller on slow clients
+                    stream.set_nonblocking(true)?;
+                    clients.push(stream);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(format_err!("Failed to accept progress client - {err}")),
+            }
+        }
+
+        clients.retain_mut(|client| client.write_all(&line).is_ok());
+
+        Ok(())
+    }
+}
+
+impl Drop for ProgressSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Connect to a [ProgressSocket] at `path` and call `handler` for each received event, until the
+/// socket is closed or `handler` returns `false`.
+pub fn watch_progress_socket<T: DeserializeOwned>(
+    path: &Path,
+    mut handler: impl FnMut(T) -> Result<bool, Error>,
+) -> Result<(), Error> {
+    let stream = UnixStream::connect(path)
+        .map_err(|err| format_err!("Failed to connect to progress socket {path:?} - {err}"))?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let event: T = serde_json::from_str(&line)
+            .map_err(|err| format_err!("Failed to parse progress event - {err}"))?;
+        if !handler(event)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
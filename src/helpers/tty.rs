@@ -1,6 +1,7 @@
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 
 use anyhow::{Error, bail, format_err};
+use nix::sys::termios::{self, LocalFlags, SetArg};
 use proxmox_schema::parse_boolean;
 
 /// Prints `query`, reads string from terminal, defaulting to `default`.
@@ -32,6 +33,99 @@ pub fn read_string_from_tty(query: &str, default: Option<&str>) -> Result<String
     }
 }
 
+/// Prints `prompt`, reads lines from terminal until `terminator` is entered on its own line.
+///
+/// An empty `terminator` ends the input with an empty line. Returns the lines joined with `\n`,
+/// excluding the terminator.
+pub fn read_multiline_from_tty(prompt: &str, terminator: &str) -> Result<String, Error> {
+    use std::io::BufRead;
+
+    if terminator.is_empty() {
+        println!("{prompt} (finish with an empty line):");
+    } else {
+        println!("{prompt} (finish with '{terminator}' on its own line):");
+    }
+
+    let mut lines = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim() == terminator {
+            break;
+        }
+        lines.push(line);
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Prints `query`, reads string from terminal and checks it with `validator`.
+///
+/// Will retry if `validator` rejects the input, printing the returned error message.
+pub fn read_string_from_tty_with_validation(
+    query: &str,
+    default: Option<&str>,
+    validator: impl Fn(&str) -> Result<(), String>,
+) -> Result<String, Error> {
+    loop {
+        let line = read_string_from_tty(query, default)?;
+        match validator(&line) {
+            Ok(()) => return Ok(line),
+            Err(err) => eprintln!("Invalid input - {err}"),
+        }
+    }
+}
+
+/// Prints `query`, reads string from terminal without echoing the input.
+///
+/// Will retry if the user doesn't input any data.
+pub fn read_password_from_tty(query: &str) -> Result<String, Error> {
+    use std::io::BufRead;
+
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        bail!("Reading a password requires an interactive terminal.");
+    }
+
+    let original = termios::tcgetattr(&stdin)?;
+    let mut no_echo = original.clone();
+    no_echo.local_flags.remove(LocalFlags::ECHO);
+    no_echo.local_flags.insert(LocalFlags::ECHONL);
+
+    loop {
+        print!("{query}: ");
+        let _ = std::io::stdout().flush();
+
+        termios::tcsetattr(&stdin, SetArg::TCSANOW, &no_echo)?;
+        let mut line = String::new();
+        let res = stdin.lock().read_line(&mut line);
+        termios::tcsetattr(&stdin, SetArg::TCSANOW, &original)?;
+        res?;
+
+        let line = line.trim_end_matches(['\r', '\n']);
+        if !line.is_empty() {
+            return Ok(line.to_string());
+        }
+    }
+}
+
+/// Reads a password twice using `query` and `confirm_query`, without echoing the input.
+///
+/// Will retry up to three times if the two inputs don't match.
+pub fn read_password_with_confirmation(query: &str, confirm_query: &str) -> Result<String, Error> {
+    const MAX_ATTEMPTS: usize = 3;
+
+    for _ in 0..MAX_ATTEMPTS {
+        let password = read_password_from_tty(query)?;
+        let confirmation = read_password_from_tty(confirm_query)?;
+        if password == confirmation {
+            return Ok(password);
+        }
+        eprintln!("Passwords do not match.");
+    }
+
+    bail!("Passwords did not match after {MAX_ATTEMPTS} attempts.");
+}
+
 /// Prints `query`, reads boolean-string from terminal, defaulting to `default`.
 ///
 /// Will retry if the user doesn't input a valid boolean string.
@@ -13,12 +13,18 @@ use sequoia_openpgp::{
     policy::StandardPolicy,
     types::HashAlgorithm,
 };
-use std::io;
+use serde::Serialize;
+use std::{io, path::Path, time::UNIX_EPOCH};
 
-use crate::config::WeakCryptoConfig;
+use proxmox_sys::fs::file_get_contents;
+use proxmox_time::epoch_i64;
+
+use crate::config::{MirrorConfig, SigType, WeakCryptoConfig};
 
 struct Helper<'a> {
     cert: &'a Cert,
+    // fingerprint of the (sub)key that made the first good signature
+    verified_by: Option<String>,
 }
 
 impl VerificationHelper for Helper<'_> {
@@ -42,13 +48,38 @@ impl VerificationHelper for Helper<'_> {
         }
         let layer = &layers[0];
         let mut errors = Vec::new();
+        // fingerprints of the signing keys that were tried, for identifying misconfigured keys
+        let mut tried_keys = Vec::new();
         match layer {
             MessageLayer::SignatureGroup { results } => {
                 // We possibly have multiple signatures, but not all keys, so `or` all the individual results.
                 for result in results {
                     match result {
-                        Ok(_) => good = true,
-                        Err(e) => errors.push(e),
+                        Ok(checksum) => {
+                            good = true;
+                            if self.verified_by.is_none() {
+                                self.verified_by = Some(checksum.ka.key().fingerprint().to_hex());
+                            }
+                        }
+                        Err(e) => {
+                            let fingerprint = match e {
+                                VerificationError::BadKey { ka, .. }
+                                | VerificationError::BadSignature { ka, .. } => {
+                                    Some(ka.key().fingerprint())
+                                }
+                                VerificationError::UnboundKey { cert, .. } => {
+                                    Some(cert.fingerprint())
+                                }
+                                _ => None,
+                            };
+                            if let Some(fingerprint) = fingerprint {
+                                let fingerprint = fingerprint.to_hex();
+                                if !tried_keys.contains(&fingerprint) {
+                                    tried_keys.push(fingerprint);
+                                }
+                            }
+                            errors.push(e);
+                        }
                     }
                 }
             }
@@ -88,18 +119,227 @@ impl VerificationHelper for Helper<'_> {
                 };
             }
             eprintln!();
-            Err(anyhow::anyhow!("No valid signature found."))
+
+            // no signature was made by (a subkey of) our certificate
+            if tried_keys.is_empty() {
+                tried_keys.push(self.cert.fingerprint().to_hex());
+            }
+            Err(anyhow::anyhow!(
+                "No valid signature found. Tried keys: [{}]",
+                tried_keys.join(", ")
+            ))
         }
     }
 }
 
-/// Verifies GPG-signed `msg` was signed by `key`, returning the verified data without signature.
+/// Verifies GPG-signed `msg` was signed by one of `keys`, returning the verified data without
+/// signature.
+///
+/// The keys are tried in order, the first one that successfully verifies the message wins.
 pub(crate) fn verify_signature(
     msg: &[u8],
-    key: &[u8],
+    keys: &[Vec<u8>],
+    detached_sig: Option<&[u8]>,
+    weak_crypto: &WeakCryptoConfig,
+) -> Result<Vec<u8>, Error> {
+    let key_files: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+    verify_signature_with_bundle(msg, &key_files, detached_sig, weak_crypto)
+}
+
+/// Verifies GPG-signed `msg` against a bundle of independently trusted key files (each either a
+/// certificate or a keyring), returning the verified data without signature.
+///
+/// The first entry is the primary key, followed by any extra keys. Verification succeeds as soon
+/// as one of them verifies the message, otherwise the errors of all key files are combined.
+pub(crate) fn verify_signature_with_bundle(
+    msg: &[u8],
+    key_files: &[&[u8]],
     detached_sig: Option<&[u8]>,
     weak_crypto: &WeakCryptoConfig,
 ) -> Result<Vec<u8>, Error> {
+    if key_files.is_empty() {
+        bail!("No key configured for verification, aborting!");
+    }
+
+    let mut errors = Vec::new();
+    for (n, key) in key_files.iter().enumerate() {
+        match verify_signature_with_key(msg, key, detached_sig, weak_crypto) {
+            Ok((verified, fingerprint)) => {
+                if n > 0 {
+                    println!("Verified using extra key #{} ({fingerprint})", n - 1);
+                } else {
+                    println!("Verified using key {fingerprint}");
+                }
+                return Ok(verified);
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+
+    if errors.len() == 1 {
+        return Err(errors.remove(0));
+    }
+
+    let combined: Vec<String> = errors
+        .iter()
+        .enumerate()
+        .map(|(n, err)| match n {
+            0 => format!("primary key - {err}"),
+            n => format!("extra key #{} - {err}", n - 1),
+        })
+        .collect();
+    bail!(
+        "None of the configured keys could verify the message:\n{}",
+        combined.join("\n")
+    );
+}
+
+/// Returns a human-readable description of each exception to the default signature policy that
+/// is enabled in `config`.
+pub fn describe_policy(config: &WeakCryptoConfig) -> Vec<String> {
+    let mut notices = Vec::new();
+
+    if config.allow_sha1 {
+        notices.push(
+            "SHA-1 based signatures are accepted, SHA-1 is vulnerable to collision attacks."
+                .to_string(),
+        );
+    }
+    if let Some(min_dsa) = config.min_dsa_key_size {
+        if min_dsa <= 1024 {
+            notices.push(format!(
+                "DSA keys with 1024 bits are accepted (minimum size set to {min_dsa}), 2048 bits or more are considered safe."
+            ));
+        }
+    }
+    if let Some(min_rsa) = config.min_rsa_key_size {
+        if min_rsa <= 1024 {
+            notices.push(format!(
+                "RSA keys with 1024 bits are accepted (minimum size set to {min_rsa}), 3072 bits or more are considered safe."
+            ));
+        }
+    }
+    if config.allow_rsa_2048 {
+        notices.push(
+            "RSA keys with 2048 bits are accepted, 3072 bits or more are considered safe."
+                .to_string(),
+        );
+    }
+
+    notices
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Validation status of a configured key file.
+pub enum KeyStatus {
+    /// Key is valid and not expired
+    #[serde(rename_all = "kebab-case")]
+    Valid {
+        /// Fingerprint of the primary key
+        fingerprint: String,
+        /// Expiry as epoch, if the key expires at all
+        expires_at: Option<i64>,
+    },
+    /// Key is valid, but has expired
+    #[serde(rename_all = "kebab-case")]
+    Expired {
+        /// Fingerprint of the primary key
+        fingerprint: String,
+        /// Expiry as epoch
+        expired_at: i64,
+    },
+    /// Key file could not be read or doesn't contain a valid certificate
+    Invalid(String),
+    /// Key file doesn't exist
+    FileNotFound,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Result of validating a single key file configured for a mirror.
+pub struct KeyValidationResult {
+    /// ID of the mirror using this key
+    pub mirror_id: String,
+    /// Path of the key file
+    pub key_path: String,
+    /// Validation status
+    pub status: KeyStatus,
+}
+
+// Validates a single certificate or keyring, reporting on the first certificate it contains.
+fn validate_key(key_path: &Path) -> KeyStatus {
+    if !key_path.exists() {
+        return KeyStatus::FileNotFound;
+    }
+
+    let check = || -> Result<KeyStatus, Error> {
+        let data = file_get_contents(key_path)?;
+        let certs = CertParser::from_bytes(&data)?.collect::<Result<Vec<Cert>, _>>()?;
+
+        let policy = StandardPolicy::new();
+        for cert in &certs {
+            cert.with_policy(&policy, None).map_err(|err| {
+                format_err!("certificate {} is not valid - {err}", cert.fingerprint())
+            })?;
+        }
+
+        let cert = certs
+            .first()
+            .ok_or_else(|| format_err!("no certificate found"))?;
+        let expires_at = match cert
+            .with_policy(&policy, None)?
+            .primary_key()
+            .key_expiration_time()
+        {
+            Some(time) => Some(time.duration_since(UNIX_EPOCH)?.as_secs() as i64),
+            None => None,
+        };
+        let fingerprint = cert.fingerprint().to_hex();
+
+        Ok(match expires_at {
+            Some(expired_at) if expired_at <= epoch_i64() => KeyStatus::Expired {
+                fingerprint,
+                expired_at,
+            },
+            _ => KeyStatus::Valid {
+                fingerprint,
+                expires_at,
+            },
+        })
+    };
+
+    check().unwrap_or_else(|err| KeyStatus::Invalid(err.to_string()))
+}
+
+/// Validates the OpenPGP keys configured for all `configs`, including any extra key paths.
+///
+/// Mirrors using minisign or signify signatures are skipped, their keys are only checked when
+/// verifying a signature.
+pub fn validate_all_keys(configs: &[MirrorConfig]) -> Vec<KeyValidationResult> {
+    configs
+        .iter()
+        .filter(|config| config.sig_type.unwrap_or_default() == SigType::Gpg)
+        .flat_map(|config| {
+            std::iter::once(&config.key_path)
+                .chain(config.extra_key_paths.iter().flatten())
+                .map(|key_path| KeyValidationResult {
+                    mirror_id: config.id.clone(),
+                    key_path: key_path.clone(),
+                    status: validate_key(Path::new(key_path)),
+                })
+        })
+        .collect()
+}
+
+// Verifies `msg` against a single certificate or keyring, returning the verified data and the
+// fingerprint of the signing key.
+fn verify_signature_with_key(
+    msg: &[u8],
+    key: &[u8],
+    detached_sig: Option<&[u8]>,
+    weak_crypto: &WeakCryptoConfig,
+) -> Result<(Vec<u8>, String), Error> {
     let mut policy = StandardPolicy::new();
     if weak_crypto.allow_sha1 {
         policy.accept_hash(HashAlgorithm::SHA1);
@@ -114,15 +354,28 @@ pub(crate) fn verify_signature(
             policy.accept_asymmetric_algo(sequoia_openpgp::policy::AsymmetricAlgorithm::RSA1024);
         }
     }
+    if weak_crypto.allow_rsa_2048 {
+        policy.accept_asymmetric_algo(sequoia_openpgp::policy::AsymmetricAlgorithm::RSA2048);
+    }
 
     let verifier = |cert| {
-        let helper = Helper { cert: &cert };
+        let helper = Helper {
+            cert: &cert,
+            verified_by: None,
+        };
+        // only set once a good signature was found
+        let fingerprint = |helper: &Helper| {
+            helper
+                .verified_by
+                .clone()
+                .ok_or_else(|| format_err!("Failed to verify message!"))
+        };
 
         if let Some(sig) = detached_sig {
             let mut verifier =
                 DetachedVerifierBuilder::from_bytes(sig)?.with_policy(&policy, None, helper)?;
             verifier.verify_bytes(msg)?;
-            Ok(msg.to_vec())
+            Ok((msg.to_vec(), fingerprint(verifier.helper_ref())?))
         } else {
             let mut verified = Vec::new();
             let mut verifier =
@@ -132,7 +385,7 @@ pub(crate) fn verify_signature(
             if !verifier.message_processed() {
                 bail!("Failed to verify message!");
             }
-            Ok(verified)
+            Ok((verified, fingerprint(verifier.helper_ref())?))
         }
     };
 
@@ -165,3 +418,95 @@ pub(crate) fn verify_signature(
     // neither a keyring nor a certificate was detect, so we abort here
     bail!("'key-path' contains neither a keyring nor a certificate, aborting!");
 }
+
+/// Verifies `msg` was signed by minisign `key`, using the detached signature `sig`.
+///
+/// `key` can either be the full public key file or just the base64-encoded key line.
+pub fn verify_minisign_signature(msg: &[u8], key: &[u8], sig: &[u8]) -> Result<(), Error> {
+    let key = std::str::from_utf8(key)
+        .map_err(|err| format_err!("minisign public key is not valid UTF-8 - {err}"))?;
+    let public_key = minisign_verify::PublicKey::decode(key)
+        .or_else(|_| minisign_verify::PublicKey::from_base64(key.trim()))
+        .map_err(|err| format_err!("Failed to parse minisign public key - {err}"))?;
+
+    let sig = std::str::from_utf8(sig)
+        .map_err(|err| format_err!("minisign signature is not valid UTF-8 - {err}"))?;
+    let signature = minisign_verify::Signature::decode(sig)
+        .map_err(|err| format_err!("Failed to parse minisign signature - {err}"))?;
+
+    public_key
+        .verify(msg, &signature, false)
+        .map_err(|err| format_err!("minisign signature verification failed - {err}"))
+}
+
+// Decodes the base64 line following the 'untrusted comment' line of a signify key or signature.
+fn decode_signify_blob(data: &[u8], what: &str) -> Result<Vec<u8>, Error> {
+    let data = std::str::from_utf8(data)
+        .map_err(|err| format_err!("signify {what} is not valid UTF-8 - {err}"))?;
+    let mut lines = data.lines();
+
+    match lines.next() {
+        Some(line) if line.starts_with("untrusted comment:") => {}
+        _ => bail!("signify {what} lacks 'untrusted comment' line"),
+    }
+
+    let encoded = lines
+        .next()
+        .ok_or_else(|| format_err!("signify {what} lacks base64 data"))?;
+    proxmox_base64::decode(encoded.trim())
+        .map_err(|err| format_err!("Failed to decode signify {what} - {err}"))
+}
+
+/// Verifies `msg` was signed by signify `key`, using the detached signature `sig`.
+pub fn verify_signify_signature(msg: &[u8], key: &[u8], sig: &[u8]) -> Result<(), Error> {
+    // 2 bytes algorithm, 8 bytes key number, followed by raw key/signature
+    let key = decode_signify_blob(key, "public key")?;
+    if key.len() != 42 || &key[..2] != b"Ed" {
+        bail!("signify public key has invalid format");
+    }
+
+    let sig = decode_signify_blob(sig, "signature")?;
+    if sig.len() != 74 || &sig[..2] != b"Ed" {
+        bail!("signify signature has invalid format");
+    }
+
+    if key[2..10] != sig[2..10] {
+        bail!(
+            "signature was made by key {}, not by {}",
+            hex::encode(&sig[2..10]),
+            hex::encode(&key[2..10])
+        );
+    }
+
+    let public_key =
+        openssl::pkey::PKey::public_key_from_raw_bytes(&key[10..], openssl::pkey::Id::ED25519)?;
+    let mut verifier = openssl::sign::Verifier::new_without_digest(&public_key)?;
+    if !verifier.verify_oneshot(&sig[10..], msg)? {
+        bail!("signify signature verification failed");
+    }
+
+    Ok(())
+}
+
+/// Verifies `msg` using the detached minisign or signify signature `sig`, trying `keys` in order.
+pub(crate) fn verify_ed25519_signature(
+    sig_type: SigType,
+    msg: &[u8],
+    keys: &[Vec<u8>],
+    sig: &[u8],
+) -> Result<(), Error> {
+    let verify = match sig_type {
+        SigType::Minisign => verify_minisign_signature,
+        SigType::Signify => verify_signify_signature,
+        SigType::Gpg => bail!("GPG signatures need to be verified using 'verify_signature'"),
+    };
+
+    for (n, key) in keys.iter().enumerate() {
+        match verify(msg, key, sig) {
+            Ok(()) => return Ok(()),
+            Err(err) => eprintln!("Verification with key #{n} failed - {err}"),
+        }
+    }
+
+    bail!("None of the configured keys could verify the message!");
+}
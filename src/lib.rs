@@ -9,16 +9,19 @@
 //! - syncing to external media
 
 use std::{
+    collections::BTreeMap,
     fmt::Display,
     ops::{Add, AddAssign},
     path::Path,
+    time::Duration,
 };
 
 use anyhow::{Error, format_err};
 use medium::MirrorInfo;
 use proxmox_apt::repositories::{APTRepositoryFileImpl, APTRepositoryImpl};
 use proxmox_apt_api_types::{APTRepository, APTRepositoryFile, APTRepositoryFileType};
-use types::Snapshot;
+use serde::Serialize;
+use types::{Snapshot, SourcesFormat};
 
 /// Main configuration file containing definitions of mirrors, external media and subscription keys.
 pub mod config;
@@ -54,7 +57,7 @@ impl FetchResult {
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 /// To keep track of progress and how much data was newly fetched vs. re-used and just linked
 struct Progress {
     new: usize,
@@ -116,11 +119,156 @@ impl Display for Progress {
     }
 }
 
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Summary of how much data was newly fetched vs. re-used by an operation.
+pub struct ProgressReport {
+    /// Number of newly fetched/added files
+    pub new_files: usize,
+    /// Total size of newly fetched/added files
+    pub new_bytes: usize,
+    /// Number of re-used, already existing files
+    pub reused_files: usize,
+    /// Time spent in the individual phases, if recorded by the operation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Summary of a snapshot creation.
+pub struct SnapshotCreationSummary {
+    /// The created snapshot
+    pub snapshot: Snapshot,
+    /// Newly fetched vs. re-used files
+    #[serde(flatten)]
+    pub progress: ProgressReport,
+    /// Number of packages skipped because of the mirror's package filters
+    pub skipped_packages: usize,
+    /// Total size of skipped packages
+    pub skipped_bytes: usize,
+    /// Newly fetched vs. re-used binary packages, per component
+    pub components: BTreeMap<String, ProgressReport>,
+    /// Non-fatal issues encountered while creating the snapshot
+    pub warnings: Vec<String>,
+    /// Duration of the whole operation
+    pub duration_secs: f64,
+}
+
+impl From<&Progress> for ProgressReport {
+    fn from(progress: &Progress) -> Self {
+        Self {
+            new_files: progress.new,
+            new_bytes: progress.new_bytes,
+            reused_files: progress.reused,
+            timings: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Wall-clock time spent in the phases of creating a snapshot.
+pub struct Timings {
+    /// Fetching and verifying the release files
+    pub release: Duration,
+    /// Fetching the package indices
+    pub indices: Duration,
+    /// Fetching binary packages
+    pub binary_packages: Duration,
+    /// Fetching source packages
+    pub source_packages: Duration,
+    /// Renaming the temporary snapshot directory
+    pub rotation: Duration,
+    /// Whole operation
+    pub total: Duration,
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "release {:.2}s, indices {:.2}s, binary packages {:.2}s, source packages {:.2}s, rotation {:.2}s, total {:.2}s",
+            self.release.as_secs_f64(),
+            self.indices.as_secs_f64(),
+            self.binary_packages.as_secs_f64(),
+            self.source_packages.as_secs_f64(),
+            self.rotation.as_secs_f64(),
+            self.total.as_secs_f64(),
+        )
+    }
+}
+
+/// Prefix marking a repository definition in deb822 format, with lines separated by a literal `\n`
+/// so that it can be stored on a single line in the config file.
+pub const DEB822_PREFIX: &str = "deb822:";
+
+/// Encode a (multi-line) repository stanza in deb822 format into a single line, suitable for use as
+/// `MirrorConfig` repository.
+pub fn encode_deb822_repository(stanza: &str) -> String {
+    format!("{DEB822_PREFIX}{}", stanza.trim().replace('\n', "\\n"))
+}
+
+// Helper to get the deb822 stanza if `repository` is defined in deb822 format.
+fn deb822_stanza(repository: &str) -> Option<String> {
+    if let Some(stanza) = repository.strip_prefix(DEB822_PREFIX) {
+        Some(stanza.replace("\\n", "\n"))
+    } else if repository.trim_start().starts_with("Types:") {
+        Some(repository.to_string())
+    } else {
+        None
+    }
+}
+
+/// Try to parse a repository stanza in deb822 (`.sources`) format into an `APTRepository`.
+pub(crate) fn convert_repo_deb822(content: String) -> Result<APTRepository, Error> {
+    let mut repository = APTRepositoryFile::with_content(content, APTRepositoryFileType::Sources);
+    repository.parse()?;
+    repository
+        .repositories
+        .first()
+        .cloned()
+        .ok_or_else(|| format_err!("No repository found in deb822 stanza."))
+}
+
 /// Try to parse a line in sources.list format into an `APTRepository`.
+///
+/// Repositories in deb822 format (starting with `Types:` or [DEB822_PREFIX]) are parsed using
+/// [convert_repo_deb822].
 pub(crate) fn convert_repo_line(line: String) -> Result<APTRepository, Error> {
+    if let Some(stanza) = deb822_stanza(&line) {
+        return convert_repo_deb822(stanza);
+    }
+
     let mut repository = APTRepositoryFile::with_content(line, APTRepositoryFileType::List);
     repository.parse()?;
-    Ok(repository.repositories[0].clone())
+    repository
+        .repositories
+        .first()
+        .cloned()
+        .ok_or_else(|| format_err!("No repository found in repository line."))
+}
+
+/// Add `extra` components to and remove `exclude` components from `repository`.
+pub(crate) fn adjust_components(
+    repository: &mut APTRepository,
+    extra: &[String],
+    exclude: &[String],
+) {
+    for component in extra {
+        if !repository.components.contains(component) {
+            repository.components.push(component.clone());
+        }
+    }
+    repository
+        .components
+        .retain(|component| !exclude.contains(component));
+}
+
+/// Check whether `repository` is a valid repository definition, either in one-line or deb822
+/// format.
+pub fn validate_repository(repository: &str) -> Result<(), Error> {
+    convert_repo_line(repository.to_string()).map(|_| ())
 }
 
 /// Generate a file-based repository line in sources.list format
@@ -129,6 +277,55 @@ pub fn generate_repo_file_line(
     mirror_id: &str,
     mirror: &MirrorInfo,
     snapshot: &Snapshot,
+) -> Result<String, Error> {
+    file_repository_definition(
+        medium_base,
+        mirror_id,
+        mirror,
+        snapshot,
+        APTRepositoryFileType::List,
+    )
+}
+
+/// Generate a file-based repository stanza in deb822 (`.sources`) format
+pub fn generate_repo_file_line_deb822(
+    medium_base: &Path,
+    mirror_id: &str,
+    mirror: &MirrorInfo,
+    snapshot: &Snapshot,
+) -> Result<String, Error> {
+    file_repository_definition(
+        medium_base,
+        mirror_id,
+        mirror,
+        snapshot,
+        APTRepositoryFileType::Sources,
+    )
+}
+
+/// Generate a file-based repository definition in the given format
+pub fn generate_repo_file_entry(
+    medium_base: &Path,
+    mirror_id: &str,
+    mirror: &MirrorInfo,
+    snapshot: &Snapshot,
+    format: SourcesFormat,
+) -> Result<String, Error> {
+    match format {
+        SourcesFormat::OneLine => generate_repo_file_line(medium_base, mirror_id, mirror, snapshot),
+        SourcesFormat::Deb822 => {
+            generate_repo_file_line_deb822(medium_base, mirror_id, mirror, snapshot)
+        }
+    }
+}
+
+// Helper to generate a repository definition pointing to a snapshot on a medium.
+fn file_repository_definition(
+    medium_base: &Path,
+    mirror_id: &str,
+    mirror: &MirrorInfo,
+    snapshot: &Snapshot,
+    file_type: APTRepositoryFileType,
 ) -> Result<String, Error> {
     let mut snapshot_path = medium_base.to_path_buf();
     snapshot_path.push(mirror_id);
@@ -138,7 +335,18 @@ pub fn generate_repo_file_line(
         .ok_or_else(|| format_err!("Failed to convert snapshot path to String"))?;
 
     let mut repo = convert_repo_line(mirror.repository.clone())?;
+    adjust_components(
+        &mut repo,
+        &mirror.extra_components,
+        &mirror.exclude_components,
+    );
+    // the Release file of partial snapshots still lists all components
+    if let Some(fetched) = mirror::partial_snapshot_components(Path::new(snapshot_path))? {
+        repo.components
+            .retain(|component| fetched.contains(component));
+    }
     repo.uris = vec![format!("file://{}", snapshot_path)];
+    repo.file_type = file_type;
 
     repo.options
         .push(proxmox_apt_api_types::APTRepositoryOption {
@@ -1,24 +1,39 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
     fs::Metadata,
     os::linux::fs::MetadataExt,
     path::{Path, PathBuf},
 };
 
 use anyhow::{Error, bail, format_err};
-use nix::libc;
+use nix::{
+    libc,
+    sys::{
+        stat::{major, minor},
+        statfs::{MSDOS_SUPER_MAGIC, statfs},
+        statvfs::statvfs,
+    },
+};
 use openssl::sha::sha256;
+use proxmox_apt::deb822::PackagesFile;
 use proxmox_subscription::SubscriptionInfo;
-use proxmox_sys::fs::{CreateOptions, file_get_contents, replace_file};
+use proxmox_sys::fs::{CreateOptions, create_path, file_get_contents, replace_file};
 use proxmox_time::{epoch_i64, epoch_to_rfc3339_utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use walkdir::WalkDir;
 
 use crate::{
-    config::{self, ConfigLockGuard, MediaConfig, MirrorConfig},
-    generate_repo_file_line,
+    ProgressReport,
+    config::{self, ConfigLockGuard, HashPreference, MediaConfig, MirrorConfig},
+    generate_repo_file_entry,
     mirror::pool,
     pool::Pool,
-    types::{Diff, SNAPSHOT_REGEX, Snapshot},
+    types::{
+        DeduplicationStats, Diff, GcReport, PoolStats, SNAPSHOT_REGEX, Snapshot, SnapshotState,
+        SourcesFormat,
+    },
 };
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -32,37 +47,65 @@ pub struct MirrorInfo {
     pub architectures: Vec<String>,
     /// Pool directory (relative to medium base)
     pub pool: String,
+    /// Components mirrored in addition to those of the repository line
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub extra_components: Vec<String>,
+    /// Components of the repository line that are not mirrored
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub exclude_components: Vec<String>,
 }
 
-impl From<&MirrorConfig> for MirrorInfo {
-    fn from(config: &MirrorConfig) -> Self {
-        Self {
-            repository: config.repository.clone(),
+impl TryFrom<&MirrorConfig> for MirrorInfo {
+    type Error = Error;
+
+    fn try_from(config: &MirrorConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            repository: render_mirror_repository(config)?,
             architectures: config.architectures.clone(),
             pool: mirror_pool_dir(config),
-        }
+            extra_components: config.extra_components.clone().unwrap_or_default(),
+            exclude_components: config.exclude_components.clone().unwrap_or_default(),
+        })
     }
 }
 
-impl From<MirrorConfig> for MirrorInfo {
-    fn from(config: MirrorConfig) -> Self {
-        Self {
+impl TryFrom<MirrorConfig> for MirrorInfo {
+    type Error = Error;
+
+    fn try_from(config: MirrorConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
             pool: mirror_pool_dir(&config),
-            repository: config.repository,
+            repository: render_mirror_repository(&config)?,
             architectures: config.architectures,
-        }
+            extra_components: config.extra_components.unwrap_or_default(),
+            exclude_components: config.exclude_components.unwrap_or_default(),
+        })
     }
 }
 
+fn render_mirror_repository(mirror: &MirrorConfig) -> Result<String, Error> {
+    mirror
+        .render_repository()
+        .map_err(|err| format_err!("Invalid repository of mirror '{}' - {err}", mirror.id))
+}
+
 fn mirror_pool_dir(mirror: &MirrorConfig) -> String {
-    let pool_suffix = hex::encode(sha256(mirror.base_dir.as_bytes()));
+    // mirrors sharing a source pool share the pool on the medium as well
+    let source_pool = mirror.pool_dir.as_ref().unwrap_or(&mirror.base_dir);
+    let pool_suffix = hex::encode(sha256(source_pool.as_bytes()));
     format!(".pool_{pool_suffix}")
 }
 
+/// Version of the statefile format written by this version of the tool.
+const MEDIUM_STATE_VERSION: u32 = 1;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 /// State of mirrors on the medium
 pub struct MediumState {
+    /// Version of the statefile format (statefiles without version are version 0).
+    #[serde(default)]
+    pub state_version: u32,
     /// Map of mirror ID to `MirrorInfo`.
     pub mirrors: HashMap<String, MirrorInfo>,
     /// Timestamp of last sync operation.
@@ -70,6 +113,94 @@ pub struct MediumState {
     /// Subscriptions
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub subscriptions: Vec<SubscriptionInfo>,
+    /// UUID of the LUKS volume backing the medium, if encrypted
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub luks_uuid: Option<String>,
+    /// Mirrors which were excluded from the last sync, their data is left as is
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub skipped_mirrors: Vec<String>,
+    /// Map of mirror ID to timestamp of the last sync of its pool. In contrast to `last_sync`,
+    /// not updated when only syncing keys.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub last_pool_sync: HashMap<String, i64>,
+}
+
+/// Progress events emitted while syncing a medium.
+#[derive(Clone, Debug)]
+pub enum SyncEvent {
+    /// Syncing of a mirror started.
+    Starting {
+        mirror_id: String,
+        /// Estimated size of the data missing on the medium
+        estimated_bytes: u64,
+    },
+    /// A file was added to the medium's pool.
+    FileTransferred { path: PathBuf, bytes: u64 },
+    /// Syncing of a mirror finished.
+    MirrorComplete {
+        mirror_id: String,
+        files_added: usize,
+        bytes_added: u64,
+    },
+    /// Syncing of all mirrors finished.
+    Complete {
+        total_files: usize,
+        total_bytes: u64,
+    },
+}
+
+/// Result of syncing a single mirror to a medium.
+pub struct MirrorSyncReport {
+    /// Newly added vs. re-used files
+    pub progress: ProgressReport,
+    /// Statistics of the mirror's pool on the medium
+    pub pool: PoolStats,
+    /// Number of snapshots of the mirror on the medium
+    pub snapshot_count: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Amount of data a sync would transfer for a single mirror.
+pub struct SyncDryRunEntry {
+    /// Number of distinct files missing in the medium's pool
+    pub files: usize,
+    /// Total size of distinct files missing in the medium's pool
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Result of a sync dry-run.
+pub struct SyncDryRunReport {
+    /// Map of mirror ID to `SyncDryRunEntry`.
+    pub mirrors: HashMap<String, SyncDryRunEntry>,
+    /// Number of files missing on the medium over all mirrors
+    pub total_files: usize,
+    /// Total size of files missing on the medium over all mirrors
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Comparison of the newest snapshots of a single mirror on the source and on the medium.
+pub struct MirrorStaleness {
+    /// Whether the source contains snapshots newer than the newest one on the medium
+    pub is_stale: bool,
+    /// Newest snapshot of the mirror on the source
+    pub source_newest: Option<Snapshot>,
+    /// Newest snapshot of the mirror on the medium
+    pub medium_newest: Option<Snapshot>,
+    /// Number of source snapshots newer than the newest one on the medium
+    pub snapshots_behind: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Result of comparing a medium's snapshots to those of the source mirrors.
+pub struct StalenessReport {
+    /// Map of mirror ID to `MirrorStaleness`.
+    pub mirrors: HashMap<String, MirrorStaleness>,
 }
 
 /// Information about the mirrors on a medium.
@@ -84,6 +215,8 @@ pub struct MediumMirrorState {
     pub source_only: HashSet<String>,
     /// Mirrors which are not configured but exist on medium
     pub target_only: HashSet<String>,
+    /// Mirrors which are configured but were excluded from the last sync
+    pub skipped: HashSet<String>,
 }
 
 // helper to derive `MediumMirrorState`
@@ -98,12 +231,19 @@ fn get_mirror_state(config: &MediaConfig, state: &MediumState) -> MediumMirrorSt
         .difference(&config_mirrors)
         .cloned()
         .collect();
+    let skipped_mirrors: HashSet<String> = state
+        .skipped_mirrors
+        .iter()
+        .filter(|id| config_mirrors.contains(*id))
+        .cloned()
+        .collect();
 
     MediumMirrorState {
         synced: synced_mirrors,
         config: config_mirrors,
         source_only: new_mirrors,
         target_only: dropped_mirrors,
+        skipped: skipped_mirrors,
     }
 }
 
@@ -130,13 +270,170 @@ fn load_state(base: &Path) -> Result<Option<MediumState>, Error> {
 
     if statefile.exists() {
         let raw = file_get_contents(&statefile)?;
-        let state: MediumState = serde_json::from_slice(&raw)?;
+        let state = match serde_json::from_slice::<MediumState>(&raw) {
+            Ok(state) if state.state_version == MEDIUM_STATE_VERSION => state,
+            Ok(_) => migrate_state(serde_json::from_slice(&raw)?)?,
+            Err(_) => match try_migrate_legacy_state(&raw) {
+                Ok(state) => state,
+                Err(_) => migrate_state(serde_json::from_slice(&raw)?)?,
+            },
+        };
         Ok(Some(state))
     } else {
         Ok(None)
     }
 }
 
+/// Load the statefile of the medium mounted at `base`.
+///
+/// Statefiles written by older versions or by `proxmox-apt-mirror` are migrated, statefiles
+/// written by newer versions are rejected.
+pub fn read_state(base: &Path) -> Result<MediumState, Error> {
+    load_state(base)?.ok_or_else(|| format_err!("Statefile {:?} doesn't exist.", statefile(base)))
+}
+
+// Helper to migrate a statefile written by an older version of the tool
+fn migrate_state(mut old: Value) -> Result<MediumState, Error> {
+    let version = match old.get("state-version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| format_err!("Invalid statefile version {version}"))?,
+        None => 0,
+    };
+
+    if version > MEDIUM_STATE_VERSION {
+        bail!(
+            "Statefile uses format version {version}, but only versions up to {MEDIUM_STATE_VERSION} are supported - please upgrade."
+        );
+    }
+
+    let map = old
+        .as_object_mut()
+        .ok_or_else(|| format_err!("Invalid statefile - not a JSON object"))?;
+
+    // 0 -> 1: only optional fields were added, no changes needed
+
+    map.insert("state-version".to_string(), MEDIUM_STATE_VERSION.into());
+
+    serde_json::from_value(old).map_err(|err| format_err!("Failed to parse statefile - {err}"))
+}
+
+// Statefile format written by `proxmox-apt-mirror`, the predecessor of this tool:
+//
+// {
+//   "mirrors": {
+//     "<mirror ID>": { "repository": "<line>", "architectures": [..], "pool": "<dir>" }
+//   },
+//   "last_sync": <epoch>
+// }
+//
+// In contrast to the current format, keys use snake_case instead of kebab-case (only affecting
+// `last_sync`), and there are no `state-version`, `subscriptions` or `luks-uuid` fields. Mirror
+// entries lack `extra-components` and `exclude-components`, as all components of the repository
+// line were mirrored.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LegacyMediumState {
+    mirrors: HashMap<String, LegacyMirrorInfo>,
+    last_sync: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LegacyMirrorInfo {
+    repository: String,
+    architectures: Vec<String>,
+    pool: String,
+}
+
+/// Parse a statefile written by `proxmox-apt-mirror` and convert it to the current format.
+///
+/// The converted state is written in the current format on the next sync.
+pub fn try_migrate_legacy_state(raw: &[u8]) -> Result<MediumState, Error> {
+    let legacy: LegacyMediumState = serde_json::from_slice(raw)
+        .map_err(|err| format_err!("Failed to parse legacy statefile - {err}"))?;
+
+    let mirrors = legacy
+        .mirrors
+        .into_iter()
+        .map(|(id, info)| {
+            let info = MirrorInfo {
+                repository: info.repository,
+                architectures: info.architectures,
+                pool: info.pool,
+                extra_components: Vec::new(),
+                exclude_components: Vec::new(),
+            };
+            (id, info)
+        })
+        .collect();
+
+    Ok(MediumState {
+        state_version: MEDIUM_STATE_VERSION,
+        mirrors,
+        last_sync: legacy.last_sync,
+        subscriptions: Vec::new(),
+        luks_uuid: None,
+        skipped_mirrors: Vec::new(),
+        last_pool_sync: HashMap::new(),
+    })
+}
+
+// Helper to get the UUID of the LUKS volume `base` is located on
+fn luks_uuid(base: &Path) -> Result<Option<String>, Error> {
+    let dev = std::fs::metadata(base)?.st_dev();
+    let dm_uuid_path = format!("/sys/dev/block/{}:{}/dm/uuid", major(dev), minor(dev));
+
+    let dm_uuid = match std::fs::read_to_string(&dm_uuid_path) {
+        Ok(dm_uuid) => dm_uuid,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => bail!("Failed to read '{dm_uuid_path}' - {err}"),
+    };
+
+    // opened LUKS volumes use 'CRYPT-LUKS<version>-<UUID without dashes>-<name>'
+    let Some((hex, _name)) = dm_uuid
+        .trim()
+        .strip_prefix("CRYPT-LUKS2-")
+        .or_else(|| dm_uuid.trim().strip_prefix("CRYPT-LUKS1-"))
+        .and_then(|rest| rest.split_once('-'))
+    else {
+        return Ok(None);
+    };
+
+    if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )))
+}
+
+// Helper to verify (or initially record) the LUKS UUID of the medium
+fn check_luks_uuid(base: &Path, state: &mut MediumState) -> Result<(), Error> {
+    let current = luks_uuid(base)?;
+
+    if let Some(recorded) = &state.luks_uuid {
+        if current.as_ref() != Some(recorded) {
+            bail!(
+                "Medium is not backed by the recorded LUKS volume '{recorded}' (found: {}) - refusing to sync.",
+                current.as_deref().unwrap_or("none")
+            );
+        }
+    } else if let Some(current) = current {
+        println!("Recording LUKS UUID '{current}' of medium.");
+        state.luks_uuid = Some(current);
+    }
+
+    Ok(())
+}
+
 // Helper to write statefile
 fn write_state(_lock: &ConfigLockGuard, base: &Path, state: &MediumState) -> Result<(), Error> {
     replace_file(
@@ -168,7 +465,7 @@ pub fn list_snapshots(medium_base: &Path, mirror: &str) -> Result<Vec<Snapshot>,
                 return Ok(());
             }
 
-            list.push(snapshot.parse()?);
+            list.push(Snapshot::from_str_strict(snapshot)?);
 
             Ok(())
         },
@@ -179,25 +476,203 @@ pub fn list_snapshots(medium_base: &Path, mirror: &str) -> Result<Vec<Snapshot>,
     Ok(list)
 }
 
+// Helper to get the path of the file storing the snapshot a mirror on the medium was rolled back
+// to. Must not be inside the mirror's pool link dir, as GC would treat it as orphan.
+fn current_snapshot_file(medium_base: &Path, mirror: &str) -> PathBuf {
+    medium_base.join(format!(".{mirror}.current-snapshot"))
+}
+
+/// Snapshot a mirror on the medium was rolled back to using [rollback_to_snapshot], if any.
+pub fn current_snapshot(medium_base: &Path, mirror: &str) -> Result<Option<Snapshot>, Error> {
+    match proxmox_sys::fs::file_read_optional_string(current_snapshot_file(medium_base, mirror))? {
+        Some(raw) => Ok(Some(Snapshot::from_str_strict(raw.trim())?)),
+        None => Ok(None),
+    }
+}
+
+/// Snapshot to use when accessing a mirror on the medium - the one it was rolled back to if that
+/// still exists, the newest one otherwise.
+pub fn default_snapshot(medium_base: &Path, mirror: &str) -> Result<Option<Snapshot>, Error> {
+    let snapshots = list_snapshots(medium_base, mirror)?;
+
+    if let Some(current) = current_snapshot(medium_base, mirror)? {
+        if snapshots.contains(&current) {
+            return Ok(Some(current));
+        }
+        eprintln!(
+            "Snapshot {current} of mirror '{mirror}' no longer exists on medium, using newest snapshot."
+        );
+    }
+
+    Ok(snapshots.last().copied())
+}
+
+// Helper to check that a snapshot on the medium contains parseable package indices. Missing
+// package files are only reported, as they might be excluded by the mirror's package filters.
+fn check_snapshot_complete(snapshot_dir: &Path) -> Result<(), Error> {
+    let dists = snapshot_dir.join("dists");
+    if !dists.exists() {
+        bail!("Snapshot {snapshot_dir:?} doesn't contain a 'dists' directory.");
+    }
+
+    let mut indices = 0;
+    let mut missing = 0;
+    for entry in WalkDir::new(&dists) {
+        let entry = entry?;
+        if !entry.file_type().is_file() || entry.file_name() != "Packages" {
+            continue;
+        }
+
+        let raw = file_get_contents(entry.path())?;
+        let index: PackagesFile = raw[..]
+            .try_into()
+            .map_err(|err| format_err!("Failed to parse {:?} - {err}", entry.path()))?;
+
+        indices += 1;
+        missing += index
+            .files
+            .iter()
+            .filter(|package| !snapshot_dir.join(&package.file).exists())
+            .count();
+    }
+
+    if indices == 0 {
+        bail!("Snapshot {snapshot_dir:?} doesn't contain any package index.");
+    }
+    if missing > 0 {
+        eprintln!(
+            "{missing} package file references of snapshot {snapshot_dir:?} can't be resolved - this is expected if package filters are configured."
+        );
+    }
+
+    Ok(())
+}
+
+/// Roll back a mirror on the medium to `snapshot`, which will then be used instead of the newest
+/// snapshot when generating repository lines on the target system.
+///
+/// The snapshot must exist on the medium and contain valid package indices.
+pub fn rollback_to_snapshot(
+    medium: &MediaConfig,
+    mirror_id: &str,
+    snapshot: &Snapshot,
+) -> Result<(), Error> {
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let _lock = lock(medium_base)?;
+
+    let state = load_state(medium_base)?
+        .ok_or_else(|| format_err!("Cannot roll back empty medium - no statefile found."))?;
+    if !state.mirrors.contains_key(mirror_id) {
+        bail!("Mirror '{mirror_id}' not found on medium.");
+    }
+    if !list_snapshots(medium_base, mirror_id)?.contains(snapshot) {
+        bail!("Snapshot {snapshot} of mirror '{mirror_id}' not found on medium.");
+    }
+
+    check_snapshot_complete(&medium_base.join(mirror_id).join(snapshot.to_string()))?;
+
+    replace_file(
+        current_snapshot_file(medium_base, mirror_id),
+        format!("{snapshot}\n").as_bytes(),
+        CreateOptions::default(),
+        true,
+    )
+}
+
+/// Undo a [rollback_to_snapshot], using the newest snapshot of the mirror again.
+pub fn clear_rollback(medium: &MediaConfig, mirror_id: &str) -> Result<(), Error> {
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let _lock = lock(medium_base)?;
+
+    remove_current_snapshot_file(medium_base, mirror_id)
+}
+
+// Helper to remove the rollback pointer of a mirror, if it exists.
+fn remove_current_snapshot_file(medium_base: &Path, mirror_id: &str) -> Result<(), Error> {
+    let file = current_snapshot_file(medium_base, mirror_id);
+    match std::fs::remove_file(&file) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => bail!("Failed to remove {file:?} - {err}"),
+    }
+}
+
+/// Calculate number and total size of files of a mirror's snapshot on a medium, counting
+/// hardlinked files once.
+pub fn snapshot_usage(
+    medium_base: &Path,
+    mirror: &str,
+    snapshot: &Snapshot,
+) -> Result<(usize, u64), Error> {
+    let mut snapshot_path = medium_base.to_path_buf();
+    snapshot_path.push(mirror);
+    snapshot_path.push(snapshot.to_string());
+
+    crate::mirror::snapshot_usage(&snapshot_path)
+}
+
 /// Generate a repository snippet for a selection of mirrors on a medium.
+///
+/// Entries need to be joined using [SourcesFormat::separator].
 pub fn generate_repo_snippet(
     medium_base: &Path,
     repositories: &HashMap<String, (&MirrorInfo, Snapshot)>,
+    format: SourcesFormat,
 ) -> Result<Vec<String>, Error> {
     let mut res = Vec::new();
     for (mirror_id, (mirror_info, snapshot)) in repositories {
-        res.push(generate_repo_file_line(
+        res.push(generate_repo_file_entry(
             medium_base,
             mirror_id,
             mirror_info,
             snapshot,
+            format,
         )?);
     }
     Ok(res)
 }
 
+/// Write one `<mirror-id>.list` (or `<mirror-id>.sources`) file per mirror into `dest_dir`,
+/// referencing the given snapshot of each mirror on the medium. Existing files will be overwritten.
+pub fn generate_apt_sources_dir(
+    medium_base: &Path,
+    state: &MediumState,
+    snapshots: &HashMap<String, Snapshot>,
+    dest_dir: &Path,
+    format: SourcesFormat,
+) -> Result<(), Error> {
+    create_path(dest_dir, None, None)?;
+
+    for (mirror_id, snapshot) in snapshots {
+        let mirror_info = state
+            .mirrors
+            .get(mirror_id)
+            .ok_or_else(|| format_err!("Mirror '{mirror_id}' not found on medium."))?;
+        let line = generate_repo_file_entry(medium_base, mirror_id, mirror_info, snapshot, format)?;
+
+        let file = dest_dir.join(format!("{mirror_id}.{}", format.file_extension()));
+        replace_file(
+            &file,
+            format!("{line}\n").as_bytes(),
+            CreateOptions::default(),
+            true,
+        )
+        .map_err(|err| format_err!("Failed to write {file:?} - {err}"))?;
+    }
+
+    Ok(())
+}
+
 /// Run garbage collection on all mirrors on a medium.
-pub fn gc(medium: &crate::config::MediaConfig) -> Result<(), Error> {
+pub fn gc(medium: &crate::config::MediaConfig) -> Result<GcReport, Error> {
     let medium_base = Path::new(&medium.mountpoint);
     if !medium_base.exists() {
         bail!("Medium mountpoint doesn't exist.");
@@ -214,8 +689,7 @@ pub fn gc(medium: &crate::config::MediaConfig) -> Result<(), Error> {
         epoch_to_rfc3339_utc(state.last_sync)?
     );
 
-    let mut total_count = 0usize;
-    let mut total_bytes = 0_u64;
+    let mut total = GcReport::default();
 
     for (id, info) in state.mirrors {
         println!("\nGC for '{id}'");
@@ -228,18 +702,23 @@ pub fn gc(medium: &crate::config::MediaConfig) -> Result<(), Error> {
         if mirror_base.exists() {
             let pool = Pool::open(&mirror_base, &mirror_pool)?;
             let locked = pool.lock()?;
-            let (count, bytes) = locked.gc()?;
-            println!("removed {count} files ({bytes}b)");
-            total_count += count;
-            total_bytes += bytes;
+            let report = locked.gc()?;
+            println!(
+                "removed {} files ({}b)",
+                report.files_removed, report.bytes_freed
+            );
+            total.merge(&report);
         } else {
             println!("{mirror_base:?} doesn't exist, skipping '{}'", id);
         };
     }
 
-    println!("GC removed {total_count} files ({total_bytes}b)");
+    println!(
+        "GC removed {} files ({}b)",
+        total.files_removed, total.bytes_freed
+    );
 
-    Ok(())
+    Ok(total)
 }
 
 /// Get `MediumState` and `MediumMirrorState` for a given medium.
@@ -258,6 +737,171 @@ pub fn status(
     Ok((state, mirror_state))
 }
 
+// Helper to escape text for inclusion in HTML
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const CATALOG_STYLE: &str = r#"body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }
+th { background: #eee; }
+#search { font-size: 1.1em; padding: 0.3em; width: 30em; }"#;
+
+const CATALOG_SCRIPT: &str = r#"document.getElementById('search').addEventListener('input', function() {
+  var term = this.value.toLowerCase();
+  document.querySelectorAll('table.packages tbody tr').forEach(function(row) {
+    var name = row.cells[0].textContent.toLowerCase();
+    row.style.display = name.indexOf(term) >= 0 ? '' : 'none';
+  });
+});"#;
+
+/// Generate a self-contained HTML catalog of the mirrors on a medium, listing their snapshots and
+/// a searchable table of the packages contained in them.
+///
+/// Packages are read from the `Packages` indices of all snapshots, each package version is listed
+/// once per mirror together with the snapshots containing it.
+pub fn generate_catalog(
+    medium_base: &Path,
+    state: &MediumState,
+    output_path: &Path,
+) -> Result<(), Error> {
+    let mut mirror_ids: Vec<&String> = state.mirrors.keys().collect();
+    mirror_ids.sort_unstable();
+
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )?;
+    writeln!(html, "<title>Offline Mirror Catalog</title>")?;
+    writeln!(html, "<style>\n{CATALOG_STYLE}\n</style>\n</head>\n<body>")?;
+    writeln!(html, "<h1>Offline Mirror Catalog</h1>")?;
+    writeln!(
+        html,
+        "<p>Last sync: {}</p>",
+        epoch_to_rfc3339_utc(state.last_sync)?
+    )?;
+    writeln!(
+        html,
+        "<p><input id=\"search\" type=\"search\" placeholder=\"Search package name\"></p>"
+    )?;
+
+    for id in mirror_ids {
+        let info = &state.mirrors[id];
+        writeln!(html, "<h2>Mirror '{}'</h2>", html_escape(id))?;
+        writeln!(
+            html,
+            "<p>Repository: <code>{}</code></p>",
+            html_escape(&info.repository)
+        )?;
+
+        let mut snapshots = list_snapshots(medium_base, id)?;
+        snapshots.sort();
+        if snapshots.is_empty() {
+            writeln!(html, "<p>No snapshots.</p>")?;
+            continue;
+        }
+
+        writeln!(html, "<p>Snapshots:</p>\n<ul>")?;
+        for snapshot in &snapshots {
+            writeln!(html, "<li>{snapshot}</li>")?;
+        }
+        writeln!(html, "</ul>")?;
+
+        let mut packages: BTreeMap<(String, String, String), Vec<Snapshot>> = BTreeMap::new();
+        for snapshot in &snapshots {
+            let dists = medium_base
+                .join(id)
+                .join(snapshot.to_string())
+                .join("dists");
+            if !dists.exists() {
+                continue;
+            }
+
+            for index_entry in WalkDir::new(&dists) {
+                let index_entry = index_entry?;
+                if !index_entry.file_type().is_file() || index_entry.file_name() != "Packages" {
+                    continue;
+                }
+
+                let raw = file_get_contents(index_entry.path())?;
+                let index: PackagesFile = match raw[..].try_into() {
+                    Ok(index) => index,
+                    Err(err) => {
+                        eprintln!("Failed to parse {:?} - {err}", index_entry.path());
+                        continue;
+                    }
+                };
+
+                for entry in index.files {
+                    let found_in = packages
+                        .entry((entry.package, entry.version, entry.architecture))
+                        .or_default();
+                    // `all` packages are referenced by the indices of each architecture
+                    if found_in.last() != Some(snapshot) {
+                        found_in.push(*snapshot);
+                    }
+                }
+            }
+        }
+
+        writeln!(html, "<table class=\"packages\">")?;
+        writeln!(
+            html,
+            "<thead><tr><th>Package</th><th>Version</th><th>Architecture</th><th>Snapshots</th></tr></thead>\n<tbody>"
+        )?;
+        for ((package, version, architecture), found_in) in packages {
+            let found_in = match (found_in.first(), found_in.last()) {
+                (Some(first), Some(last)) if first == last => first.to_string(),
+                (Some(first), Some(last)) => format!("{} ({first}..{last})", found_in.len()),
+                _ => String::new(),
+            };
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{found_in}</td></tr>",
+                html_escape(&package),
+                html_escape(&version),
+                html_escape(&architecture),
+            )?;
+        }
+        writeln!(html, "</tbody>\n</table>")?;
+    }
+
+    writeln!(
+        html,
+        "<script>\n{CATALOG_SCRIPT}\n</script>\n</body>\n</html>"
+    )?;
+
+    replace_file(output_path, html.as_bytes(), CreateOptions::default(), true)
+}
+
+/// Get the recorded and the currently detected LUKS UUID of a given medium.
+pub fn format_info(
+    medium: &crate::config::MediaConfig,
+) -> Result<(Option<String>, Option<String>), Error> {
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let recorded = load_state(medium_base)?.and_then(|state| state.luks_uuid);
+    let current = luks_uuid(medium_base)?;
+
+    Ok((recorded, current))
+}
+
 /// Sync only subscription keys to medium
 pub fn sync_keys(
     medium: &crate::config::MediaConfig,
@@ -282,13 +926,19 @@ pub fn sync_keys(
         None => {
             println!("Creating new statefile..");
             MediumState {
+                state_version: MEDIUM_STATE_VERSION,
                 mirrors: HashMap::new(),
                 last_sync: 0,
                 subscriptions: vec![],
+                luks_uuid: None,
+                skipped_mirrors: Vec::new(),
+                last_pool_sync: HashMap::new(),
             }
         }
     };
 
+    check_luks_uuid(medium_base, &mut state)?;
+
     state.last_sync = epoch_i64();
     println!("Sync timestamp: {}", epoch_to_rfc3339_utc(state.last_sync)?);
 
@@ -303,8 +953,10 @@ pub fn sync_keys(
 pub fn sync(
     medium: &crate::config::MediaConfig,
     mirrors: Vec<MirrorConfig>,
+    exclude_mirrors: &[String],
     subscriptions: Vec<SubscriptionInfo>,
-) -> Result<(), Error> {
+    on_progress: Option<Box<dyn Fn(SyncEvent) + Send>>,
+) -> Result<HashMap<String, MirrorSyncReport>, Error> {
     println!(
         "Syncing {} mirrors {:?} to medium '{}' ({:?})",
         &medium.mirrors.len(),
@@ -317,6 +969,8 @@ pub fn sync(
         bail!("Number of mirrors in config and sync request don't match.");
     }
 
+    medium.validate()?;
+
     let medium_base = Path::new(&medium.mountpoint);
     if !medium_base.exists() {
         bail!("Medium mountpoint doesn't exist.");
@@ -336,13 +990,56 @@ pub fn sync(
         None => {
             println!("Creating new statefile..");
             MediumState {
+                state_version: MEDIUM_STATE_VERSION,
                 mirrors: HashMap::new(),
                 last_sync: 0,
                 subscriptions: vec![],
+                luks_uuid: None,
+                skipped_mirrors: Vec::new(),
+                last_pool_sync: HashMap::new(),
             }
         }
     };
 
+    check_luks_uuid(medium_base, &mut state)?;
+
+    let fs_constraints = detect_fs_constraints(medium_base)?;
+    if fs_constraints.name_max < SHA256_NAME_LEN {
+        bail!(
+            "File system of medium only supports file names up to {} bytes, at least {SHA256_NAME_LEN} are required.",
+            fs_constraints.name_max
+        );
+    }
+    if fs_constraints.forbids_colons {
+        bail!(
+            "File system of medium doesn't allow ':' in file names, which is part of every snapshot name - use a different file system (e.g. ext4)."
+        );
+    }
+    if fs_constraints.is_restricted() {
+        eprintln!(
+            "Warning: file system of medium only supports file names up to {} bytes, syncing files with longer names will fail.",
+            fs_constraints.name_max
+        );
+        if !fs_constraints.supports_sha512_names() {
+            eprintln!("Warning: storing pool files by their SHA-256 checksum only.");
+        }
+    }
+    if !fs_constraints.supports_sha512_names() {
+        // files of such pools might lack a SHA-256 checksum
+        for mirror in &mirrors {
+            if mirror.hash_preference == Some(HashPreference::Sha512Only) {
+                bail!(
+                    "Mirror '{}' stores pool files by their SHA-512 checksum only, which the file system of the medium doesn't support.",
+                    mirror.id
+                );
+            }
+        }
+    }
+
+    // files of mirrors whose pool was synced before only need to be checked if changed since
+    // then, unless all files should be verified anyway
+    let previous_pool_sync = std::mem::take(&mut state.last_pool_sync);
+
     state.last_sync = epoch_i64();
     println!("Sync timestamp: {}", epoch_to_rfc3339_utc(state.last_sync)?);
 
@@ -367,6 +1064,25 @@ pub fn sync(
         );
     }
 
+    for excluded in exclude_mirrors {
+        if !mirror_state.config.contains(excluded) {
+            bail!(
+                "Cannot exclude mirror '{excluded}' - not configured for medium '{}'.",
+                medium.id
+            );
+        }
+    }
+    let (mirrors, skipped): (Vec<MirrorConfig>, Vec<MirrorConfig>) = mirrors
+        .into_iter()
+        .partition(|mirror| !exclude_mirrors.contains(&mirror.id));
+    if !skipped.is_empty() {
+        println!(
+            "Skipping {} excluded mirror(s), leaving their data on the medium untouched: {:?}",
+            skipped.len(),
+            exclude_mirrors,
+        );
+    }
+
     if !mirror_state.source_only.is_empty() {
         println!(
             "Adding {} new mirror(s) to target medium: {:?}",
@@ -382,14 +1098,58 @@ pub fn sync(
         );
     }
 
+    let estimate = calculate_sync_size(
+        medium_base,
+        &pools,
+        &mirrors,
+        medium.max_snapshots_per_mirror,
+    )?;
+    let required = estimate.total_bytes;
+    let available = available_space(medium)?;
+    if required > available {
+        bail!(
+            "Not enough space on medium - {required}b required, but only {available}b available."
+        );
+    }
+
+    let mut infos = mirrors
+        .iter()
+        .map(|mirror| Ok((mirror.id.clone(), MirrorInfo::try_from(mirror)?)))
+        .collect::<Result<HashMap<String, MirrorInfo>, Error>>()?;
+
     println!("\nStarting sync now!");
-    state.mirrors = HashMap::new();
+    let previous_mirrors = std::mem::take(&mut state.mirrors);
+    state.skipped_mirrors = Vec::with_capacity(skipped.len());
+    for mirror in skipped {
+        // keep info of previously synced mirrors, so that their data is not treated as orphaned
+        if let Some(info) = previous_mirrors.get(&mirror.id) {
+            state.mirrors.insert(mirror.id.clone(), info.clone());
+            if let Some(last_pool_sync) = previous_pool_sync.get(&mirror.id) {
+                state
+                    .last_pool_sync
+                    .insert(mirror.id.clone(), *last_pool_sync);
+            }
+        }
+        state.skipped_mirrors.push(mirror.id);
+    }
+    state.skipped_mirrors.sort();
+    let mut reports = HashMap::new();
 
     for mirror in mirrors.into_iter() {
         let mut mirror_base = medium_base.to_path_buf();
         mirror_base.push(Path::new(&mirror.id));
 
         println!("\nSyncing '{}' to {mirror_base:?}..", mirror.id);
+        if let Some(on_progress) = &on_progress {
+            on_progress(SyncEvent::Starting {
+                mirror_id: mirror.id.clone(),
+                estimated_bytes: estimate
+                    .mirrors
+                    .get(&mirror.id)
+                    .map(|entry| entry.bytes)
+                    .unwrap_or_default(),
+            });
+        }
 
         let mut mirror_pool = medium_base.to_path_buf();
         let pool_dir = match pools.get(&mirror.id) {
@@ -398,16 +1158,76 @@ pub fn sync(
         };
         mirror_pool.push(pool_dir);
 
-        let target_pool = if mirror_base.exists() {
-            Pool::open(&mirror_base, &mirror_pool)?
-        } else {
-            Pool::create(&mirror_base, &mirror_pool)?
+        let mut target_pool = Pool::open_or_create(&mirror_base, &mirror_pool)?;
+        if !fs_constraints.supports_sha512_names() {
+            target_pool.set_hash_preference(HashPreference::Sha256Only);
+        }
+
+        let pruned = match medium.max_snapshots_per_mirror {
+            Some(max_snapshots) => snapshots_over_limit(medium_base, &mirror, max_snapshots)?,
+            None => Vec::new(),
         };
+        if !pruned.is_empty() {
+            println!(
+                "Pruning {} snapshot(s) exceeding the limit of {} snapshots from medium: {pruned:?}",
+                pruned.len(),
+                medium.max_snapshots_per_mirror.unwrap_or_default(),
+            );
+        }
+
+        let since = previous_pool_sync
+            .get(&mirror.id)
+            .copied()
+            .filter(|_| !medium.verify && previous_mirrors.contains_key(&mirror.id));
+        if let Some(since) = since {
+            println!(
+                "Only checking files changed since last sync ({})",
+                epoch_to_rfc3339_utc(since)?
+            );
+        }
+
+        let on_file_added = on_progress.as_deref().map(|on_progress| {
+            move |path: &Path, bytes: u64| {
+                on_progress(SyncEvent::FileTransferred {
+                    path: path.to_path_buf(),
+                    bytes,
+                })
+            }
+        });
 
         let source_pool: Pool = pool(&mirror)?;
-        source_pool.lock()?.sync_pool(&target_pool, medium.verify)?;
+        let progress = source_pool.lock_shared()?.sync_pool(
+            &target_pool,
+            medium.verify,
+            medium.sync_strategy(),
+            &pruned,
+            since,
+            on_file_added.as_ref().map(|f| f as &dyn Fn(&Path, u64)),
+        )?;
+        if let Some(on_progress) = &on_progress {
+            on_progress(SyncEvent::MirrorComplete {
+                mirror_id: mirror.id.clone(),
+                files_added: progress.new_files,
+                bytes_added: progress.new_bytes as u64,
+            });
+        }
+        let pool_stats = target_pool.lock()?.stats()?;
+        let snapshot_count = list_snapshots(medium_base, &mirror.id)?.len();
+        reports.insert(
+            mirror.id.clone(),
+            MirrorSyncReport {
+                progress,
+                pool: pool_stats,
+                snapshot_count,
+            },
+        );
 
-        state.mirrors.insert(mirror.id.clone(), mirror.into());
+        if let Some(info) = infos.remove(&mirror.id) {
+            state.mirrors.insert(mirror.id.clone(), info);
+        }
+        state
+            .last_pool_sync
+            .insert(mirror.id.clone(), state.last_sync);
     }
 
     if !mirror_state.target_only.is_empty() {
@@ -427,6 +1247,7 @@ pub fn sync(
                     pool_dir.push(pool);
                     let pool = Pool::open(&mirror_base, &pool_dir)?;
                     pool.lock()?.destroy()?;
+                    remove_current_snapshot_file(medium_base, &dropped)?;
                 }
                 None => bail!(
                     "No pool information for previously synced, but no longer configured mirror '{dropped}'"
@@ -439,7 +1260,325 @@ pub fn sync(
     state.subscriptions = subscriptions;
     write_state(&lock, medium_base, &state)?;
 
-    Ok(())
+    if let Some(on_progress) = &on_progress {
+        on_progress(SyncEvent::Complete {
+            total_files: reports
+                .values()
+                .map(|report| report.progress.new_files)
+                .sum(),
+            total_bytes: reports
+                .values()
+                .map(|report| report.progress.new_bytes as u64)
+                .sum(),
+        });
+    }
+
+    Ok(reports)
+}
+
+/// Calculate the amount of data a sync of `mirrors` to `medium` would transfer, without writing
+/// anything to the medium.
+pub fn sync_dry_run(
+    medium: &crate::config::MediaConfig,
+    mirrors: Vec<MirrorConfig>,
+) -> Result<SyncDryRunReport, Error> {
+    if mirrors.len() != medium.mirrors.len() {
+        bail!("Number of mirrors in config and sync request don't match.");
+    }
+
+    medium.validate()?;
+
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let _lock = lock(medium_base)?;
+
+    let pools: HashMap<String, String> = match load_state(medium_base)? {
+        Some(state) => state
+            .mirrors
+            .into_iter()
+            .map(|(id, info)| (id, info.pool))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    calculate_sync_size(
+        medium_base,
+        &pools,
+        &mirrors,
+        medium.max_snapshots_per_mirror,
+    )
+}
+
+/// Compare the newest snapshots on the medium with those of the source mirrors.
+///
+/// In contrast to `diff`, this only looks at snapshot names and not at the pool contents.
+pub fn compare_timestamps(
+    medium: &MediaConfig,
+    mirrors: &[MirrorConfig],
+) -> Result<StalenessReport, Error> {
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let mut report = StalenessReport::default();
+
+    for mirror in mirrors {
+        let source_snapshots = crate::mirror::list_complete_snapshots(mirror)?;
+        let medium_snapshots = if medium_base.join(&mirror.id).exists() {
+            list_snapshots(medium_base, &mirror.id)?
+        } else {
+            Vec::new()
+        };
+
+        let source_newest = source_snapshots.last().copied();
+        let medium_newest = medium_snapshots.last().copied();
+
+        let snapshots_behind = match medium_newest {
+            Some(medium_newest) => source_snapshots
+                .iter()
+                .filter(|snapshot| **snapshot > medium_newest)
+                .count(),
+            None => source_snapshots.len(),
+        };
+
+        report.mirrors.insert(
+            mirror.id.clone(),
+            MirrorStaleness {
+                is_stale: snapshots_behind > 0,
+                source_newest,
+                medium_newest,
+                snapshots_behind,
+            },
+        );
+    }
+
+    Ok(report)
+}
+
+// Helper to determine the oldest snapshots of a mirror exceeding the medium's snapshot limit, as
+// paths relative to the mirror's link dir. The snapshot currently used on the medium is kept.
+fn snapshots_over_limit(
+    medium_base: &Path,
+    mirror: &MirrorConfig,
+    max_snapshots: usize,
+) -> Result<Vec<PathBuf>, Error> {
+    let current = current_snapshot(medium_base, &mirror.id)?;
+
+    let mut snapshots: Vec<Snapshot> = crate::mirror::list_snapshots(mirror)?
+        .into_iter()
+        .filter(|(_snapshot, state)| *state == SnapshotState::Complete)
+        .map(|(snapshot, _state)| snapshot)
+        .collect();
+    snapshots.sort();
+
+    let excess = snapshots.len().saturating_sub(max_snapshots);
+
+    Ok(snapshots
+        .into_iter()
+        .take(excess)
+        .filter(|snapshot| Some(*snapshot) != current)
+        .map(|snapshot| PathBuf::from(snapshot.to_string()))
+        .collect())
+}
+
+// Helper to calculate the data missing on the medium, should be called with the medium locked.
+// Snapshots which are pruned by the sync due to `max_snapshots` are not counted.
+fn calculate_sync_size(
+    medium_base: &Path,
+    pools: &HashMap<String, String>,
+    mirrors: &[MirrorConfig],
+    max_snapshots: Option<usize>,
+) -> Result<SyncDryRunReport, Error> {
+    let mut report = SyncDryRunReport::default();
+
+    for mirror in mirrors {
+        let mut mirror_base = medium_base.to_path_buf();
+        mirror_base.push(Path::new(&mirror.id));
+
+        let mut mirror_pool = medium_base.to_path_buf();
+        let pool_dir = match pools.get(&mirror.id) {
+            Some(pool_dir) => pool_dir.to_owned(),
+            None => mirror_pool_dir(&mirror),
+        };
+        mirror_pool.push(pool_dir);
+
+        let source_pool: Pool = pool(mirror)?;
+        let target_pool = if mirror_base.exists() && mirror_pool.exists() {
+            Some(Pool::open(&mirror_base, &mirror_pool)?)
+        } else {
+            None
+        };
+        let pruned = match max_snapshots {
+            Some(max_snapshots) => snapshots_over_limit(medium_base, mirror, max_snapshots)?,
+            None => Vec::new(),
+        };
+        let (files, bytes) = source_pool
+            .lock_shared()?
+            .missing_in(target_pool.as_ref(), &pruned)?;
+
+        let entry = SyncDryRunEntry { files, bytes };
+        report.total_files += entry.files;
+        report.total_bytes += entry.bytes;
+        report.mirrors.insert(mirror.id.clone(), entry);
+    }
+
+    Ok(report)
+}
+
+/// Available space on the filesystem backing the medium.
+pub fn available_space(medium: &MediaConfig) -> Result<u64, Error> {
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let stat = statvfs(medium_base)
+        .map_err(|err| format_err!("Failed to query filesystem of {medium_base:?} - {err}"))?;
+
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+/// File name length supported by common Linux file systems.
+const EXPECTED_NAME_MAX: u64 = 255;
+
+/// Length of hex-encoded SHA-512 checksum file names in a pool.
+const SHA512_NAME_LEN: u64 = 128;
+
+/// Length of hex-encoded SHA-256 checksum file names in a pool.
+const SHA256_NAME_LEN: u64 = 64;
+
+/// Magic number of exFAT file systems, not provided by `nix`.
+const EXFAT_SUPER_MAGIC: i64 = 0x2011_bab0;
+
+/// Constraints of the file system backing a path.
+#[derive(Clone, Copy, Debug)]
+pub struct FsConstraints {
+    /// Maximum length of a single file name in bytes
+    pub name_max: u64,
+    /// Whether `:` and other characters are forbidden in file names, as on FAT file systems
+    pub forbids_colons: bool,
+}
+
+impl FsConstraints {
+    /// Whether file names are more limited than on common Linux file systems, e.g. on some FAT
+    /// variants.
+    pub fn is_restricted(&self) -> bool {
+        self.name_max < EXPECTED_NAME_MAX
+    }
+
+    /// Whether pool files can be stored by their SHA-512 checksum.
+    pub fn supports_sha512_names(&self) -> bool {
+        self.name_max >= SHA512_NAME_LEN
+    }
+}
+
+/// Detect the constraints of the file system backing `path`.
+pub fn detect_fs_constraints(path: &Path) -> Result<FsConstraints, Error> {
+    let stat = statvfs(path)
+        .map_err(|err| format_err!("Failed to query filesystem of {path:?} - {err}"))?;
+    let fs_type = statfs(path)
+        .map_err(|err| format_err!("Failed to query filesystem of {path:?} - {err}"))?
+        .filesystem_type();
+
+    Ok(FsConstraints {
+        name_max: stat.name_max() as u64,
+        forbids_colons: fs_type == MSDOS_SUPER_MAGIC || fs_type.0 as i64 == EXFAT_SUPER_MAGIC,
+    })
+}
+
+/// Quick overview of the space used on the medium's file system and the approximate number of
+/// files in each mirror's pool, without locking or walking the pools.
+pub fn approximate_usage(medium: &MediaConfig) -> Result<(u64, HashMap<String, usize>), Error> {
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let state = match load_state(medium_base)? {
+        Some(state) => state,
+        None => return Ok((0, HashMap::new())),
+    };
+
+    let mut used = 0;
+    let mut file_counts = HashMap::new();
+    for (id, info) in state.mirrors {
+        let mirror_base = medium_base.join(&id);
+        let pool_dir = medium_base.join(&info.pool);
+        if !mirror_base.exists() || !pool_dir.exists() {
+            continue;
+        }
+
+        let pool = Pool::open(&mirror_base, &pool_dir)?;
+        // all pools are located on the medium's file system
+        used = pool.approximate_pool_size()?;
+        file_counts.insert(id, pool.pool_file_count()?);
+    }
+
+    Ok((used, file_counts))
+}
+
+/// Space used by the pool files of all mirrors synced to the medium.
+pub fn used_space(medium: &MediaConfig) -> Result<u64, Error> {
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let _lock = lock(medium_base)?;
+    let state = match load_state(medium_base)? {
+        Some(state) => state,
+        None => return Ok(0),
+    };
+
+    let mut inodes = HashSet::new();
+    let mut used = 0;
+    for info in state.mirrors.values() {
+        let pool_dir = medium_base.join(&info.pool);
+        if !pool_dir.exists() {
+            continue;
+        }
+        for entry in WalkDir::new(pool_dir) {
+            let meta = entry?.metadata()?;
+            if meta.is_file() && inodes.insert(meta.st_ino()) {
+                used += meta.st_size();
+            }
+        }
+    }
+
+    Ok(used)
+}
+
+/// Storage efficiency of the pools of all mirrors synced to the medium.
+pub fn dedup_stats(medium: &MediaConfig) -> Result<HashMap<String, DeduplicationStats>, Error> {
+    let medium_base = Path::new(&medium.mountpoint);
+    if !medium_base.exists() {
+        bail!("Medium mountpoint doesn't exist.");
+    }
+
+    let _lock = lock(medium_base)?;
+    let state = match load_state(medium_base)? {
+        Some(state) => state,
+        None => return Ok(HashMap::new()),
+    };
+
+    let mut res = HashMap::new();
+    for (id, info) in state.mirrors {
+        let mirror_base = medium_base.join(&id);
+        let pool_dir = medium_base.join(&info.pool);
+        if !mirror_base.exists() || !pool_dir.exists() {
+            continue;
+        }
+
+        let pool = Pool::open(&mirror_base, &pool_dir)?;
+        let stats = pool.lock()?.compute_dedup_ratio()?;
+        res.insert(id, stats);
+    }
+
+    Ok(res)
 }
 
 /// Sync medium's content according to config.
@@ -480,9 +1619,9 @@ pub fn diff(
 
                 let size = meta.st_size();
                 if added {
-                    diff.added.paths.push((file, size));
+                    diff.added.push(file, size, meta.st_ino());
                 } else {
-                    diff.removed.paths.push((file, size));
+                    diff.removed.push(file, size, meta.st_ino());
                 }
                 diff
             })
@@ -1,8 +1,13 @@
 use std::{
+    cell::RefCell,
     cmp::max,
-    collections::HashMap,
-    io::Read,
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::CString,
+    fs::File,
+    io::{Read, Write},
+    os::linux::fs::MetadataExt,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use anyhow::{Error, bail, format_err};
@@ -11,14 +16,21 @@ use globset::{Glob, GlobSet, GlobSetBuilder};
 use nix::libc;
 use proxmox_http::{HttpClient, HttpOptions, ProxyConfig, client::sync::Client};
 use proxmox_schema::{ApiType, Schema};
-use proxmox_sys::fs::file_get_contents;
+use proxmox_sys::fs::{CreateOptions, file_get_contents, replace_file};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use walkdir::WalkDir;
 
 use crate::{
-    FetchResult, Progress,
-    config::{MirrorConfig, SkipConfig, SubscriptionKey, WeakCryptoConfig},
+    FetchResult, Progress, ProgressReport, SnapshotCreationSummary, Timings, adjust_components,
+    config::{MirrorConfig, SigType, SkipConfig, SubscriptionKey, WeakCryptoConfig},
     convert_repo_line,
-    pool::Pool,
-    types::{Diff, SNAPSHOT_REGEX, Snapshot},
+    pool::{Pool, PoolLockGuard},
+    types::{
+        CompactReport, CopyReport, DeduplicationStats, Diff, GcReport, ImportStats, MirrorError,
+        PackageMatch, PoolCheckReport, PoolStats, PreflightIssue, SNAPSHOT_DIR_REGEX, ScrubReport,
+        SizeEstimate, Snapshot, SnapshotState, SnapshotSummary, VerifyLinksReport,
+    },
 };
 
 use proxmox_apt::deb822::{
@@ -33,9 +45,214 @@ fn mirror_dir(config: &MirrorConfig) -> PathBuf {
     PathBuf::from(&config.base_dir).join(&config.id)
 }
 
+// Helper to get the path of the file listing pinned snapshots. Must not be inside the pool's
+// link dir, as GC would treat it as orphan.
+fn pinned_file(config: &MirrorConfig) -> PathBuf {
+    PathBuf::from(&config.base_dir).join(format!(".{}.pinned.json", config.id))
+}
+
+// Helper to get the path of the lock file guarding snapshot creation. Must not be inside the
+// pool's link dir, as GC would treat it as orphan.
+fn snapshot_lock_file(config: &MirrorConfig) -> PathBuf {
+    PathBuf::from(&config.base_dir).join(format!(".{}.snapshot.lock", config.id))
+}
+
+// Helper to get the path of the marker file of a snapshot only containing some of the mirror's
+// components. Must not be inside the pool's link dir, as GC would treat it as orphan.
+fn partial_marker_file(config: &MirrorConfig, snapshot: &Snapshot) -> PathBuf {
+    PathBuf::from(&config.base_dir).join(format!(".{}.{snapshot}.partial", config.id))
+}
+
+/// Name of the file inside a snapshot describing the mirror configuration it was created with.
+pub const SNAPSHOT_SOURCES_FILE: &str = ".sources.json";
+
+// Helper to serialize the mirror configuration stored in a snapshot. Key paths and credentials
+// are specific to the mirroring system and therefore left out. For partial snapshots, the fetched
+// components are recorded as well, see [partial_snapshot_components].
+fn snapshot_sources(
+    config: &MirrorConfig,
+    components_filter: Option<&[String]>,
+) -> Result<Vec<u8>, Error> {
+    let mut value = serde_json::to_value(config)?;
+    if let Some(object) = value.as_object_mut() {
+        for field in ["key-path", "extra-key-paths", "http-auth"] {
+            object.remove(field);
+        }
+        if let Some(filter) = components_filter {
+            object.insert(
+                "partial-components".to_string(),
+                serde_json::to_value(filter)?,
+            );
+        }
+    }
+    Ok(serde_json::to_vec_pretty(&value)?)
+}
+
+/// Components contained in the partial snapshot at `snapshot_path`, either in the mirror's link
+/// dir or on a medium.
+///
+/// Returns `None` for complete snapshots and for snapshots created before the components were
+/// recorded in [SNAPSHOT_SOURCES_FILE].
+pub fn partial_snapshot_components(snapshot_path: &Path) -> Result<Option<Vec<String>>, Error> {
+    let path = snapshot_path.join(SNAPSHOT_SOURCES_FILE);
+    let raw = match proxmox_sys::fs::file_read_optional_string(&path)? {
+        Some(raw) => raw,
+        None => return Ok(None),
+    };
+    let mut sources: Value = serde_json::from_str(&raw)
+        .map_err(|err| format_err!("Failed to parse {path:?} - {err}"))?;
+
+    match sources.get_mut("partial-components").map(Value::take) {
+        Some(components) => Ok(Some(serde_json::from_value(components)?)),
+        None => Ok(None),
+    }
+}
+
+/// Read the mirror configuration a snapshot was created with, see [SNAPSHOT_SOURCES_FILE].
+///
+/// Returns `None` for snapshots created before this file was written.
+pub fn read_snapshot_sources(
+    config: &MirrorConfig,
+    snapshot: &Snapshot,
+) -> Result<Option<Value>, Error> {
+    let pool: Pool = pool(config)?;
+    let path = pool.get_path(&Path::new(&snapshot.to_string()).join(SNAPSHOT_SOURCES_FILE))?;
+
+    match proxmox_sys::fs::file_read_optional_string(&path)? {
+        Some(raw) => Ok(Some(serde_json::from_str(&raw)?)),
+        None => Ok(None),
+    }
+}
+
+/// Name of a new snapshot created at `epoch`, using the mirror's `snapshot-name-template` if set.
+///
+/// Supported placeholders are `{date}` (`YYYY-MM-DD`) and `{datetime}` (`YYYY-MM-DDTHH:MM:SSZ`).
+/// The expanded name must still be a valid snapshot name, for example `{date}T00:00:00Z` results
+/// in at most one snapshot per day.
+pub fn snapshot_name(config: &MirrorConfig, epoch: i64) -> Result<Snapshot, Error> {
+    let template = match &config.snapshot_name_template {
+        Some(template) => template,
+        None => return Snapshot::from_epoch(epoch),
+    };
+
+    let name = template
+        .replace("{date}", &proxmox_time::strftime_utc("%F", epoch)?)
+        .replace("{datetime}", &proxmox_time::epoch_to_rfc3339_utc(epoch)?);
+
+    if !Snapshot::is_valid_name(&name) {
+        bail!(
+            "snapshot name template '{template}' expands to '{name}', which is not a valid \
+            snapshot name - expected 'YYYY-MM-DDTHH:MM:SSZ', for example '{{date}}T00:00:00Z'"
+        );
+    }
+
+    Snapshot::from_str_strict(&name)
+}
+
+/// Check whether a snapshot was created for a subset of the mirror's components only.
+pub fn is_partial_snapshot(config: &MirrorConfig, snapshot: &Snapshot) -> bool {
+    partial_marker_file(config, snapshot).exists()
+}
+
+// Helper to get the path of the cache of pool entries confirmed while creating a snapshot. Must
+// not be inside the pool's link dir, as GC would treat it as orphan.
+fn run_cache_file(config: &MirrorConfig, snapshot: &Snapshot) -> PathBuf {
+    PathBuf::from(&config.base_dir).join(format!(".{}.run-cache-{snapshot}.json", config.id))
+}
+
+/// Checksums of files confirmed to be in the pool while creating a snapshot.
+///
+/// Entries are appended to a file as they are confirmed, one JSON string per line, so that a
+/// re-run of an interrupted snapshot creation can skip looking them up in the pool again.
+struct RunCache {
+    path: PathBuf,
+    known: RefCell<HashSet<String>>,
+    file: RefCell<File>,
+}
+
+impl RunCache {
+    /// Open the cache at `path`, loading entries of a previous, interrupted run.
+    fn open(path: PathBuf) -> Result<Self, Error> {
+        let mut known = HashSet::new();
+        if let Some(raw) = proxmox_sys::fs::file_read_optional_string(&path)? {
+            for line in raw.lines() {
+                // the last line might be truncated if the previous run was interrupted
+                if let Ok(key) = serde_json::from_str::<String>(line) {
+                    known.insert(key);
+                }
+            }
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| format_err!("Failed to open run cache {path:?} - {err}"))?;
+
+        Ok(Self {
+            path,
+            known: RefCell::new(known),
+            file: RefCell::new(file),
+        })
+    }
+
+    fn key(checksums: &CheckSums) -> Option<String> {
+        if let Some(sha512) = checksums.sha512 {
+            Some(format!("sha512:{}", hex::encode(sha512)))
+        } else {
+            checksums
+                .sha256
+                .map(|sha256| format!("sha256:{}", hex::encode(sha256)))
+        }
+    }
+
+    fn contains(&self, checksums: &CheckSums) -> bool {
+        Self::key(checksums).is_some_and(|key| self.known.borrow().contains(&key))
+    }
+
+    fn insert(&self, checksums: &CheckSums) -> Result<(), Error> {
+        let Some(key) = Self::key(checksums) else {
+            return Ok(());
+        };
+        if self.known.borrow_mut().insert(key.clone()) {
+            writeln!(self.file.borrow_mut(), "{}", serde_json::to_string(&key)?)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the cache file, once the snapshot was successfully created.
+    fn remove(self) -> Result<(), Error> {
+        std::fs::remove_file(&self.path)
+            .map_err(|err| format_err!("Failed to remove run cache {:?} - {err}", self.path))
+    }
+}
+
+// Helper to prevent concurrent snapshot creation for a mirror. The lock is released when the
+// returned file is closed, including when the process exits.
+fn lock_snapshot_creation(config: &MirrorConfig, timeout: Duration) -> Result<File, Error> {
+    let path = snapshot_lock_file(config);
+    proxmox_sys::fs::open_file_locked(&path, timeout, true, CreateOptions::default()).map_err(
+        |err| {
+            format_err!(
+                "Failed to lock {path:?}, is another snapshot of mirror '{}' being created? - {err}",
+                config.id
+            )
+        },
+    )
+}
+
+fn pool_dir(config: &MirrorConfig) -> PathBuf {
+    match &config.pool_dir {
+        Some(pool_dir) => PathBuf::from(pool_dir),
+        None => PathBuf::from(&config.base_dir).join(".pool"),
+    }
+}
+
 pub(crate) fn pool(config: &MirrorConfig) -> Result<Pool, Error> {
-    let pool_dir = PathBuf::from(&config.base_dir).join(".pool");
-    Pool::open(&mirror_dir(config), &pool_dir)
+    let mut pool = Pool::open(&mirror_dir(config), &pool_dir(config))?;
+    pool.set_hash_preference(config.hash_preference.unwrap_or_default());
+    pool.set_gc_io_limit(config.gc_io_limit_ops_per_sec);
+    Ok(pool)
 }
 
 /// `MirrorConfig`, but some fields converted/parsed into usable types.
@@ -43,14 +260,17 @@ struct ParsedMirrorConfig {
     pub repository: APTRepository,
     pub architectures: Vec<String>,
     pub pool: Pool,
-    pub key: Vec<u8>,
+    pub keys: Vec<Vec<u8>>,
+    pub sig_type: SigType,
     pub verify: bool,
     pub sync: bool,
     pub auth: Option<String>,
     pub client: Client,
     pub ignore_errors: bool,
+    pub repair: bool,
     pub skip: SkipConfig,
     pub weak_crypto: WeakCryptoConfig,
+    run_cache: Option<RunCache>,
 }
 
 impl TryInto<ParsedMirrorConfig> for MirrorConfig {
@@ -59,19 +279,23 @@ impl TryInto<ParsedMirrorConfig> for MirrorConfig {
     fn try_into(self) -> Result<ParsedMirrorConfig, Self::Error> {
         let pool = pool(&self)?;
 
-        let repository = convert_repo_line(self.repository.clone())?;
-
-        let key = file_get_contents(Path::new(&self.key_path))?;
+        let mut repository = convert_repo_line(self.render_repository()?)?;
+        let had_components = !repository.components.is_empty();
+        adjust_components(
+            &mut repository,
+            self.extra_components.as_deref().unwrap_or_default(),
+            self.exclude_components.as_deref().unwrap_or_default(),
+        );
+        if had_components && repository.components.is_empty() {
+            bail!("No components left to mirror after applying 'exclude-components'.");
+        }
 
-        let options = HttpOptions {
-            user_agent: Some(
-                concat!("proxmox-offline-mirror/", env!("CARGO_PKG_VERSION")).to_string(),
-            ),
-            proxy_config: ProxyConfig::from_proxy_env()?,
-            ..Default::default()
-        }; // TODO actually read version ;)
+        let mut keys = vec![file_get_contents(Path::new(&self.key_path))?];
+        for key_path in self.extra_key_paths.iter().flatten() {
+            keys.push(file_get_contents(Path::new(key_path))?);
+        }
 
-        let client = Client::new(options);
+        let client = http_client()?;
 
         let weak_crypto = match self.weak_crypto {
             Some(property_string) => {
@@ -86,18 +310,32 @@ impl TryInto<ParsedMirrorConfig> for MirrorConfig {
             repository,
             architectures: self.architectures,
             pool,
-            key,
+            keys,
+            sig_type: self.sig_type.unwrap_or_default(),
             verify: self.verify,
             sync: self.sync,
-            auth: None,
+            auth: self.http_auth.map(|base64| format!("basic {base64}")),
             client,
             ignore_errors: self.ignore_errors,
+            repair: self.repair,
             skip: self.skip,
             weak_crypto,
+            run_cache: None,
         })
     }
 }
 
+// Helper to create the HTTP client used for fetching repository files.
+fn http_client() -> Result<Client, Error> {
+    let options = HttpOptions {
+        user_agent: Some(concat!("proxmox-offline-mirror/", env!("CARGO_PKG_VERSION")).to_string()),
+        proxy_config: ProxyConfig::from_proxy_env()?,
+        ..Default::default()
+    }; // TODO actually read version ;)
+
+    Ok(Client::new(options))
+}
+
 // Helper to get absolute URL for dist-specific relative `path`.
 fn get_dist_url(repo: &APTRepository, path: &str) -> String {
     let dist_root = format!("{}/dists/{}", repo.uris[0], repo.suites[0]);
@@ -139,7 +377,9 @@ fn fetch_repo_file(
         None
     };
 
-    let response = client.get(uri, headers.as_ref())?;
+    let response = client
+        .get(uri, headers.as_ref())
+        .map_err(|err| MirrorError::NetworkError(format!("GET '{uri}' failed - {err}")))?;
 
     let reader: Box<dyn Read> = response.into_body();
     let mut reader = reader.take(max_size as u64);
@@ -147,7 +387,14 @@ fn fetch_repo_file(
     reader.read_to_end(&mut data)?;
 
     if let Some(checksums) = checksums {
-        checksums.verify(&data)?;
+        if let Err(err) = checksums.verify(&data) {
+            eprintln!("Checksum verification of '{uri}' failed - {err}");
+            return Err(MirrorError::ChecksumMismatch {
+                url: uri.to_string(),
+                source: err,
+            }
+            .into());
+        }
     }
 
     Ok(FetchResult {
@@ -159,6 +406,8 @@ fn fetch_repo_file(
 /// Helper to fetch InRelease or Release/Release.gpg files from repository.
 ///
 /// Set `detached` == false to fetch InRelease or to `detached` == true for Release/Release.gpg.
+/// For minisign and signify repositories, only the detached variant (Release/Release.minisig or
+/// Release/Release.sig) is supported.
 /// Verifies the contained/detached signature and stores all fetched files under `prefix`.
 ///
 /// Returns the verified raw release file data, or None if the "fetch" part itself fails.
@@ -168,18 +417,25 @@ fn fetch_release(
     detached: bool,
     dry_run: bool,
 ) -> Result<Option<FetchResult>, Error> {
+    let sig_file = config.sig_type.detached_file_name();
+
+    if !detached && config.sig_type != SigType::Gpg {
+        println!("Skipping InRelease file, not supported for {sig_file} signatures");
+        return Ok(None);
+    }
+
     let (name, fetched, sig) = if detached {
-        println!("Fetching Release/Release.gpg files");
+        println!("Fetching Release/{sig_file} files");
         let sig = match fetch_repo_file(
             &config.client,
-            &get_dist_url(&config.repository, "Release.gpg"),
+            &get_dist_url(&config.repository, sig_file),
             1024 * 1024,
             None,
             config.auth.as_deref(),
         ) {
             Ok(res) => res,
             Err(err) => {
-                eprintln!("Release.gpg fetch failure: {err}");
+                eprintln!("{sig_file} fetch failure: {err}");
                 return Ok(None);
             }
         };
@@ -198,7 +454,7 @@ fn fetch_release(
             }
         };
         fetched.fetched += sig.fetched;
-        ("Release(.gpg)", fetched, Some(sig.data()))
+        ("Release", fetched, Some(sig.data()))
     } else {
         println!("Fetching InRelease file");
         let fetched = match fetch_repo_file(
@@ -219,8 +475,17 @@ fn fetch_release(
 
     println!("Verifying '{name}' signature using provided repository key..");
     let content = fetched.data_ref();
-    let verified =
-        helpers::verify_signature(content, &config.key, sig.as_deref(), &config.weak_crypto)?;
+    let verified = match (config.sig_type, sig.as_deref()) {
+        (SigType::Gpg, sig) => {
+            helpers::verify_signature(content, &config.keys, sig, &config.weak_crypto)
+        }
+        (sig_type, Some(sig)) => {
+            helpers::verify_ed25519_signature(sig_type, content, &config.keys, sig)
+                .map(|()| content.to_vec())
+        }
+        (_, None) => Err(format_err!("Missing detached signature for '{name}'")),
+    }
+    .map_err(|err| MirrorError::SignatureVerificationFailed(format!("{name} - {err}")))?;
     println!("Success");
 
     let sha512 = Some(openssl::sha::sha512(content));
@@ -258,7 +523,7 @@ fn fetch_release(
         }
         locked.link_file(
             &csums,
-            Path::new(&get_dist_path(&config.repository, prefix, "Release.gpg")),
+            Path::new(&get_dist_path(&config.repository, prefix, sig_file)),
         )?;
     } else {
         locked.link_file(
@@ -289,9 +554,11 @@ fn fetch_index_file(
     uncompressed: Option<&FileReference>,
     by_hash: bool,
     dry_run: bool,
+    warnings: &mut Vec<MirrorWarning>,
 ) -> Result<FetchResult, Error> {
     let url = get_dist_url(&config.repository, &reference.path);
     let path = get_dist_path(&config.repository, prefix, &reference.path);
+    let mut repair = false;
 
     if let Some(uncompressed) = uncompressed {
         let uncompressed_path = get_dist_path(&config.repository, prefix, &uncompressed.path);
@@ -299,20 +566,28 @@ fn fetch_index_file(
         if config.pool.contains(&reference.checksums)
             && config.pool.contains(&uncompressed.checksums)
         {
-            let data = config
+            match config
                 .pool
-                .get_contents(&uncompressed.checksums, config.verify)?;
-
-            if dry_run {
-                return Ok(FetchResult { data, fetched: 0 });
+                .get_contents(&uncompressed.checksums, config.verify)
+            {
+                Ok(data) => {
+                    if dry_run {
+                        return Ok(FetchResult { data, fetched: 0 });
+                    }
+                    // Ensure they're linked at current path
+                    config.pool.lock()?.link_file(&reference.checksums, &path)?;
+                    config
+                        .pool
+                        .lock()?
+                        .link_file(&uncompressed.checksums, &uncompressed_path)?;
+                    return Ok(FetchResult { data, fetched: 0 });
+                }
+                Err(err) if config.repair && !dry_run => {
+                    report_repair(&uncompressed_path, err, warnings);
+                    repair = true;
+                }
+                Err(err) => return Err(err),
             }
-            // Ensure they're linked at current path
-            config.pool.lock()?.link_file(&reference.checksums, &path)?;
-            config
-                .pool
-                .lock()?
-                .link_file(&uncompressed.checksums, &uncompressed_path)?;
-            return Ok(FetchResult { data, fetched: 0 });
         }
     }
 
@@ -344,6 +619,7 @@ fn fetch_index_file(
                 &reference.checksums,
                 true,
                 dry_run,
+                warnings,
             )),
         })
         .ok_or_else(|| format_err!("Failed to retrieve {}", reference.path))??;
@@ -380,7 +656,9 @@ fn fetch_index_file(
 
     let locked = &config.pool.lock()?;
     if let Some(uncompressed) = uncompressed {
-        if !locked.contains(&uncompressed.checksums) {
+        if repair {
+            locked.replace_file_in_pool(decompressed, &uncompressed.checksums, config.sync)?;
+        } else if !locked.contains(&uncompressed.checksums) {
             locked.add_file(decompressed, &uncompressed.checksums, config.sync)?;
         }
 
@@ -392,6 +670,15 @@ fn fetch_index_file(
     Ok(res)
 }
 
+// Helper to report a pool file that failed verification and will be fetched again. The fetched
+// file then replaces the existing one via `replace_file_in_pool`, so that links in existing
+// snapshots are repaired as well.
+fn report_repair(file: &Path, err: Error, warnings: &mut Vec<MirrorWarning>) {
+    let msg = format!("{file:?}: existing pool file failed verification, re-fetching - {err}");
+    eprintln!("{msg}");
+    warnings.push(MirrorWarning::VerifyError(msg));
+}
+
 /// Helper to fetch arbitrary files like binary packages.
 ///
 /// Will skip fetching if matching file already exists locally, in which case it will just be
@@ -399,6 +686,10 @@ fn fetch_index_file(
 ///
 /// If need_data is false and the mirror config is set to skip verification, reading the file's
 /// content will be skipped as well if fetching was skipped.
+///
+/// If the mirror config is set to repair, existing files failing verification will be fetched
+/// again and replaced in the pool.
+#[allow(clippy::too_many_arguments)]
 fn fetch_plain_file(
     config: &ParsedMirrorConfig,
     url: &str,
@@ -407,41 +698,67 @@ fn fetch_plain_file(
     checksums: &CheckSums,
     need_data: bool,
     dry_run: bool,
+    warnings: &mut Vec<MirrorWarning>,
 ) -> Result<FetchResult, Error> {
     let locked = &config.pool.lock()?;
-    let res = if locked.contains(checksums) {
+    let mut repair = false;
+    let cached = config
+        .run_cache
+        .as_ref()
+        .is_some_and(|cache| cache.contains(checksums));
+    let existing = if cached || locked.contains(checksums) {
         if need_data || config.verify {
-            locked
-                .get_contents(checksums, config.verify)
-                .map(|data| FetchResult { data, fetched: 0 })?
+            match locked.get_contents(checksums, config.verify) {
+                Ok(data) => Some(FetchResult { data, fetched: 0 }),
+                Err(err) if config.repair && !dry_run => {
+                    report_repair(file, err, warnings);
+                    repair = true;
+                    None
+                }
+                Err(err) => return Err(err),
+            }
         } else {
             // performance optimization for .deb files if verify is false
             // we never need the file contents and they make up the bulk of a repo
-            FetchResult {
+            Some(FetchResult {
                 data: vec![],
                 fetched: 0,
-            }
+            })
         }
     } else if dry_run && !need_data {
-        FetchResult {
+        Some(FetchResult {
             data: vec![],
             fetched: 0,
-        }
+        })
     } else {
-        let fetched = fetch_repo_file(
-            &config.client,
-            url,
-            max_size,
-            Some(checksums),
-            config.auth.as_deref(),
-        )?;
-        locked.add_file(fetched.data_ref(), checksums, config.verify)?;
-        fetched
+        None
+    };
+
+    let res = match existing {
+        Some(res) => res,
+        None => {
+            let fetched = fetch_repo_file(
+                &config.client,
+                url,
+                max_size,
+                Some(checksums),
+                config.auth.as_deref(),
+            )?;
+            if repair {
+                locked.replace_file_in_pool(fetched.data_ref(), checksums, config.sync)?;
+            } else {
+                locked.add_file(fetched.data_ref(), checksums, config.verify)?;
+            }
+            fetched
+        }
     };
 
     if !dry_run {
         // Ensure it's linked at current path
         locked.link_file(checksums, file)?;
+        if let Some(cache) = &config.run_cache {
+            cache.insert(checksums)?;
+        }
     }
 
     Ok(res)
@@ -449,11 +766,9 @@ fn fetch_plain_file(
 
 /// Initialize a new mirror (by creating the corresponding pool).
 pub fn init(config: &MirrorConfig) -> Result<(), Error> {
-    let pool_dir = PathBuf::from(&config.base_dir).join(".pool");
-
     let dir = mirror_dir(config);
 
-    Pool::create(&dir, &pool_dir)?;
+    Pool::create(&dir, &pool_dir(config))?;
     Ok(())
 }
 
@@ -465,24 +780,36 @@ pub fn destroy(config: &MirrorConfig) -> Result<(), Error> {
     Ok(())
 }
 
-/// List snapshots
-pub fn list_snapshots(config: &MirrorConfig) -> Result<Vec<Snapshot>, Error> {
+/// List snapshots, including leftover temporary directories of interrupted snapshot creations
+/// marked as `SnapshotState::Incomplete`.
+pub fn list_snapshots(
+    config: &MirrorConfig,
+) -> Result<Vec<(Snapshot, SnapshotState)>, MirrorError> {
     let _pool: Pool = pool(config)?;
 
-    let mut list: Vec<Snapshot> = vec![];
+    let mut list: Vec<(Snapshot, SnapshotState)> = vec![];
 
     let path = mirror_dir(config);
 
     proxmox_sys::fs::scandir(
         libc::AT_FDCWD,
         &path,
-        &SNAPSHOT_REGEX,
+        &SNAPSHOT_DIR_REGEX,
         |_l2_fd, snapshot, file_type| {
             if file_type != nix::dir::Type::Directory {
                 return Ok(());
             }
 
-            list.push(snapshot.parse()?);
+            match snapshot.strip_suffix(".tmp") {
+                Some(snapshot) => list.push((
+                    Snapshot::from_str_strict(snapshot)?,
+                    SnapshotState::Incomplete,
+                )),
+                None => list.push((
+                    Snapshot::from_str_strict(snapshot)?,
+                    SnapshotState::Complete,
+                )),
+            }
 
             Ok(())
         },
@@ -493,14 +820,174 @@ pub fn list_snapshots(config: &MirrorConfig) -> Result<Vec<Snapshot>, Error> {
     Ok(list)
 }
 
+/// List successfully created snapshots
+pub fn list_complete_snapshots(config: &MirrorConfig) -> Result<Vec<Snapshot>, MirrorError> {
+    Ok(list_snapshots(config)?
+        .into_iter()
+        .filter_map(|(snapshot, state)| (state == SnapshotState::Complete).then_some(snapshot))
+        .collect())
+}
+
+/// Remove all leftover temporary directories of interrupted snapshot creations.
+///
+/// Must not be called while a snapshot of this mirror is being created, as its temporary directory
+/// would be removed as well. Returns the snapshots whose directories were removed.
+pub fn cleanup_incomplete_snapshots(config: &MirrorConfig) -> Result<Vec<Snapshot>, MirrorError> {
+    let pool: Pool = pool(config)?;
+
+    let incomplete: Vec<Snapshot> = list_snapshots(config)?
+        .into_iter()
+        .filter_map(|(snapshot, state)| (state == SnapshotState::Incomplete).then_some(snapshot))
+        .collect();
+
+    let locked = pool.lock()?;
+    for snapshot in &incomplete {
+        let path = pool.get_path(Path::new(&format!("{snapshot}.tmp")))?;
+        locked.remove_dir(&path)?;
+
+        let run_cache = run_cache_file(config, snapshot);
+        if run_cache.exists() {
+            std::fs::remove_file(&run_cache)?;
+        }
+    }
+
+    Ok(incomplete)
+}
+
+/// List snapshots including metadata. Number and size of contained files are only calculated if
+/// `detailed` is set, as this requires walking the whole snapshot directory.
+pub fn list_snapshot_summaries(
+    config: &MirrorConfig,
+    detailed: bool,
+) -> Result<Vec<SnapshotSummary>, Error> {
+    let pool: Pool = pool(config)?;
+    let pinned = pinned_snapshots(config)?;
+
+    let mut summaries = Vec::new();
+    for (snapshot, state) in list_snapshots(config)? {
+        let (file_count, size_bytes) = if detailed {
+            let dir = match state {
+                SnapshotState::Complete => snapshot.to_string(),
+                SnapshotState::Incomplete => format!("{snapshot}.tmp"),
+            };
+            let path = pool.get_path(Path::new(&dir))?;
+            let (count, size) = snapshot_usage(&path)?;
+            (Some(count), Some(size))
+        } else {
+            (None, None)
+        };
+
+        summaries.push(SnapshotSummary {
+            snapshot,
+            created_at: snapshot.to_string(),
+            is_pinned: state == SnapshotState::Complete && pinned.contains(&snapshot),
+            is_partial: state == SnapshotState::Complete && is_partial_snapshot(config, &snapshot),
+            state,
+            file_count,
+            size_bytes,
+        });
+    }
+
+    Ok(summaries)
+}
+
+// Helper to calculate number and total size of files below `path`, counting hardlinks once.
+pub(crate) fn snapshot_usage(path: &Path) -> Result<(usize, u64), Error> {
+    let mut inodes = HashSet::new();
+    let mut count = 0;
+    let mut size = 0;
+
+    for entry in WalkDir::new(path) {
+        let meta = entry?.metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        count += 1;
+        if inodes.insert(meta.st_ino()) {
+            size += meta.st_size();
+        }
+    }
+
+    Ok((count, size))
+}
+
+/// Non-fatal issues encountered while creating a snapshot.
+enum MirrorWarning {
+    /// A file could not be fetched, but errors are ignored.
+    FetchError(String),
+    /// An existing pool file failed verification and was repaired by fetching it again.
+    VerifyError(String),
+}
+
+impl std::fmt::Display for MirrorWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorWarning::FetchError(msg) => write!(f, "fetch error: {msg}"),
+            MirrorWarning::VerifyError(msg) => write!(f, "repaired: {msg}"),
+        }
+    }
+}
+
+/// Progress events emitted while creating a snapshot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum SnapshotEvent {
+    /// Snapshot creation started.
+    Starting {
+        mirror: String,
+        snapshot: Snapshot,
+        dry_run: bool,
+    },
+    /// A new phase of snapshot creation started, e.g. fetching indices or packages.
+    Phase { name: String },
+    /// Fetching the files referenced by a package index made progress.
+    Progress {
+        /// Basename of the package index
+        index: String,
+        /// Number of processed entries
+        files: usize,
+        /// Total number of entries in the package index
+        total: usize,
+        /// Newly fetched bytes
+        bytes: usize,
+    },
+    /// Snapshot creation finished.
+    Complete {
+        files: usize,
+        bytes: usize,
+        warnings: usize,
+    },
+    /// Snapshot creation failed.
+    Failed { error: String },
+}
+
 struct MirrorProgress {
-    warnings: Vec<String>,
+    on_progress: Option<Box<dyn Fn(SnapshotEvent) + Send>>,
+    warnings: Vec<MirrorWarning>,
     dry_run: Progress,
     total: Progress,
+    components: HashMap<String, Progress>,
     skip_count: usize,
     skip_bytes: usize,
 }
 
+impl MirrorProgress {
+    fn emit(&self, event: SnapshotEvent) {
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(event);
+        }
+    }
+
+    fn emit_fetch_progress(&self, index: &str, fetch_progress: &Progress, total: usize) {
+        self.emit(SnapshotEvent::Progress {
+            index: index.to_string(),
+            files: fetch_progress.file_count(),
+            total,
+            bytes: fetch_progress.new_bytes,
+        });
+    }
+}
+
 fn convert_to_globset(config: &ParsedMirrorConfig) -> Result<Option<GlobSet>, Error> {
     Ok(if let Some(skipped_packages) = &config.skip.skip_packages {
         let mut globs = GlobSetBuilder::new();
@@ -515,15 +1002,39 @@ fn convert_to_globset(config: &ParsedMirrorConfig) -> Result<Option<GlobSet>, Er
     })
 }
 
+// Helper to extract the `Priority` of each package in a packages index, keyed by `Filename`, as
+// the parsed `PackagesFile` doesn't contain it.
+fn parse_package_priorities(data: &[u8]) -> HashMap<String, String> {
+    let mut priorities = HashMap::new();
+    let mut file = None;
+    let mut priority = None;
+
+    for line in String::from_utf8_lossy(data).lines().chain([""]) {
+        if line.is_empty() {
+            if let (Some(file), Some(priority)) = (file.take(), priority.take()) {
+                priorities.insert(file, priority);
+            }
+        } else if let Some(value) = line.strip_prefix("Filename:") {
+            file = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("Priority:") {
+            priority = Some(value.trim().to_string());
+        }
+    }
+
+    priorities
+}
+
 fn fetch_binary_packages(
     config: &ParsedMirrorConfig,
     component: &str,
     packages_indices: HashMap<&String, PackagesFile>,
+    priorities: &HashMap<String, String>,
     dry_run: bool,
     prefix: &Path,
     progress: &mut MirrorProgress,
 ) -> Result<(), Error> {
     let skipped_package_globs = convert_to_globset(config)?;
+    let mut component_progress = Progress::new();
 
     for (basename, references) in packages_indices {
         let total_files = references.files.len();
@@ -539,6 +1050,24 @@ fn fetch_binary_packages(
         let mut skip_bytes = 0usize;
 
         for package in references.files {
+            // index files might contain packages of architectures not selected for mirroring,
+            // architecture independent packages are part of the indices of each architecture
+            let arch_skipped = (package.architecture != "all"
+                && !config.architectures.contains(&package.architecture))
+                || config
+                    .skip
+                    .skip_architectures
+                    .as_ref()
+                    .is_some_and(|skipped| skipped.contains(&package.architecture));
+            if arch_skipped {
+                println!(
+                    "\tskipping {} - {}b (architecture '{}')",
+                    package.package, package.size, package.architecture
+                );
+                skip_count += 1;
+                skip_bytes += package.size;
+                continue;
+            }
             if let Some(sections) = &config.skip.skip_sections {
                 if sections.iter().any(|section| {
                     package.section == *section
@@ -553,6 +1082,19 @@ fn fetch_binary_packages(
                     continue;
                 }
             }
+            if let Some(skip_priorities) = &config.skip.skip_priorities {
+                if let Some(priority) = priorities.get(&package.file) {
+                    if skip_priorities.contains(priority) {
+                        println!(
+                            "\tskipping {} - {}b (priority '{priority}')",
+                            package.package, package.size
+                        );
+                        skip_count += 1;
+                        skip_bytes += package.size;
+                        continue;
+                    }
+                }
+            }
             if let Some(skipped_package_globs) = &skipped_package_globs {
                 let matches = skipped_package_globs.matches(&package.package);
                 if !matches.is_empty() {
@@ -597,6 +1139,7 @@ fn fetch_binary_packages(
                     &package.checksums,
                     false,
                     dry_run,
+                    &mut progress.warnings,
                 ) {
                     Ok(res) => fetch_progress.update(&res),
                     Err(err) if config.ignore_errors => {
@@ -605,7 +1148,7 @@ fn fetch_binary_packages(
                             basename, package.file, err,
                         );
                         eprintln!("{msg}");
-                        progress.warnings.push(msg);
+                        progress.warnings.push(MirrorWarning::FetchError(msg));
                     }
                     Err(err) => return Err(err),
                 }
@@ -613,9 +1156,12 @@ fn fetch_binary_packages(
 
             if fetch_progress.file_count() % (max(total_files / 100, 1)) == 0 {
                 println!("\tProgress: {fetch_progress}");
+                progress.emit_fetch_progress(basename, &fetch_progress, total_files);
             }
         }
         println!("\tProgress: {fetch_progress}");
+        progress.emit_fetch_progress(basename, &fetch_progress, total_files);
+        component_progress += fetch_progress.clone();
         if dry_run {
             progress.dry_run += fetch_progress;
         } else {
@@ -628,6 +1174,11 @@ fn fetch_binary_packages(
         }
     }
 
+    *progress
+        .components
+        .entry(component.to_string())
+        .or_default() += component_progress;
+
     Ok(())
 }
 
@@ -717,6 +1268,7 @@ fn fetch_source_packages(
                         &file_reference.checksums,
                         false,
                         dry_run,
+                        &mut progress.warnings,
                     ) {
                         Ok(res) => fetch_progress.update(&res),
                         Err(err) if config.ignore_errors => {
@@ -725,7 +1277,7 @@ fn fetch_source_packages(
                                 basename, file_reference.file, err,
                             );
                             eprintln!("{msg}");
-                            progress.warnings.push(msg);
+                            progress.warnings.push(MirrorWarning::FetchError(msg));
                         }
                         Err(err) => return Err(err),
                     }
@@ -733,10 +1285,12 @@ fn fetch_source_packages(
 
                 if fetch_progress.file_count() % (max(total_source_packages / 100, 1)) == 0 {
                     println!("\tProgress: {fetch_progress}");
+                    progress.emit_fetch_progress(basename, &fetch_progress, total_source_packages);
                 }
             }
         }
         println!("\tProgress: {fetch_progress}");
+        progress.emit_fetch_progress(basename, &fetch_progress, total_source_packages);
         if dry_run {
             progress.dry_run += fetch_progress;
         } else {
@@ -752,90 +1306,636 @@ fn fetch_source_packages(
     Ok(())
 }
 
-/// Create a new snapshot of the remote repository, fetching and storing files as needed.
-///
-/// Operates in three phases:
-/// - Fetch and verify release files
-/// - Fetch referenced indices according to config
-/// - Fetch binary packages referenced by package indices
-///
-/// Files will be linked in a temporary directory and only renamed to the final, valid snapshot
-/// directory at the end. In case of error, leftover `XXX.tmp` directories at the top level of
-/// `base_dir` can be safely removed once the next snapshot was successfully created, as they only
-/// contain hardlinks.
-pub fn create_snapshot(
-    config: MirrorConfig,
-    snapshot: &Snapshot,
+// Helper to get the authorization header value for mirrors requiring a subscription.
+fn subscription_auth(
+    config: &MirrorConfig,
     subscription: Option<SubscriptionKey>,
-    dry_run: bool,
-) -> Result<(), Error> {
-    let auth = if let Some(product) = &config.use_subscription {
-        match subscription {
-            None => {
-                bail!(
-                    "Mirror {} requires a subscription key, but none given.",
-                    config.id
-                );
-            }
-            Some(key) if key.product() == *product => {
-                let base64 = proxmox_base64::encode(format!("{}:{}", key.key, key.server_id));
-                Some(format!("basic {base64}"))
-            }
-            Some(key) => {
+) -> Result<Option<String>, Error> {
+    let product = match &config.use_subscription {
+        Some(product) => product,
+        None => return Ok(None),
+    };
+
+    match subscription {
+        None => bail!(
+            "Mirror {} requires a subscription key, but none given.",
+            config.id
+        ),
+        Some(key) => {
+            let key_product = key.product()?;
+            if key_product != *product {
                 bail!(
-                    "Repository product type '{}' and key product type '{}' don't match.",
-                    product,
-                    key.product()
+                    "Repository product type '{product}' and key product type '{key_product}' don't match."
                 );
             }
+            let base64 = proxmox_base64::encode(format!("{}:{}", key.key, key.server_id));
+            Ok(Some(format!("basic {base64}")))
         }
-    } else {
-        None
+    }
+}
+
+// Helper to parse the `Date` field of a release file, e.g. "Sat, 10 Aug 2024 09:25:36 UTC".
+fn parse_release_date(date: &str) -> Result<i64, Error> {
+    // strptime only parses numeric offsets
+    let numeric = match date
+        .strip_suffix("UTC")
+        .or_else(|| date.strip_suffix("GMT"))
+    {
+        Some(date) => format!("{date}+0000"),
+        None => date.to_string(),
+    };
+    let c_date = CString::new(numeric)?;
+
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let rest = unsafe {
+        libc::strptime(
+            c_date.as_ptr(),
+            c"%a, %d %b %Y %H:%M:%S %z".as_ptr(),
+            &mut tm,
+        )
     };
+    if rest.is_null() || unsafe { *rest } != 0 {
+        bail!("unexpected date format '{date}'");
+    }
+
+    let offset = tm.tm_gmtoff as i64;
+    Ok(proxmox_time::timegm(&mut tm)? - offset)
+}
 
+/// Fetch and verify the upstream release file and return the epoch of its `Date` field.
+pub fn upstream_release_date(
+    config: MirrorConfig,
+    subscription: Option<SubscriptionKey>,
+) -> Result<i64, MirrorError> {
+    let auth = subscription_auth(&config, subscription)?;
     let mut config: ParsedMirrorConfig = config.try_into()?;
-    config.auth = auth;
+    if auth.is_some() {
+        config.auth = auth;
+    }
 
-    let prefix = format!("{snapshot}.tmp");
-    let prefix = Path::new(&prefix);
+    // InRelease is only available for GPG signed repositories
+    let detached = config.sig_type != SigType::Gpg;
+    let release = fetch_release(&config, Path::new(""), detached, true)?
+        .ok_or_else(|| format_err!("Failed to fetch release file."))?;
 
-    let mut progress = MirrorProgress {
-        warnings: Vec::new(),
-        skip_count: 0,
-        skip_bytes: 0,
-        dry_run: Progress::new(),
-        total: Progress::new(),
-    };
+    let data = String::from_utf8_lossy(release.data_ref());
+    let date = data
+        .lines()
+        .find_map(|line| line.strip_prefix("Date:"))
+        .ok_or_else(|| format_err!("Release file doesn't contain a 'Date' field."))?;
 
-    let parse_release = |res: FetchResult, name: &str| -> Result<ReleaseFile, Error> {
-        println!("Parsing {name}..");
-        let parsed: ReleaseFile = res.data[..].try_into()?;
-        println!(
-            "'{name}' file has {} referenced files..",
-            parsed.files.len()
-        );
-        Ok(parsed)
+    Ok(parse_release_date(date.trim())?)
+}
+
+/// Find the latest complete snapshot whose release file is identical to the current upstream one.
+///
+/// Only the upstream release file is fetched, without storing it in the pool. Returns `None` if
+/// there is no complete, non-partial snapshot or if the release file changed since the latest one.
+pub fn unchanged_snapshot(
+    config: &MirrorConfig,
+    subscription: Option<SubscriptionKey>,
+) -> Result<Option<Snapshot>, MirrorError> {
+    let latest = match list_complete_snapshots(config)?.into_iter().max() {
+        Some(latest) if !is_partial_snapshot(config, &latest) => latest,
+        _ => return Ok(None),
     };
 
-    // we want both on-disk for compat reasons, if both are available
-    let release = fetch_release(&config, prefix, true, dry_run)?
-        .map(|res| {
-            progress.total.update(&res);
-            parse_release(res, "Release")
-        })
-        .transpose()?;
+    let auth = subscription_auth(config, subscription)?;
+    let mut parsed: ParsedMirrorConfig = config.clone().try_into()?;
+    if auth.is_some() {
+        parsed.auth = auth;
+    }
 
-    let in_release = fetch_release(&config, prefix, false, dry_run)?
-        .map(|res| {
-            progress.total.update(&res);
-            parse_release(res, "InRelease")
-        })
-        .transpose()?;
+    // InRelease is only available for GPG signed repositories
+    let detached = parsed.sig_type != SigType::Gpg;
+    let release = fetch_release(&parsed, Path::new(""), detached, true)?
+        .ok_or_else(|| format_err!("Failed to fetch release file."))?;
+
+    let name = if detached { "Release" } else { "InRelease" };
+    let path = get_dist_path(&parsed.repository, Path::new(&latest.to_string()), name);
+    let path = parsed.pool.get_path(&path)?;
+
+    let local = match proxmox_sys::fs::file_get_optional_contents(&path)? {
+        Some(local) if detached => local,
+        // compare signed content only, a snapshot signed by a since rotated key counts as changed
+        Some(local) => {
+            match helpers::verify_signature(&local, &parsed.keys, None, &parsed.weak_crypto) {
+                Ok(verified) => verified,
+                Err(_) => return Ok(None),
+            }
+        }
+        None => return Ok(None),
+    };
 
-    // at least one must be available to proceed
-    let release = release
-        .or(in_release)
-        .ok_or_else(|| format_err!("Neither Release(.gpg) nor InRelease available!"))?;
+    Ok((local == release.data_ref()).then_some(latest))
+}
+
+/// Create `snapshot` as a clone of `latest`, an unchanged snapshot of the same mirror as returned
+/// by [unchanged_snapshot].
+///
+/// The mirror configuration stored in the clone is replaced by the current one, see
+/// [SNAPSHOT_SOURCES_FILE]. `overwrite`, `lock_timeout` and `on_progress` are handled like by
+/// [create_snapshot].
+pub fn clone_snapshot(
+    config: &MirrorConfig,
+    latest: &Snapshot,
+    snapshot: &Snapshot,
+    dry_run: bool,
+    overwrite: bool,
+    lock_timeout: Duration,
+    on_progress: Option<Box<dyn Fn(SnapshotEvent) + Send>>,
+) -> Result<SnapshotCreationSummary, MirrorError> {
+    let start = Instant::now();
+    let emit = |event| {
+        if let Some(on_progress) = &on_progress {
+            on_progress(event);
+        }
+    };
+    emit(SnapshotEvent::Starting {
+        mirror: config.id.clone(),
+        snapshot: *snapshot,
+        dry_run,
+    });
+
+    let mut report = ProgressReport::default();
+    if dry_run {
+        println!("No changes detected since snapshot {latest}, would clone it.");
+    } else {
+        let _lock = lock_snapshot_creation(config, lock_timeout)?;
+
+        let pool: Pool = pool(config)?;
+        let snapshot_path = pool.get_path(Path::new(&snapshot.to_string()))?;
+        if snapshot_path.exists() {
+            if !overwrite {
+                return Err(MirrorError::SnapshotAlreadyExists(*snapshot));
+            }
+            if read_pinned(config)?.contains(snapshot) {
+                return Err(format_err!(
+                    "Snapshot {snapshot} is pinned, unpin it first to replace it."
+                )
+                .into());
+            }
+        }
+
+        let prefix = format!("{snapshot}.tmp");
+        let prefix = Path::new(&prefix);
+        let locked = pool.lock()?;
+        // might be left over by an interrupted run
+        let tmp_path = pool.get_path(prefix)?;
+        if tmp_path.exists() {
+            locked.remove_dir(&tmp_path)?;
+        }
+
+        let copied = locked.copy_dir(Path::new(&latest.to_string()), &pool, prefix)?;
+        link_snapshot_sources(
+            &locked,
+            prefix,
+            &snapshot_sources(config, None)?,
+            config.sync,
+        )?;
+
+        let partial_marker = partial_marker_file(config, snapshot);
+        if partial_marker.exists() {
+            std::fs::remove_file(&partial_marker)?;
+        }
+        if snapshot_path.exists() {
+            println!("Replacing existing snapshot {snapshot}");
+            locked.remove_dir(&snapshot_path)?;
+        }
+        locked.rename(prefix, Path::new(&snapshot.to_string()))?;
+        println!("No changes detected, created snapshot clone {snapshot} of {latest}.");
+
+        report.new_files = copied.files_copied;
+        report.new_bytes = copied.bytes_copied as usize;
+        report.reused_files = copied.files_shared;
+    }
+
+    emit(SnapshotEvent::Complete {
+        files: report.new_files + report.reused_files,
+        bytes: report.new_bytes,
+        warnings: 0,
+    });
+
+    Ok(SnapshotCreationSummary {
+        snapshot: *snapshot,
+        progress: report,
+        skipped_packages: 0,
+        skipped_bytes: 0,
+        components: BTreeMap::new(),
+        warnings: Vec::new(),
+        duration_secs: start.elapsed().as_secs_f64(),
+    })
+}
+
+// Helper to link the serialized mirror configuration into the snapshot directory at `prefix`,
+// replacing an existing one. Should be called with the pool locked.
+fn link_snapshot_sources(
+    locked: &PoolLockGuard,
+    prefix: &Path,
+    sources: &[u8],
+    sync: bool,
+) -> Result<(), Error> {
+    let sources_csums = CheckSums {
+        sha256: Some(openssl::sha::sha256(sources)),
+        sha512: Some(openssl::sha::sha512(sources)),
+        ..Default::default()
+    };
+    if !locked.contains(&sources_csums) {
+        locked.add_file(sources, &sources_csums, sync)?;
+    }
+    let sources_path = prefix.join(SNAPSHOT_SOURCES_FILE);
+    // might be left over by an interrupted run or copied from another snapshot
+    let existing = locked.get_path(&sources_path)?;
+    if existing.exists() {
+        locked.unlink_file(&existing, false)?;
+    }
+    locked.link_file(&sources_csums, &sources_path)?;
+
+    Ok(())
+}
+
+/// Estimate the size of a new snapshot without fetching any packages.
+///
+/// Only the release file and the `Packages` indices it references are fetched, without storing
+/// them in the pool. For each index, only the variant which would be fetched is counted. Package
+/// sizes are summed up from the index entries without applying skip filters, so the result is an
+/// upper bound. Packages listed in multiple indices, like those for architecture `all`, are
+/// counted once. Source packages are not included.
+pub fn estimate_snapshot_size(
+    config: &MirrorConfig,
+    subscription: Option<SubscriptionKey>,
+) -> Result<SizeEstimate, Error> {
+    let auth = subscription_auth(config, subscription)?;
+    let mut config: ParsedMirrorConfig = config.clone().try_into()?;
+    if auth.is_some() {
+        config.auth = auth;
+    }
+
+    // InRelease is only available for GPG signed repositories
+    let detached = config.sig_type != SigType::Gpg;
+    let release = fetch_release(&config, Path::new(""), detached, true)?
+        .ok_or_else(|| format_err!("Failed to fetch release file."))?;
+    let release: ReleaseFile = release.data[..].try_into()?;
+
+    let binary = config
+        .repository
+        .types
+        .contains(&APTRepositoryPackageType::Deb);
+
+    let mut estimate = SizeEstimate::default();
+    let mut warnings = Vec::new();
+    let mut counted = HashSet::new();
+
+    for (basename, references) in &release.files {
+        let reference = match references.first() {
+            Some(reference) => reference,
+            None => continue,
+        };
+        if !config.repository.components.contains(&reference.component) {
+            continue;
+        }
+
+        let skip = match &reference.file_type {
+            FileReferenceType::Ignored | FileReferenceType::PDiff => true,
+            FileReferenceType::Sources(_) => !config
+                .repository
+                .types
+                .contains(&APTRepositoryPackageType::DebSrc),
+            file_type => match file_type.architecture() {
+                Some(arch) => !binary || !config.architectures.contains(arch),
+                None => false,
+            },
+        };
+        if skip {
+            continue;
+        }
+
+        // if both compressed and uncompressed are referenced, the uncompressed file may not exist
+        // on the server
+        let uncompressed = references
+            .iter()
+            .find(|reference| reference.path == *basename);
+        let to_fetch = references
+            .iter()
+            .find(|reference| Some(*reference) != uncompressed)
+            .unwrap_or(reference);
+
+        estimate.index_bytes += to_fetch.size as u64;
+
+        if !matches!(reference.file_type, FileReferenceType::Packages(_, _)) {
+            continue;
+        }
+
+        let res = fetch_index_file(
+            &config,
+            Path::new(""),
+            to_fetch,
+            uncompressed,
+            release.aquire_by_hash,
+            true,
+            &mut warnings,
+        )?;
+        let packages: PackagesFile = res.data[..].try_into()?;
+
+        for package in packages.files {
+            if !counted.insert((package.checksums.sha256, package.checksums.sha512)) {
+                continue;
+            }
+            estimate.package_bytes_upper_bound += package.size as u64;
+            if config.pool.contains(&package.checksums) {
+                estimate.already_in_pool_estimate += package.size as u64;
+            }
+        }
+    }
+
+    estimate.warnings = warnings.iter().map(|warning| warning.to_string()).collect();
+
+    Ok(estimate)
+}
+
+/// Check a mirror config for common misconfigurations without creating a snapshot.
+///
+/// Checks that the key files exist and can be parsed, that the repository definition is valid,
+/// that the release file is reachable (using a HEAD request) and that `base_dir` is writable.
+/// Returns an empty list if no issues were found.
+pub fn preflight_check(config: &MirrorConfig) -> Result<Vec<PreflightIssue>, Error> {
+    let mut issues = Vec::new();
+    let sig_type = config.sig_type.unwrap_or_default();
+
+    for (index, key_path) in std::iter::once(&config.key_path)
+        .chain(config.extra_key_paths.iter().flatten())
+        .enumerate()
+    {
+        let path = Path::new(key_path);
+        let issue = |message| match index {
+            0 => PreflightIssue::error(message),
+            _ => PreflightIssue::warning(message),
+        };
+
+        if !path.exists() {
+            issues.push(issue(format!("Key file {path:?} doesn't exist.")));
+            continue;
+        }
+
+        match sig_type {
+            SigType::Gpg => match helpers::keys::check_key_expiry(path) {
+                Ok(info) if info.is_expired => issues.push(issue(format!(
+                    "Key {} in {path:?} has expired.",
+                    info.fingerprint
+                ))),
+                Ok(_) => {}
+                Err(err) => issues.push(issue(format!(
+                    "Key file {path:?} is not a valid OpenPGP key - {err}"
+                ))),
+            },
+            SigType::Minisign | SigType::Signify => {
+                if let Err(err) = file_get_contents(path) {
+                    issues.push(issue(format!("Failed to read key file {path:?} - {err}")));
+                }
+            }
+        }
+    }
+
+    match config.render_repository().and_then(convert_repo_line) {
+        Ok(mut repository) => {
+            adjust_components(
+                &mut repository,
+                config.extra_components.as_deref().unwrap_or_default(),
+                config.exclude_components.as_deref().unwrap_or_default(),
+            );
+
+            // InRelease is only available for GPG signed repositories
+            let release_file = match sig_type {
+                SigType::Gpg => "InRelease",
+                SigType::Minisign | SigType::Signify => "Release",
+            };
+            let url = get_dist_url(&repository, release_file);
+
+            let mut request = http::Request::builder()
+                .method(http::Method::HEAD)
+                .uri(&url);
+            if config.use_subscription.is_none() {
+                if let Some(http_auth) = &config.http_auth {
+                    request = request.header("Authorization", format!("basic {http_auth}"));
+                }
+            }
+            let request = request.body(String::new())?;
+
+            let response: Result<http::Response<String>, Error> = http_client()?.request(request);
+            match response {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response)
+                    if config.use_subscription.is_some()
+                        && matches!(response.status().as_u16(), 401 | 403) =>
+                {
+                    issues.push(PreflightIssue::warning(format!(
+                        "HEAD '{url}' returned {} - subscription authentication is not checked.",
+                        response.status()
+                    )))
+                }
+                Ok(response) => issues.push(PreflightIssue::error(format!(
+                    "HEAD '{url}' returned {}.",
+                    response.status()
+                ))),
+                Err(err) => issues.push(PreflightIssue::error(format!(
+                    "HEAD '{url}' failed - {err}"
+                ))),
+            }
+        }
+        Err(err) => issues.push(PreflightIssue::error(format!(
+            "Invalid repository definition - {err}"
+        ))),
+    }
+
+    let base_dir = Path::new(&config.base_dir);
+    if !base_dir.exists() {
+        issues.push(PreflightIssue::error(format!(
+            "Base directory {base_dir:?} doesn't exist."
+        )));
+    } else if let Err(err) = nix::unistd::access(base_dir, nix::unistd::AccessFlags::W_OK) {
+        issues.push(PreflightIssue::error(format!(
+            "Base directory {base_dir:?} is not writable - {err}"
+        )));
+    }
+
+    Ok(issues)
+}
+
+// Warns about a configured key that has expired or will expire soon.
+fn warn_key_expiry(key_path: &Path) {
+    const EXPIRY_WARN_DAYS: i64 = 30;
+
+    match helpers::keys::check_key_expiry(key_path) {
+        Ok(info) if info.is_expired => {
+            eprintln!(
+                "WARNING: key {} in {key_path:?} has expired!",
+                info.fingerprint
+            )
+        }
+        Ok(info) => {
+            if let Some(expires_at) = info.expires_at {
+                let days = (expires_at - proxmox_time::epoch_i64()) / 86400;
+                if days < EXPIRY_WARN_DAYS {
+                    eprintln!(
+                        "WARNING: key {} in {key_path:?} expires in {days} day(s)!",
+                        info.fingerprint
+                    );
+                }
+            }
+        }
+        Err(err) => eprintln!("WARNING: failed to check expiry of key {key_path:?} - {err}"),
+    }
+}
+
+/// Create a new snapshot of the remote repository, fetching and storing files as needed.
+///
+/// Operates in three phases:
+/// - Fetch and verify release files
+/// - Fetch referenced indices according to config
+/// - Fetch binary packages referenced by package indices
+///
+/// Files will be linked in a temporary directory and only renamed to the final, valid snapshot
+/// directory at the end. In case of error, leftover `XXX.tmp` directories at the top level of
+/// `base_dir` can be safely removed once the next snapshot was successfully created, as they only
+/// contain hardlinks.
+///
+/// Only one snapshot of a mirror can be created at a time. If another process is currently
+/// creating one, this waits for up to `lock_timeout` before failing.
+///
+/// If `overwrite` is set, an existing snapshot with the same name is only replaced once the new
+/// one was created successfully. Pinned snapshots are never replaced.
+///
+/// If `components_filter` is set, only the given components are fetched. The resulting snapshot
+/// is valid for those components, but is marked as partial, see [is_partial_snapshot].
+///
+/// If `on_progress` is set, it will be called with [SnapshotEvent]s while the snapshot is being
+/// created.
+pub fn create_snapshot(
+    config: MirrorConfig,
+    snapshot: &Snapshot,
+    subscription: Option<SubscriptionKey>,
+    dry_run: bool,
+    overwrite: bool,
+    lock_timeout: Duration,
+    components_filter: Option<Vec<String>>,
+    on_progress: Option<Box<dyn Fn(SnapshotEvent) + Send>>,
+) -> Result<SnapshotCreationSummary, MirrorError> {
+    // a dry run doesn't write anything, so it can run alongside a regular one
+    let _lock = match dry_run {
+        true => None,
+        false => Some(lock_snapshot_creation(&config, lock_timeout)?),
+    };
+
+    let auth = subscription_auth(&config, subscription)?;
+
+    if config.sig_type.unwrap_or_default() == SigType::Gpg {
+        warn_key_expiry(Path::new(&config.key_path));
+    }
+
+    let partial_marker = partial_marker_file(&config, snapshot);
+    let run_cache = run_cache_file(&config, snapshot);
+    let mirror_id = config.id.clone();
+    let sources = snapshot_sources(&config, components_filter.as_deref())?;
+    let pinned = read_pinned(&config)?.contains(snapshot);
+
+    let mut config: ParsedMirrorConfig = config.try_into()?;
+
+    if let Some(filter) = &components_filter {
+        for component in filter {
+            if !config.repository.components.contains(component) {
+                return Err(format_err!(
+                    "Component '{component}' is not part of the mirrored repository."
+                )
+                .into());
+            }
+        }
+    }
+    if auth.is_some() {
+        config.auth = auth;
+    }
+
+    let notices = helpers::describe_policy(&config.weak_crypto);
+    if !notices.is_empty() {
+        println!("Security notice - the following weak cryptography exceptions are enabled:");
+        for notice in notices {
+            println!("- {notice}");
+        }
+    }
+
+    // a dry run never creates the snapshot directory
+    let snapshot_path = config.pool.get_path(Path::new(&snapshot.to_string()))?;
+    if !dry_run && snapshot_path.exists() {
+        if !overwrite {
+            return Err(MirrorError::SnapshotAlreadyExists(*snapshot));
+        }
+        if pinned {
+            return Err(format_err!(
+                "Snapshot {snapshot} is pinned, unpin it first to replace it."
+            )
+            .into());
+        }
+    }
+
+    let prefix = format!("{snapshot}.tmp");
+    let prefix = Path::new(&prefix);
+
+    if !dry_run {
+        config.run_cache = Some(RunCache::open(run_cache)?);
+    }
+
+    let start = Instant::now();
+    let mut timings = Timings::default();
+
+    let mut progress = MirrorProgress {
+        on_progress,
+        warnings: Vec::new(),
+        skip_count: 0,
+        skip_bytes: 0,
+        dry_run: Progress::new(),
+        total: Progress::new(),
+        components: HashMap::new(),
+    };
+
+    let parse_release = |res: FetchResult, name: &str| -> Result<ReleaseFile, Error> {
+        println!("Parsing {name}..");
+        let parsed: ReleaseFile = res.data[..].try_into()?;
+        println!(
+            "'{name}' file has {} referenced files..",
+            parsed.files.len()
+        );
+        Ok(parsed)
+    };
+
+    progress.emit(SnapshotEvent::Starting {
+        mirror: mirror_id,
+        snapshot: *snapshot,
+        dry_run,
+    });
+
+    // we want both on-disk for compat reasons, if both are available
+    progress.emit(SnapshotEvent::Phase {
+        name: "release".to_string(),
+    });
+    let phase = Instant::now();
+    let release = fetch_release(&config, prefix, true, dry_run)?
+        .map(|res| {
+            progress.total.update(&res);
+            parse_release(res, "Release")
+        })
+        .transpose()?;
+
+    let in_release = fetch_release(&config, prefix, false, dry_run)?
+        .map(|res| {
+            progress.total.update(&res);
+            parse_release(res, "InRelease")
+        })
+        .transpose()?;
+
+    // at least one must be available to proceed
+    let release = release
+        .or(in_release)
+        .ok_or_else(|| format_err!("Neither Release(.gpg) nor InRelease available!"))?;
+    timings.release = phase.elapsed();
 
     let mut per_component = HashMap::new();
     let mut others = Vec::new();
@@ -855,7 +1955,10 @@ pub fn create_snapshot(
         } else {
             continue;
         };
-        let skip_components = !&config.repository.components.contains(&reference.component);
+        let skip_components = !&config.repository.components.contains(&reference.component)
+            || components_filter
+                .as_ref()
+                .is_some_and(|filter| !filter.contains(&reference.component));
 
         let skip = skip_components
             || match &reference.file_type {
@@ -925,6 +2028,12 @@ pub fn create_snapshot(
         ),
     > = HashMap::new();
 
+    let mut package_priorities = HashMap::new();
+
+    progress.emit(SnapshotEvent::Phase {
+        name: "indices".to_string(),
+    });
+    let phase = Instant::now();
     let mut failed_references = Vec::new();
     for (component, references) in per_component {
         println!("\nFetching indices for component '{component}'");
@@ -958,6 +2067,7 @@ pub fn create_snapshot(
                     uncompressed_ref,
                     release.aquire_by_hash,
                     dry_run,
+                    &mut progress.warnings,
                 ) {
                     Ok(res) => res,
                     Err(err) if !reference.file_type.is_package_index() => {
@@ -966,11 +2076,11 @@ pub fn create_snapshot(
                             reference.file_type, reference.path
                         );
                         eprintln!("{msg}");
-                        progress.warnings.push(msg);
+                        progress.warnings.push(MirrorWarning::FetchError(msg));
                         failed_references.push(reference);
                         continue;
                     }
-                    Err(err) => return Err(err),
+                    Err(err) => return Err(err.into()),
                 };
                 fetch_progress.update(&res);
 
@@ -982,6 +2092,9 @@ pub fn create_snapshot(
                 match reference_type {
                     FileReferenceType::Packages(_, _) => {
                         let packages: PackagesFile = data[..].try_into()?;
+                        if config.skip.skip_priorities.is_some() {
+                            package_priorities.extend(parse_package_priorities(&data[..]));
+                        }
                         let size: usize = packages.files.iter().map(|p| p.size).sum();
                         println!("\t{} packages totalling {size}", packages.files.len());
                         component_deb_size += size;
@@ -1020,6 +2133,7 @@ pub fn create_snapshot(
 
         progress.total += fetch_progress;
     }
+    timings.indices = phase.elapsed();
     println!("Total deb size: {packages_size}");
     if !failed_references.is_empty() {
         eprintln!("Failed to download non-package-index references:");
@@ -1030,15 +2144,22 @@ pub fn create_snapshot(
 
     for (component, (packages_indices, source_packages_indices)) in per_component_indices {
         println!("\nFetching {component} packages..");
+        progress.emit(SnapshotEvent::Phase {
+            name: format!("packages ({component})"),
+        });
+        let phase = Instant::now();
         fetch_binary_packages(
             &config,
             &component,
             packages_indices,
+            &package_priorities,
             dry_run,
             prefix,
             &mut progress,
         )?;
+        timings.binary_packages += phase.elapsed();
 
+        let phase = Instant::now();
         fetch_source_packages(
             &config,
             &component,
@@ -1047,6 +2168,7 @@ pub fn create_snapshot(
             prefix,
             &mut progress,
         )?;
+        timings.source_packages += phase.elapsed();
     }
 
     if dry_run {
@@ -1068,36 +2190,508 @@ pub fn create_snapshot(
         );
     }
 
-    if !progress.warnings.is_empty() {
+    let components: BTreeMap<String, ProgressReport> = progress
+        .components
+        .iter()
+        .map(|(component, progress)| (component.clone(), progress.into()))
+        .collect();
+    if !components.is_empty() {
+        let width = components
+            .keys()
+            .map(String::len)
+            .fold("component".len(), max);
+        println!("\nPer-component package stats:");
+        println!(
+            "{:<width$}  {:>10}  {:>14}  {:>10}",
+            "component", "new", "new bytes", "re-used"
+        );
+        for (component, stats) in &components {
+            println!(
+                "{component:<width$}  {:>10}  {:>14}  {:>10}",
+                stats.new_files, stats.new_bytes, stats.reused_files
+            );
+        }
+    }
+
+    let warnings: Vec<String> = progress
+        .warnings
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect();
+    if !warnings.is_empty() {
         eprintln!("Warnings:");
-        for msg in progress.warnings {
+        for msg in &warnings {
             eprintln!("- {msg}");
         }
     }
+    let (skipped_packages, skipped_bytes) = (progress.skip_count, progress.skip_bytes);
 
-    if !dry_run {
+    let mut report: ProgressReport = if dry_run {
+        (&(progress.total + progress.dry_run)).into()
+    } else {
         println!("\nRotating temp. snapshot in-place: {prefix:?} -> \"{snapshot}\"");
+        progress.emit(SnapshotEvent::Phase {
+            name: "rotation".to_string(),
+        });
+        let phase = Instant::now();
         let locked = config.pool.lock()?;
+
+        link_snapshot_sources(&locked, prefix, &sources, config.sync)?;
+
+        match &components_filter {
+            Some(filter) => replace_file(
+                &partial_marker,
+                &serde_json::to_vec(filter).map_err(Error::from)?,
+                CreateOptions::default(),
+                true,
+            )?,
+            None => {
+                if partial_marker.exists() {
+                    std::fs::remove_file(&partial_marker)?;
+                }
+            }
+        }
+        if snapshot_path.exists() {
+            println!("Replacing existing snapshot {snapshot}");
+            locked.remove_dir(&snapshot_path)?;
+        }
         locked.rename(prefix, Path::new(&format!("{snapshot}")))?;
+        if let Some(cache) = config.run_cache.take() {
+            cache.remove()?;
+        }
+        timings.rotation = phase.elapsed();
+
+        (&progress.total).into()
+    };
+
+    timings.total = start.elapsed();
+    println!("\nTimings: {timings}");
+    let duration_secs = timings.total.as_secs_f64();
+    report.timings = Some(timings);
+
+    // `progress` is partially moved at this point
+    if let Some(on_progress) = &progress.on_progress {
+        on_progress(SnapshotEvent::Complete {
+            files: report.new_files + report.reused_files,
+            bytes: report.new_bytes,
+            warnings: warnings.len(),
+        });
     }
 
-    Ok(())
+    Ok(SnapshotCreationSummary {
+        snapshot: *snapshot,
+        progress: report,
+        skipped_packages,
+        skipped_bytes,
+        components,
+        warnings,
+        duration_secs,
+    })
 }
 
 /// Remove a snapshot by removing the corresponding snapshot directory. To actually free up space,
 /// a garbage collection needs to be run afterwards.
-pub fn remove_snapshot(config: &MirrorConfig, snapshot: &Snapshot) -> Result<(), Error> {
+///
+/// Pinned snapshots can only be removed with `force`, which also unpins them.
+pub fn remove_snapshot(
+    config: &MirrorConfig,
+    snapshot: &Snapshot,
+    force: bool,
+) -> Result<(), MirrorError> {
+    let pool: Pool = pool(config)?;
+    let path = pool.get_path(Path::new(&snapshot.to_string()))?;
+
+    let locked = pool.lock()?;
+    if !path.exists() {
+        return Err(MirrorError::SnapshotNotFound(*snapshot));
+    }
+
+    let mut pinned = read_pinned(config)?;
+    if pinned.contains(snapshot) {
+        if !force {
+            return Err(format_err!(
+                "Snapshot {snapshot} is pinned, unpin it first or use 'force'."
+            )
+            .into());
+        }
+        pinned.retain(|pinned| pinned != snapshot);
+        write_pinned(config, &pinned)?;
+    }
+
+    locked.remove_dir(&path)?;
+
+    let partial_marker = partial_marker_file(config, snapshot);
+    if partial_marker.exists() {
+        std::fs::remove_file(&partial_marker)?;
+    }
+
+    Ok(())
+}
+
+// Helper to read list of pinned snapshots, should be called with the pool locked.
+fn read_pinned(config: &MirrorConfig) -> Result<Vec<Snapshot>, Error> {
+    match proxmox_sys::fs::file_read_optional_string(pinned_file(config))? {
+        Some(raw) => Ok(serde_json::from_str(&raw)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Helper to write list of pinned snapshots, should be called with the pool locked.
+fn write_pinned(config: &MirrorConfig, pinned: &[Snapshot]) -> Result<(), Error> {
+    replace_file(
+        pinned_file(config),
+        &serde_json::to_vec(pinned)?,
+        CreateOptions::default(),
+        true,
+    )
+}
+
+/// List pinned snapshots, which are protected from removal.
+pub fn pinned_snapshots(config: &MirrorConfig) -> Result<Vec<Snapshot>, Error> {
+    let pool: Pool = pool(config)?;
+    let _lock = pool.lock()?;
+
+    let mut pinned = read_pinned(config)?;
+    pinned.sort_unstable();
+
+    Ok(pinned)
+}
+
+/// Pin a snapshot, protecting it from removal.
+pub fn pin_snapshot(config: &MirrorConfig, snapshot: &Snapshot) -> Result<(), Error> {
     let pool: Pool = pool(config)?;
     let path = pool.get_path(Path::new(&snapshot.to_string()))?;
+    if !path.exists() {
+        bail!("Snapshot {snapshot} doesn't exist.");
+    }
+
+    let _lock = pool.lock()?;
+    let mut pinned = read_pinned(config)?;
+    if pinned.contains(snapshot) {
+        println!("Snapshot {snapshot} is already pinned.");
+        return Ok(());
+    }
+    pinned.push(*snapshot);
+    pinned.sort_unstable();
 
-    pool.lock()?.remove_dir(&path)
+    write_pinned(config, &pinned)
+}
+
+/// Unpin a previously pinned snapshot.
+pub fn unpin_snapshot(config: &MirrorConfig, snapshot: &Snapshot) -> Result<(), Error> {
+    let pool: Pool = pool(config)?;
+
+    let _lock = pool.lock()?;
+    let mut pinned = read_pinned(config)?;
+    if !pinned.contains(snapshot) {
+        bail!("Snapshot {snapshot} is not pinned.");
+    }
+    pinned.retain(|pinned| pinned != snapshot);
+
+    write_pinned(config, &pinned)
 }
 
 /// Run a garbage collection on the underlying pool.
-pub fn gc(config: &MirrorConfig) -> Result<(usize, u64), Error> {
+pub fn gc(config: &MirrorConfig) -> Result<GcReport, MirrorError> {
+    let pool: Pool = pool(config)?;
+
+    Ok(pool.lock()?.gc()?)
+}
+
+/// Copy a single file referenced by `repo_path` (relative to the repository root) in `snapshot` to
+/// `dest`. The file contents are read from the pool and verified against the pool checksums.
+///
+/// Refuses to overwrite an existing `dest` unless `overwrite` is set.
+pub fn restore_file(
+    config: &MirrorConfig,
+    snapshot: &Snapshot,
+    repo_path: &str,
+    dest: &Path,
+    overwrite: bool,
+) -> Result<(), Error> {
+    let repo_path = Path::new(repo_path);
+    if repo_path
+        .components()
+        .any(|component| !matches!(component, std::path::Component::Normal(_)))
+    {
+        bail!("Repository path {repo_path:?} must be relative and must not contain '..'.");
+    }
+
+    if dest.exists() && !overwrite {
+        bail!("Destination {dest:?} already exists, use 'overwrite' to replace it.");
+    }
+
+    let pool: Pool = pool(config)?;
+    let snapshot_dir = pool.get_path(Path::new(&snapshot.to_string()))?;
+    if !snapshot_dir.exists() {
+        bail!("Snapshot {snapshot} doesn't exist.");
+    }
+
+    let path = snapshot_dir.join(repo_path);
+    if !path.exists() {
+        bail!("File {repo_path:?} not found in snapshot {snapshot}.");
+    }
+
+    let data = {
+        let locked = pool.lock()?;
+        let checksums = locked.checksums_for_link(&path)?;
+        locked.get_contents(&checksums, true)?
+    };
+
+    replace_file(dest, &data, CreateOptions::default(), true)?;
+    println!("Restored {repo_path:?} from snapshot {snapshot} to {dest:?}");
+
+    Ok(())
+}
+
+/// Search the `Packages` indices of all snapshots for `package`, optionally restricted to a specific
+/// `version`. If `version` is given, only the first match per snapshot is returned.
+pub fn search_package_in_snapshots(
+    config: &MirrorConfig,
+    package: &str,
+    version: Option<&str>,
+) -> Result<Vec<(Snapshot, PackageMatch)>, Error> {
+    let pool: Pool = pool(config)?;
+
+    let mut matches: Vec<(Snapshot, PackageMatch)> = Vec::new();
+    for snapshot in list_complete_snapshots(config)? {
+        let dists = pool
+            .get_path(Path::new(&snapshot.to_string()))?
+            .join("dists");
+        if !dists.exists() {
+            continue;
+        }
+
+        'snapshot: for index_entry in WalkDir::new(&dists) {
+            let index_entry = index_entry?;
+            if !index_entry.file_type().is_file() || index_entry.file_name() != "Packages" {
+                continue;
+            }
+
+            let raw = file_get_contents(index_entry.path())?;
+            let index: PackagesFile = match raw[..].try_into() {
+                Ok(index) => index,
+                Err(err) => {
+                    eprintln!("Failed to parse {:?} - {err}", index_entry.path());
+                    continue;
+                }
+            };
+
+            for entry in index.files {
+                if entry.package != package || version.is_some_and(|v| v != entry.version) {
+                    continue;
+                }
+
+                let found = PackageMatch {
+                    snapshot,
+                    version: entry.version,
+                    architecture: entry.architecture,
+                    file: entry.file,
+                };
+                // `all` packages are referenced by the indices of each architecture
+                if !matches.iter().any(|(_, existing)| *existing == found) {
+                    matches.push((snapshot, found));
+                }
+
+                if version.is_some() {
+                    break 'snapshot;
+                }
+            }
+        }
+    }
+
+    Ok(matches)
+}
+
+/// Verify the contents of all files in the underlying pool, optionally removing corrupt files so
+/// that they are fetched again when creating the next snapshot.
+pub fn scrub(config: &MirrorConfig, fix: bool) -> Result<ScrubReport, Error> {
+    let pool: Pool = pool(config)?;
+
+    pool.lock()?.scrub(fix)
+}
+
+/// Merge duplicate SHA-256 and SHA-512 checksum files of the same content in the underlying pool.
+pub fn compact(config: &MirrorConfig) -> Result<CompactReport, Error> {
+    let pool: Pool = pool(config)?;
+
+    pool.lock()?.compact()
+}
+
+/// Check that all files in the mirror's snapshots reference a file in the underlying pool,
+/// optionally removing dangling files.
+pub fn verify_links(config: &MirrorConfig, fix: bool) -> Result<VerifyLinksReport, Error> {
+    let pool: Pool = pool(config)?;
+
+    pool.lock()?.verify_links(fix)
+}
+
+/// Check the underlying pool for files in the mirror's snapshots without pool backing, and pool
+/// files not referenced by any snapshot. Nothing is removed.
+pub fn pool_check(config: &MirrorConfig) -> Result<PoolCheckReport, Error> {
+    let pool: Pool = pool(config)?;
+    let locked = pool.lock()?;
+
+    Ok(PoolCheckReport {
+        orphaned_links: locked.verify_links(false)?.dangling,
+        unlinked_pool_files: locked.list_unlinked_pool_files()?,
+    })
+}
+
+/// Export a CSV index of all files in the mirror's snapshots and their checksums, see
+/// [crate::pool::PoolLockGuard::export_index]. Writes to stdout if no `output` is given.
+///
+/// Returns the number of exported files.
+pub fn export_index(config: &MirrorConfig, output: Option<&Path>) -> Result<usize, Error> {
+    let pool: Pool = pool(config)?;
+    let locked = pool.lock()?;
+
+    match output {
+        Some(output) => locked.export_index(output),
+        None => locked.write_index(std::io::stdout().lock()),
+    }
+}
+
+/// Collect statistics about the underlying pool.
+pub fn pool_stats(config: &MirrorConfig) -> Result<PoolStats, Error> {
+    let pool: Pool = pool(config)?;
+
+    pool.lock()?.stats()
+}
+
+/// Compare the size of all snapshots to the size of the unique files in the underlying pool.
+pub fn dedup_stats(config: &MirrorConfig) -> Result<DeduplicationStats, Error> {
+    let pool: Pool = pool(config)?;
+
+    pool.lock()?.compute_dedup_ratio()
+}
+
+/// Copy a snapshot of mirror `src` to mirror `dest`, re-using files already contained in the
+/// destination pool.
+///
+/// Files are linked in a temporary directory first, which is renamed to `dest_snapshot` once all
+/// files were copied.
+pub fn copy_snapshot(
+    src: &MirrorConfig,
+    src_snapshot: &Snapshot,
+    dest: &MirrorConfig,
+    dest_snapshot: &Snapshot,
+) -> Result<CopyReport, Error> {
+    let src_pool: Pool = pool(src)?;
+    let dest_pool: Pool = pool(dest)?;
+
+    if !src_pool
+        .get_path(Path::new(&src_snapshot.to_string()))?
+        .exists()
+    {
+        return Err(MirrorError::SnapshotNotFound(*src_snapshot).into());
+    }
+
+    let _lock = lock_snapshot_creation(dest, Duration::ZERO)?;
+
+    if dest_pool
+        .get_path(Path::new(&dest_snapshot.to_string()))?
+        .exists()
+    {
+        return Err(MirrorError::SnapshotAlreadyExists(*dest_snapshot).into());
+    }
+
+    let prefix = format!("{dest_snapshot}.tmp");
+    let prefix = Path::new(&prefix);
+
+    let report =
+        src_pool
+            .lock()?
+            .copy_dir(Path::new(&src_snapshot.to_string()), &dest_pool, prefix)?;
+
+    let partial_marker = partial_marker_file(src, src_snapshot);
+    if partial_marker.exists() {
+        std::fs::copy(&partial_marker, partial_marker_file(dest, dest_snapshot))?;
+    }
+
+    dest_pool
+        .lock()?
+        .rename(prefix, Path::new(&dest_snapshot.to_string()))?;
+
+    Ok(report)
+}
+
+/// Import the files below `source_dir`, for example a repository tree created by another mirroring
+/// tool, as `snapshot` of mirror `config`.
+///
+/// Files are linked in a temporary directory first, which is renamed to `snapshot` once all files
+/// were imported. Files already contained in the pool are re-used, and only re-read if `verify` is
+/// set.
+pub fn import_snapshot(
+    config: &MirrorConfig,
+    source_dir: &Path,
+    snapshot: &Snapshot,
+    verify: bool,
+) -> Result<ImportStats, Error> {
+    if !source_dir.is_dir() {
+        bail!("Import source {source_dir:?} is not a directory.");
+    }
+
     let pool: Pool = pool(config)?;
 
-    pool.lock()?.gc()
+    let _lock = lock_snapshot_creation(config, Duration::ZERO)?;
+
+    if pool.get_path(Path::new(&snapshot.to_string()))?.exists() {
+        return Err(MirrorError::SnapshotAlreadyExists(*snapshot).into());
+    }
+
+    let prefix = format!("{snapshot}.tmp");
+    let prefix = Path::new(&prefix);
+
+    let locked = pool.lock()?;
+    // might be left over by an interrupted run
+    let tmp_path = pool.get_path(prefix)?;
+    if tmp_path.exists() {
+        locked.remove_dir(&tmp_path)?;
+    }
+
+    let stats = locked.import_dir(source_dir, prefix, verify)?;
+    locked.rename(prefix, Path::new(&snapshot.to_string()))?;
+
+    Ok(stats)
+}
+
+/// Copy the pool of a mirror to `new_base_dir` and (optionally) `new_pool_dir`, together with the
+/// mirror's metadata like pinned snapshots.
+///
+/// Returns the updated mirror config, which needs to be saved by the caller. The old pool is left
+/// intact and needs to be removed manually once the new one is in use.
+pub fn move_pool(
+    config: &MirrorConfig,
+    new_base_dir: String,
+    new_pool_dir: Option<String>,
+) -> Result<MirrorConfig, Error> {
+    let pool: Pool = pool(config)?;
+
+    let mut new_config = config.clone();
+    new_config.base_dir = new_base_dir;
+    new_config.pool_dir = new_pool_dir;
+
+    let _lock = lock_snapshot_creation(config, Duration::ZERO)?;
+
+    pool.lock()?.move_to(
+        &pool_dir(&new_config),
+        &mirror_dir(&new_config),
+        config.sync,
+    )?;
+
+    let pinned = pinned_file(config);
+    if pinned.exists() {
+        std::fs::copy(&pinned, pinned_file(&new_config))?;
+    }
+    for (snapshot, _state) in list_snapshots(config)? {
+        let partial_marker = partial_marker_file(config, &snapshot);
+        if partial_marker.exists() {
+            std::fs::copy(&partial_marker, partial_marker_file(&new_config, &snapshot))?;
+        }
+    }
+
+    Ok(new_config)
 }
 
 /// Print differences between two snapshots
@@ -1105,10 +2699,62 @@ pub fn diff_snapshots(
     config: &MirrorConfig,
     snapshot: &Snapshot,
     other_snapshot: &Snapshot,
-) -> Result<Diff, Error> {
+) -> Result<Diff, MirrorError> {
     let pool = pool(config)?;
-    pool.lock()?.diff_dirs(
+    for snapshot in [snapshot, other_snapshot] {
+        if !pool.get_path(Path::new(&snapshot.to_string()))?.exists() {
+            return Err(MirrorError::SnapshotNotFound(*snapshot));
+        }
+    }
+
+    Ok(pool.lock()?.diff_dirs(
         Path::new(&format!("{snapshot}")),
         Path::new(&format!("{other_snapshot}")),
-    )
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::PACKAGE_PRIORITIES;
+
+    #[test]
+    fn package_priorities_all_levels() {
+        let mut index = String::new();
+        for priority in PACKAGE_PRIORITIES {
+            index.push_str(&format!(
+                "Package: pkg-{priority}\nPriority: {priority}\nFilename: pool/main/p/pkg-{priority}.deb\n\n"
+            ));
+        }
+
+        let priorities = parse_package_priorities(index.as_bytes());
+        assert_eq!(priorities.len(), PACKAGE_PRIORITIES.len());
+        for priority in PACKAGE_PRIORITIES {
+            assert_eq!(
+                priorities
+                    .get(&format!("pool/main/p/pkg-{priority}.deb"))
+                    .map(String::as_str),
+                Some(*priority)
+            );
+        }
+    }
+
+    #[test]
+    fn package_priorities_incomplete_stanzas() {
+        // no priority, priority after the filename and no trailing empty line
+        let index = "Package: a\nFilename: pool/a.deb\n\nPackage: b\nFilename: pool/b.deb\nPriority: optional\n\nPackage: c\nPriority: extra\nFilename: pool/c.deb";
+
+        let priorities = parse_package_priorities(index.as_bytes());
+        assert_eq!(priorities.len(), 2);
+        assert_eq!(priorities.get("pool/a.deb"), None);
+        assert_eq!(
+            priorities.get("pool/b.deb").map(String::as_str),
+            Some("optional")
+        );
+        assert_eq!(
+            priorities.get("pool/c.deb").map(String::as_str),
+            Some("extra")
+        );
+    }
 }
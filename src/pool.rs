@@ -1,21 +1,31 @@
 use std::{
     cmp::max,
-    collections::{HashMap, hash_map::Entry},
+    collections::{BTreeSet, HashMap, HashSet, hash_map::Entry},
     fs::{File, Metadata, hard_link},
+    io::{Read, Write},
     ops::Deref,
     os::linux::fs::MetadataExt,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use anyhow::{Error, bail, format_err};
-use nix::unistd;
+use nix::{libc, sys::statvfs::statvfs, unistd};
+use openssl::hash::{Hasher, MessageDigest};
 
 use proxmox_apt::deb822::CheckSums;
 use proxmox_sys::fs::{CreateOptions, create_path, file_get_contents, replace_file};
 use proxmox_time::epoch_i64;
 use walkdir::WalkDir;
 
-use crate::types::Diff;
+use crate::{
+    ProgressReport,
+    config::{HashPreference, SyncStrategy},
+    types::{
+        CompactReport, CopyReport, DeduplicationStats, Diff, DiffMember, GcReport, ImportStats,
+        MirrorError, PoolStats, ScrubReport, VerifyLinksReport,
+    },
+};
 
 #[derive(Debug)]
 /// Pool consisting of two (possibly overlapping) directory trees:
@@ -24,9 +34,20 @@ use crate::types::Diff;
 ///
 /// Files are considered orphaned and eligible for GC if they either only exist in pool_dir
 /// or only exist in link dir.
+///
+/// Checksum files are only named after APT checksums (`sha256`/`sha512`), including internally
+/// generated files like a snapshot's `.sources.json`. Every file in link_dir must map back to such
+/// a checksum via its inode, which copying, syncing, diffing and verifying rely on, so there is no
+/// separate storage for other hash types.
+///
+/// If pool_dir and link_dir are on different file systems, files are copied into link_dir instead
+/// of hardlinked. Such copies are matched to their checksum files by content hash.
 pub(crate) struct Pool {
     pool_dir: PathBuf,
     link_dir: PathBuf,
+    cross_device: bool,
+    hash_preference: HashPreference,
+    gc_io_limit: Option<u32>,
 }
 
 /// Lock guard used to guard against concurrent modification
@@ -53,6 +74,9 @@ impl Pool {
         Ok(Self {
             pool_dir: pool.to_path_buf(),
             link_dir: link_dir.to_path_buf(),
+            cross_device: is_cross_device(link_dir, pool)?,
+            hash_preference: HashPreference::default(),
+            gc_io_limit: None,
         })
     }
 
@@ -69,18 +93,92 @@ impl Pool {
         Ok(Self {
             pool_dir: pool.to_path_buf(),
             link_dir: link_dir.to_path_buf(),
+            cross_device: is_cross_device(link_dir, pool)?,
+            hash_preference: HashPreference::default(),
+            gc_io_limit: None,
         })
     }
 
+    /// Open a pool, creating `pool_dir` and `link_dir` first if they don't exist yet.
+    pub(crate) fn open_or_create(link_dir: &Path, pool: &Path) -> Result<Self, Error> {
+        // `create_path` is a no-op for existing directories, so no separate existence check
+        create_path(pool, None, None)?;
+        create_path(link_dir, None, None)?;
+
+        Self::open(link_dir, pool)
+    }
+
+    /// Set which checksums are used for storing and looking up files.
+    pub(crate) fn set_hash_preference(&mut self, hash_preference: HashPreference) {
+        self.hash_preference = hash_preference;
+    }
+
+    /// Limit the number of file removals per second during GC.
+    pub(crate) fn set_gc_io_limit(&mut self, ops_per_sec: Option<u32>) {
+        self.gc_io_limit = ops_per_sec;
+    }
+
+    /// Approximate size of the pool, without locking it or walking its files.
+    ///
+    /// This is the space used on the file system containing `pool_dir`, so it includes any other
+    /// data stored on that file system.
+    pub(crate) fn approximate_pool_size(&self) -> Result<u64, Error> {
+        let stat = statvfs(&self.pool_dir).map_err(|err| {
+            format_err!("Failed to query filesystem of {:?} - {err}", self.pool_dir)
+        })?;
+
+        let used_blocks = stat.blocks().saturating_sub(stat.blocks_free());
+        Ok(used_blocks as u64 * stat.fragment_size() as u64)
+    }
+
+    /// Approximate number of files in the pool, without locking it or reading any file metadata.
+    ///
+    /// Files are usually stored under both checksums, so the entries of the larger checksum
+    /// directory are counted.
+    pub(crate) fn pool_file_count(&self) -> Result<usize, Error> {
+        let mut count = 0;
+        for dir in ["sha256", "sha512"] {
+            let path = self.pool_dir.join(dir);
+            match std::fs::read_dir(&path) {
+                Ok(entries) => count = max(count, entries.count()),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => bail!("Failed to read {path:?} - {err}"),
+            }
+        }
+
+        Ok(count)
+    }
+
     /// Lock a pool to add/remove files or links, or protect against concurrent modifications.
     pub(crate) fn lock(&self) -> Result<PoolLockGuard, Error> {
+        self.lock_inner(true)
+    }
+
+    /// Lock a pool to protect against concurrent modifications while only reading from it, e.g.
+    /// when syncing it to a medium.
+    ///
+    /// Multiple shared locks can be held at the same time, so the returned guard must not be used
+    /// to add or remove files or links.
+    pub(crate) fn lock_shared(&self) -> Result<PoolLockGuard, Error> {
+        self.lock_inner(false)
+    }
+
+    fn lock_inner(&self, exclusive: bool) -> Result<PoolLockGuard, Error> {
         let timeout = std::time::Duration::new(30, 0);
-        let lock = Some(proxmox_sys::fs::open_file_locked(
+        let start = std::time::Instant::now();
+        let lock = match proxmox_sys::fs::open_file_locked(
             self.lock_path(),
             timeout,
-            true,
+            exclusive,
             CreateOptions::default(),
-        )?);
+        ) {
+            Ok(lock) => Some(lock),
+            Err(err) if start.elapsed() >= timeout => {
+                eprintln!("Failed to lock pool - {err}");
+                return Err(MirrorError::PoolLockTimeout.into());
+            }
+            Err(err) => return Err(err),
+        };
 
         Ok(PoolLockGuard {
             pool: self,
@@ -116,8 +214,32 @@ impl Pool {
         Ok(data)
     }
 
-    // Helper to return all possible checksum file paths for a given checksum. Checksums considered
-    // insecure will be ignored.
+    /// Returns the number of links to the file for `checksums` outside of `pool_dir`, i.e., how
+    /// often it is referenced in `link_dir`.
+    ///
+    /// Only checksum files derivable from `checksums` are accounted for as pool-internal links.
+    pub(crate) fn link_count_for(&self, checksums: &CheckSums) -> Result<u64, Error> {
+        let csum_paths = self.get_checksum_paths(checksums)?;
+
+        let source = csum_paths
+            .iter()
+            .find(|path| path.exists())
+            .ok_or_else(|| format_err!("Pool doesn't contain file with this checksum."))?;
+
+        let meta = source.metadata()?;
+        let inode = meta.st_ino();
+
+        let pool_links = csum_paths
+            .iter()
+            .filter_map(|path| path.metadata().ok())
+            .filter(|meta| meta.st_ino() == inode)
+            .count() as u64;
+
+        Ok(meta.st_nlink().saturating_sub(pool_links))
+    }
+
+    // Helper to return all possible checksum file paths for a given checksum, according to the
+    // pool's hash preference. Checksums considered insecure will be ignored.
     fn get_checksum_paths(&self, checksums: &CheckSums) -> Result<Vec<PathBuf>, Error> {
         if !checksums.is_secure() {
             bail!("pool cannot operate on files lacking secure checksum!");
@@ -125,18 +247,28 @@ impl Pool {
 
         let mut res = Vec::new();
 
-        if let Some(sha512) = checksums.sha512 {
-            let mut pool = self.pool_dir.clone();
-            pool.push("sha512");
-            pool.push(hex::encode(sha512));
-            res.push(pool);
+        if self.hash_preference != HashPreference::Sha256Only {
+            match checksums.sha512 {
+                Some(sha512) => {
+                    let mut pool = self.pool_dir.clone();
+                    pool.push("sha512");
+                    pool.push(hex::encode(sha512));
+                    res.push(pool);
+                }
+                None if self.hash_preference == HashPreference::Sha512Only => {
+                    bail!("pool is restricted to SHA-512, but file lacks SHA-512 checksum!");
+                }
+                None => {}
+            }
         }
 
-        if let Some(sha256) = checksums.sha256 {
-            let mut pool = self.pool_dir.clone();
-            pool.push("sha256");
-            pool.push(hex::encode(sha256));
-            res.push(pool);
+        if self.hash_preference != HashPreference::Sha512Only {
+            if let Some(sha256) = checksums.sha256 {
+                let mut pool = self.pool_dir.clone();
+                pool.push("sha256");
+                pool.push(hex::encode(sha256));
+                res.push(pool);
+            }
         }
 
         if res.is_empty() {
@@ -146,6 +278,49 @@ impl Pool {
         Ok(res)
     }
 
+    // Helper to look up the checksums of a file in `link_dir` via its inode. If the pool spans
+    // multiple file systems, files not sharing an inode with a checksum file are matched by
+    // content hash instead.
+    fn lookup_link_csum(
+        &self,
+        path: &Path,
+        meta: &Metadata,
+        inode_map: &HashMap<u64, CheckSums>,
+    ) -> Result<Option<CheckSums>, Error> {
+        if let Some(csum) = inode_map.get(&meta.st_ino()) {
+            return Ok(Some(csum.clone()));
+        }
+
+        if !self.cross_device {
+            return Ok(None);
+        }
+
+        let mut sha256 = [0u8; 32];
+        hex::decode_to_slice(hash_file(path, MessageDigest::sha256())?, &mut sha256)?;
+        let mut csum = CheckSums {
+            sha256: Some(sha256),
+            ..Default::default()
+        };
+
+        // checksum files might only exist for SHA512
+        if !self.contains(&csum) {
+            let mut sha512 = [0u8; 64];
+            hex::decode_to_slice(hash_file(path, MessageDigest::sha512())?, &mut sha512)?;
+            csum = CheckSums {
+                sha512: Some(sha512),
+                ..Default::default()
+            };
+        }
+
+        for pool_path in self.get_checksum_paths(&csum)? {
+            if let Ok(meta) = pool_path.metadata() {
+                return Ok(inode_map.get(&meta.st_ino()).cloned());
+            }
+        }
+
+        Ok(None)
+    }
+
     fn path_in_pool(&self, path: &Path) -> bool {
         path.starts_with(&self.pool_dir)
     }
@@ -246,7 +421,24 @@ impl PoolLockGuard<'_> {
     /// - iterate over source pool links, add missing checksum files and links to target pool
     /// - iterate over target pool links, remove those which are not present in source pool
     /// - if links were removed in phase 3, run GC on target pool
-    pub(crate) fn sync_pool(&self, target: &Pool, verify: bool) -> Result<(), Error> {
+    ///
+    /// Paths below `skip` (relative to `link_dir`) are neither synced nor kept in the target pool.
+    ///
+    /// If `since` is set, links which already exist in the target pool and whose file wasn't
+    /// modified or linked since that epoch are skipped without further checks, see
+    /// [Self::link_changed_since].
+    ///
+    /// `on_file_added` is called with the path (relative to `link_dir`) and size of each file
+    /// added to the target pool.
+    pub(crate) fn sync_pool(
+        &self,
+        target: &Pool,
+        verify: bool,
+        sync_strategy: SyncStrategy,
+        skip: &[PathBuf],
+        since: Option<i64>,
+        on_file_added: Option<&dyn Fn(&Path, u64)>,
+    ) -> Result<ProgressReport, Error> {
         let target = target.lock()?;
 
         let (inode_map, total_link_count) = self.get_inode_csum_map()?;
@@ -257,6 +449,9 @@ impl PoolLockGuard<'_> {
         let mut added_count = 0usize;
         let mut added_size = 0usize;
         let mut link_count = 0usize;
+        let mut unchanged_count = 0usize;
+        // new files and links to FSYNC with the batch strategy
+        let mut written = Vec::new();
 
         println!("Looking for new files and links..");
         let mut checked_link_count = 0;
@@ -271,26 +466,56 @@ impl PoolLockGuard<'_> {
                 continue;
             };
 
+            let rel_path = path.strip_prefix(&self.pool.link_dir)?;
+            if skip.iter().any(|skipped| rel_path.starts_with(skipped)) {
+                continue;
+            }
+
+            if let Some(since) = since {
+                // links removed from the target since, e.g. by pruning, need to be re-created
+                if !Self::link_changed_since(&meta, since) && target.get_path(rel_path)?.exists() {
+                    unchanged_count += 1;
+                    continue;
+                }
+            }
+
             checked_link_count += 1;
 
-            match inode_map.get(&meta.st_ino()) {
+            match self.lookup_link_csum(&path, &meta, &inode_map)? {
                 Some(csum) => {
-                    if target.contains(csum) {
+                    if target.contains(&csum) {
                         if verify {
-                            target.get_contents(csum, true)?;
+                            target.get_contents(&csum, true)?;
                         }
                     } else {
-                        let contents = self.get_contents(csum, verify)?;
-                        target.add_file(&contents, csum, verify)?;
+                        let contents = self.get_contents(&csum, verify)?;
+                        target.add_file(
+                            &contents,
+                            &csum,
+                            sync_strategy == SyncStrategy::PerFile,
+                        )?;
+                        if sync_strategy == SyncStrategy::Batch {
+                            written.extend(target.get_checksum_paths(&csum)?);
+                        }
 
                         added_count += 1;
                         added_size += contents.len();
+
+                        if let Some(on_file_added) = on_file_added {
+                            on_file_added(
+                                path.strip_prefix(&self.pool.link_dir)?,
+                                contents.len() as u64,
+                            );
+                        }
                     }
 
                     let path = path.strip_prefix(&self.pool.link_dir)?;
 
-                    if target.link_file(csum, path)? {
+                    if target.link_file(&csum, path)? {
                         link_count += 1;
+                        if sync_strategy == SyncStrategy::Batch {
+                            written.push(target.get_path(path)?);
+                        }
                     }
                 }
                 None => bail!("Found file not part of source pool: {path:?}"),
@@ -306,6 +531,14 @@ impl PoolLockGuard<'_> {
         println!(
             "Stats: checked {checked_link_count} links; added {added_count} files ({added_size}b) / {link_count} links to target pool"
         );
+        if unchanged_count > 0 {
+            println!("Skipped {unchanged_count} links unchanged since last sync.");
+        }
+
+        if !written.is_empty() {
+            println!("Syncing new files to disk..");
+            target.fsync_paths(&written)?;
+        }
 
         println!("Looking for vanished files..");
         let mut vanished_count = 0usize;
@@ -324,8 +557,10 @@ impl PoolLockGuard<'_> {
             };
 
             let rel_path = path.strip_prefix(&target.pool.link_dir)?;
-            if !self.pool.get_path(rel_path)?.exists() {
-                match target_inode_map.get(&meta.st_ino()) {
+            if !self.pool.get_path(rel_path)?.exists()
+                || skip.iter().any(|skipped| rel_path.starts_with(skipped))
+            {
+                match target.lookup_link_csum(&path, &meta, &target_inode_map)? {
                     Some(_csum) => {
                         target.unlink_file(&path, true)?;
                         vanished_count += 1;
@@ -346,8 +581,11 @@ impl PoolLockGuard<'_> {
                 println!("Found {orphaned_count} orphaned files.");
             }
             println!("Running GC now.");
-            let (count, size) = target.gc()?;
-            println!("GC removed {count} files, freeing {size}b");
+            let report = target.gc()?;
+            println!(
+                "GC removed {} files, freeing {}b",
+                report.files_removed, report.bytes_freed
+            );
         } else {
             println!("None found.")
         }
@@ -356,6 +594,212 @@ impl PoolLockGuard<'_> {
             "Syncing done: added {added_count} files ({added_size}b) / {link_count} links to target pool"
         );
 
+        Ok(ProgressReport {
+            new_files: added_count,
+            new_bytes: added_size,
+            reused_files: total_count.saturating_sub(added_count),
+            timings: None,
+        })
+    }
+
+    /// Whether the file of a link was modified or linked (which updates its inode's `ctime`) at or
+    /// after `since`.
+    fn link_changed_since(meta: &Metadata, since: i64) -> bool {
+        max(meta.st_mtime(), meta.st_ctime()) >= since
+    }
+
+    /// Copies all files below `path` (relative to `link_dir`) into the `target` pool and links them
+    /// at `target_path` (relative to the target's `link_dir`). Files already contained in the
+    /// target pool are re-used instead of copied.
+    ///
+    /// `target` may share its `pool_dir` with this pool, in which case it is not locked again.
+    pub(crate) fn copy_dir(
+        &self,
+        path: &Path,
+        target: &Pool,
+        target_path: &Path,
+    ) -> Result<CopyReport, Error> {
+        let target = if target.lock_path() == self.lock_path() {
+            // already protected by our own lock
+            PoolLockGuard {
+                pool: target,
+                _lock: None,
+            }
+        } else {
+            target.lock()?
+        };
+
+        let source_dir = self.get_path(path)?;
+        let (inode_map, _link_count) = self.get_inode_csum_map()?;
+        let mut report = CopyReport::default();
+        let mut written = Vec::new();
+
+        for link_entry in WalkDir::new(&source_dir).into_iter() {
+            let path = link_entry?.into_path();
+
+            let meta = path.metadata()?;
+            if !meta.is_file() {
+                continue;
+            };
+
+            let csum = self
+                .lookup_link_csum(&path, &meta, &inode_map)?
+                .ok_or_else(|| format_err!("Found file not part of source pool: {path:?}"))?;
+
+            if target.contains(&csum) {
+                report.files_shared += 1;
+            } else {
+                let contents = self.get_contents(&csum, false)?;
+                target.add_file(&contents, &csum, false)?;
+                written.extend(target.get_checksum_paths(&csum)?);
+                report.files_copied += 1;
+                report.bytes_copied += contents.len() as u64;
+            }
+
+            let link_path = target_path.join(path.strip_prefix(&source_dir)?);
+            if target.link_file(&csum, &link_path)? {
+                written.push(target.get_path(&link_path)?);
+            }
+        }
+
+        target.fsync_paths(&written)?;
+
+        Ok(report)
+    }
+
+    /// Imports all files below `source_dir` into the pool and links them below `dest_snapshot`
+    /// (relative to `link_dir`), holding the pool lock for the whole operation.
+    ///
+    /// Checksums are calculated from the file contents, existing pool files are only re-read if
+    /// `verify` is set.
+    pub(crate) fn import_dir(
+        &self,
+        source_dir: &Path,
+        dest_snapshot: &Path,
+        verify: bool,
+    ) -> Result<ImportStats, Error> {
+        let mut stats = ImportStats::default();
+        let mut written = Vec::new();
+
+        for entry in WalkDir::new(source_dir).into_iter() {
+            let path = entry?.into_path();
+
+            let meta = path.metadata()?;
+            if !meta.is_file() {
+                continue;
+            };
+
+            let data = file_get_contents(&path)?;
+            let checksums = CheckSums {
+                sha256: Some(openssl::sha::sha256(&data)),
+                sha512: Some(openssl::sha::sha512(&data)),
+                ..Default::default()
+            };
+
+            if self.contains(&checksums) {
+                if verify {
+                    self.get_contents(&checksums, true)?;
+                }
+                stats.reused_files += 1;
+            } else {
+                self.add_file(&data, &checksums, false)?;
+                written.extend(self.get_checksum_paths(&checksums)?);
+                stats.added_files += 1;
+                stats.added_bytes += data.len() as u64;
+            }
+
+            let link_path = dest_snapshot.join(path.strip_prefix(source_dir)?);
+            if self.link_file(&checksums, &link_path)? {
+                written.push(self.get_path(&link_path)?);
+                stats.links += 1;
+            }
+        }
+
+        self.fsync_paths(&written)?;
+
+        Ok(stats)
+    }
+
+    /// Copy the whole pool to `new_pool_dir` and `new_link_dir`, e.g. to migrate it to another disk.
+    ///
+    /// Checksum files sharing an inode are copied once and hardlinked in the new pool, and all files
+    /// in `link_dir` are re-created as links to the copied checksum files. The new pool is verified
+    /// using [Self::scrub] afterwards. This pool is left intact, updating the config and removing
+    /// the old pool is up to the caller.
+    pub(crate) fn move_to(
+        &self,
+        new_pool_dir: &Path,
+        new_link_dir: &Path,
+        sync: bool,
+    ) -> Result<(), Error> {
+        if new_pool_dir == self.pool.pool_dir {
+            bail!("New pool dir must differ from current pool dir {new_pool_dir:?}.");
+        }
+
+        let mut new_pool = Pool::create(new_link_dir, new_pool_dir)?;
+        new_pool.set_hash_preference(self.pool.hash_preference);
+        let new_pool = new_pool.lock()?;
+
+        // inode in old pool => path of its first copy in new pool
+        let mut copied: HashMap<u64, PathBuf> = HashMap::new();
+
+        for pool_entry in WalkDir::new(&self.pool.pool_dir).into_iter() {
+            let path = pool_entry?.into_path();
+            if path == self.lock_path() {
+                continue;
+            }
+
+            let meta = path.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+
+            let target = new_pool_dir.join(path.strip_prefix(&self.pool.pool_dir)?);
+            ensure_parent_dir_exists(&target)?;
+
+            match copied.entry(meta.st_ino()) {
+                Entry::Occupied(first) => {
+                    link_file_do(first.get(), &target, false)?;
+                }
+                Entry::Vacant(entry) => {
+                    // the new pool dir might be shared with other pools already containing this file
+                    if !target.exists() {
+                        let data = file_get_contents(&path)?;
+                        replace_file(&target, &data, CreateOptions::default(), sync)?;
+                    }
+                    entry.insert(target);
+                }
+            }
+        }
+
+        let (inode_map, _link_count) = self.get_inode_csum_map()?;
+
+        for link_entry in WalkDir::new(&self.pool.link_dir).into_iter() {
+            let path = link_entry?.into_path();
+            if self.path_in_pool(&path) {
+                continue;
+            }
+
+            let meta = path.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+
+            let csum = self
+                .lookup_link_csum(&path, &meta, &inode_map)?
+                .ok_or_else(|| format_err!("Found file not part of pool: {path:?}"))?;
+
+            new_pool.link_file(&csum, path.strip_prefix(&self.pool.link_dir)?)?;
+        }
+
+        let report = new_pool.scrub(false)?;
+        if report.mismatched > 0 {
+            bail!(
+                "Verification of new pool {new_pool_dir:?} failed - {} files with mismatching contents.",
+                report.mismatched
+            );
+        }
+
         Ok(())
     }
 
@@ -380,7 +824,88 @@ impl PoolLockGuard<'_> {
         ensure_parent_dir_exists(&first)?;
         replace_file(&first, data, CreateOptions::default(), sync)?;
         for target in csum_paths {
-            link_file_do(&first, &target)?;
+            link_file_do(&first, &target, false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the checksum file(s) for `checksums` with `new_data`, e.g. after the existing file
+    /// failed verification and was fetched again.
+    ///
+    /// The data is written to a temporary file in `pool_dir` and verified against `checksums`
+    /// before being renamed over the checksum file. Hardlinks in `link_dir` referencing the old
+    /// checksum file are re-created to reference the new one. Copies in a `link_dir` on another
+    /// file system are not updated.
+    pub(crate) fn replace_file_in_pool(
+        &self,
+        new_data: &[u8],
+        checksums: &CheckSums,
+        sync: bool,
+    ) -> Result<(), Error> {
+        let csum_paths = self.pool.get_checksum_paths(checksums)?;
+        let first = &csum_paths[0];
+        let file_name = first
+            .file_name()
+            .ok_or_else(|| format_err!("Invalid checksum path {first:?}"))?
+            .to_string_lossy();
+
+        let tmp_path = self.pool.pool_dir.join(format!(".{file_name}.tmp"));
+        replace_file(&tmp_path, new_data, CreateOptions::default(), sync)?;
+        if let Err(err) = checksums.verify(&file_get_contents(&tmp_path)?) {
+            let _ = std::fs::remove_file(&tmp_path);
+            bail!("Failed to verify replacement for {first:?} - {err}");
+        }
+
+        // links need to be collected while the old file is still in place
+        let old_inode = csum_paths
+            .iter()
+            .find_map(|path| path.metadata().ok())
+            .map(|meta| meta.st_ino());
+        let mut links = Vec::new();
+        if let Some(old_inode) = old_inode {
+            for link_entry in WalkDir::new(&self.pool.link_dir).into_iter() {
+                let path = link_entry?.into_path();
+                if self.pool.path_in_pool(&path) {
+                    continue;
+                }
+
+                let meta = path.symlink_metadata()?;
+                if meta.is_file() && meta.st_ino() == old_inode {
+                    links.push(path);
+                }
+            }
+        }
+
+        std::fs::rename(&tmp_path, first)
+            .map_err(|err| format_err!("Failed to rename {tmp_path:?} to {first:?} - {err}"))?;
+
+        for path in &csum_paths[1..] {
+            if path.exists() {
+                unistd::unlink(path)
+                    .map_err(|err| format_err!("Failed to remove {path:?} - {err}"))?;
+            }
+            link_file_do(first, path, false)?;
+        }
+
+        for link in links {
+            let link_name = link
+                .file_name()
+                .ok_or_else(|| format_err!("Invalid link path {link:?}"))?
+                .to_string_lossy();
+            let tmp_link = link.with_file_name(format!(".{link_name}.tmp"));
+            hard_link(first, &tmp_link)
+                .map_err(|err| format_err!("Failed to link {first:?} at {tmp_link:?} - {err}"))?;
+            std::fs::rename(&tmp_link, &link)
+                .map_err(|err| format_err!("Failed to rename {tmp_link:?} to {link:?} - {err}"))?;
+        }
+
+        if sync {
+            if let Some(parent) = first.parent() {
+                File::open(parent)
+                    .and_then(|dir| dir.sync_all())
+                    .map_err(|err| format_err!("Failed to fsync {parent:?} - {err}"))?;
+            }
         }
 
         Ok(())
@@ -409,7 +934,7 @@ impl PoolLockGuard<'_> {
             bail!("Cannot link to file outside of pool.");
         }
 
-        link_file_do(source, &path)
+        link_file_do(source, &path, self.pool.cross_device)
     }
 
     /// Unlink a previously linked file at `path` (absolute, must be below `link_dir`). Optionally
@@ -456,15 +981,17 @@ impl PoolLockGuard<'_> {
     /// - any checksum files that have no links outside of `pool_dir`
     /// - any files in `link_dir` that have no corresponding checksum files
     /// - any empty directories below `link_dir` remaining after the file removal
-    pub(crate) fn gc(&self) -> Result<(usize, u64), Error> {
+    ///
+    /// Copies in `link_dir` of a cross-device pool keep their checksum files alive.
+    pub(crate) fn gc(&self) -> Result<GcReport, Error> {
         let (inode_map, _link_count) = self.get_inode_csum_map()?;
+        let (copies, copied_inodes) = self.collect_copies(&inode_map)?;
 
-        let mut count = 0;
-        let mut size = 0;
+        let mut report = GcReport::default();
+        let start = Instant::now();
 
         let handle_entry = |entry: Result<walkdir::DirEntry, walkdir::Error>,
-                            count: &mut usize,
-                            size: &mut u64,
+                            report: &mut GcReport,
                             remove_empty_dir: bool|
          -> Result<(), Error> {
             let path = entry?.into_path();
@@ -493,6 +1020,10 @@ impl PoolLockGuard<'_> {
                         println!("Something fishy going on with {path:?}");
                         false
                     }
+                    std::cmp::Ordering::Equal if copied_inodes.contains(&meta.st_ino()) => {
+                        // still has copies in link dir
+                        false
+                    }
                     std::cmp::Ordering::Equal => {
                         // only checksum files remaining
                         println!("Removing {path:?}");
@@ -503,15 +1034,25 @@ impl PoolLockGuard<'_> {
                         false
                     }
                 }
+            } else if copies.contains(&path) {
+                false
             } else {
                 println!("Removing orphan: {path:?}");
+                report.orphaned_links_removed += 1;
                 true
             };
 
             if remove {
-                *count += 1;
-                *size += meta.st_size();
+                report.files_removed += 1;
+                report.bytes_freed += meta.st_size();
                 unistd::unlink(&path)?;
+
+                if let Some(limit) = self.pool.gc_io_limit {
+                    let rate = report.files_removed as f64 / start.elapsed().as_secs_f64();
+                    if rate > limit as f64 {
+                        std::thread::sleep(Duration::from_secs_f64(1.0 / limit as f64));
+                    }
+                }
             }
             Ok(())
         };
@@ -519,12 +1060,46 @@ impl PoolLockGuard<'_> {
         WalkDir::new(&self.pool.link_dir)
             .contents_first(true)
             .into_iter()
-            .try_for_each(|entry| handle_entry(entry, &mut count, &mut size, true))?;
+            .try_for_each(|entry| handle_entry(entry, &mut report, true))?;
         WalkDir::new(&self.pool.pool_dir)
             .into_iter()
-            .try_for_each(|entry| handle_entry(entry, &mut count, &mut size, false))?;
+            .try_for_each(|entry| handle_entry(entry, &mut report, false))?;
 
-        Ok((count, size))
+        report.pool_files_remaining = self.stats()?.files;
+
+        Ok(report)
+    }
+
+    // Helper to collect copies in `link_dir` of a cross-device pool and the inodes of the checksum
+    // files they reference. Copies don't show up in the link count of checksum files.
+    fn collect_copies(
+        &self,
+        inode_map: &HashMap<u64, CheckSums>,
+    ) -> Result<(HashSet<PathBuf>, HashSet<u64>), Error> {
+        let mut copies = HashSet::new();
+        let mut copied_inodes = HashSet::new();
+        if !self.pool.cross_device {
+            return Ok((copies, copied_inodes));
+        }
+
+        for link_entry in WalkDir::new(&self.pool.link_dir).into_iter() {
+            let path = link_entry?.into_path();
+            let meta = path.metadata()?;
+            if !meta.is_file() || inode_map.contains_key(&meta.st_ino()) {
+                continue;
+            }
+
+            if let Some(csum) = self.lookup_link_csum(&path, &meta, inode_map)? {
+                for csum_path in self.get_checksum_paths(&csum)? {
+                    if let Ok(csum_meta) = csum_path.metadata() {
+                        copied_inodes.insert(csum_meta.st_ino());
+                    }
+                }
+                copies.insert(path);
+            }
+        }
+
+        Ok((copies, copied_inodes))
     }
 
     /// Destroy this pool instance by removing `link_dir` and running a GC. The pool base dir will remain.
@@ -558,14 +1133,16 @@ impl PoolLockGuard<'_> {
     }
 
     /// Calculate diff between two pool dirs
+    ///
+    /// Changed entries are recorded with the size of the file below `path`.
     pub(crate) fn diff_dirs(&self, path: &Path, other_path: &Path) -> Result<Diff, Error> {
         let mut diff = Diff::default();
 
         let handle_entry = |entry: Result<walkdir::DirEntry, walkdir::Error>,
                             base: &Path,
                             other_base: &Path,
-                            changed: Option<&mut Vec<(PathBuf, u64)>>,
-                            missing: &mut Vec<(PathBuf, u64)>|
+                            changed: Option<&mut DiffMember>,
+                            missing: &mut DiffMember|
          -> Result<(), Error> {
             let path = entry?.into_path();
 
@@ -580,15 +1157,14 @@ impl PoolLockGuard<'_> {
             if absolute.exists() {
                 if let Some(changed) = changed {
                     let other_meta = absolute.metadata()?;
-                    if other_meta.st_ino() != meta.st_ino() {
-                        changed.push((
-                            relative.to_path_buf(),
-                            meta.st_size().abs_diff(other_meta.st_size()),
-                        ));
+                    if other_meta.st_ino() != meta.st_ino()
+                        && !(self.cross_device && is_same_content(&path, &absolute)?)
+                    {
+                        changed.push(relative.to_path_buf(), meta.st_size(), meta.st_ino());
                     }
                 }
             } else {
-                missing.push((relative.to_path_buf(), meta.st_size()));
+                missing.push(relative.to_path_buf(), meta.st_size(), meta.st_ino());
             }
 
             Ok(())
@@ -602,20 +1178,20 @@ impl PoolLockGuard<'_> {
                 entry,
                 &path,
                 &other_path,
-                Some(&mut diff.changed.paths),
-                &mut diff.removed.paths,
+                Some(&mut diff.changed),
+                &mut diff.removed,
             )
         })?;
         WalkDir::new(&other_path)
             .into_iter()
-            .try_for_each(|entry| {
-                handle_entry(entry, &other_path, &path, None, &mut diff.added.paths)
-            })?;
+            .try_for_each(|entry| handle_entry(entry, &other_path, &path, None, &mut diff.added))?;
 
         Ok(diff)
     }
 
     /// Calculate diff between two pools
+    ///
+    /// Changed entries are recorded with the size of the file in this pool.
     pub(crate) fn diff_pools(&self, other: &Pool) -> Result<Diff, Error> {
         let mut diff = Diff::default();
 
@@ -624,8 +1200,8 @@ impl PoolLockGuard<'_> {
                             pool_csums: &HashMap<u64, CheckSums>,
                             other_pool: &Pool,
                             other_csums: &HashMap<u64, CheckSums>,
-                            changed: Option<&mut Vec<(PathBuf, u64)>>,
-                            missing: &mut Vec<(PathBuf, u64)>|
+                            changed: Option<&mut DiffMember>,
+                            missing: &mut DiffMember|
          -> Result<(), Error> {
             let path = entry?.into_path();
 
@@ -640,32 +1216,30 @@ impl PoolLockGuard<'_> {
             let absolute = other_pool.get_path(relative)?;
             if absolute.exists() {
                 if let Some(changed) = changed {
-                    let csum = match pool_csums.get(&meta.st_ino()) {
+                    let csum = match pool.lookup_link_csum(&path, &meta, pool_csums)? {
                         Some(csum) => csum,
                         None => {
                             eprintln!("{path:?} path not registered with pool.");
-                            changed.push((relative.to_path_buf(), 0)); // TODO add warning/error field?
+                            changed.push(relative.to_path_buf(), 0, meta.st_ino()); // TODO add warning/error field?
                             return Ok(());
                         }
                     };
                     let other_meta = absolute.metadata()?;
-                    let other_csum = match other_csums.get(&other_meta.st_ino()) {
-                        Some(csum) => csum,
-                        None => {
-                            eprintln!("{absolute:?} path not registered with pool.");
-                            changed.push((relative.to_path_buf(), 0)); // TODO add warning/error field?
-                            return Ok(());
-                        }
-                    };
+                    let other_csum =
+                        match other_pool.lookup_link_csum(&absolute, &other_meta, other_csums)? {
+                            Some(csum) => csum,
+                            None => {
+                                eprintln!("{absolute:?} path not registered with pool.");
+                                changed.push(relative.to_path_buf(), 0, meta.st_ino()); // TODO add warning/error field?
+                                return Ok(());
+                            }
+                        };
                     if csum != other_csum {
-                        changed.push((
-                            relative.to_path_buf(),
-                            meta.st_size().abs_diff(other_meta.st_size()),
-                        ));
+                        changed.push(relative.to_path_buf(), meta.st_size(), meta.st_ino());
                     }
                 }
             } else {
-                missing.push((relative.to_path_buf(), meta.st_size()));
+                missing.push(relative.to_path_buf(), meta.st_size(), meta.st_ino());
             }
 
             Ok(())
@@ -684,8 +1258,8 @@ impl PoolLockGuard<'_> {
                     &csums,
                     &other,
                     &other_csums,
-                    Some(&mut diff.changed.paths),
-                    &mut diff.removed.paths,
+                    Some(&mut diff.changed),
+                    &mut diff.removed,
                 )
             })?;
         WalkDir::new(&other.link_dir)
@@ -698,13 +1272,423 @@ impl PoolLockGuard<'_> {
                     self,
                     &csums,
                     None,
-                    &mut diff.added.paths,
+                    &mut diff.added,
                 )
             })?;
 
         Ok(diff)
     }
 
+    /// Count the distinct files referenced in `link_dir` that are missing in the `target` pool, and
+    /// their total size. Files linked at multiple paths are counted once, paths below `skip`
+    /// (relative to `link_dir`) are ignored. Without `target`, all files are counted.
+    pub(crate) fn missing_in(
+        &self,
+        target: Option<&Pool>,
+        skip: &[PathBuf],
+    ) -> Result<(usize, u64), Error> {
+        let (inode_map, _link_count) = self.get_inode_csum_map()?;
+        let mut seen = HashSet::new();
+        let mut files = 0;
+        let mut bytes = 0;
+
+        for link_entry in WalkDir::new(&self.pool.link_dir).into_iter() {
+            let path = link_entry?.into_path();
+            if self.path_in_pool(&path) {
+                continue;
+            }
+
+            let meta = path.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+
+            let rel_path = path.strip_prefix(&self.pool.link_dir)?;
+            if skip.iter().any(|skipped| rel_path.starts_with(skipped)) {
+                continue;
+            }
+
+            let csum = match self.lookup_link_csum(&path, &meta, &inode_map)? {
+                Some(csum) => csum,
+                None => continue,
+            };
+            if !seen.insert(self.get_checksum_paths(&csum)?.remove(0)) {
+                continue;
+            }
+
+            if target.is_some_and(|target| target.contains(&csum)) {
+                continue;
+            }
+
+            files += 1;
+            bytes += meta.st_size();
+        }
+
+        Ok((files, bytes))
+    }
+
+    /// FSYNC the given files in `pool_dir` or `link_dir` and all directories containing them, to
+    /// persist files and links written without FSYNC.
+    pub(crate) fn fsync_paths(&self, paths: &[PathBuf]) -> Result<(), Error> {
+        let mut dirs = BTreeSet::new();
+
+        for path in paths {
+            fsync_path(path)?;
+
+            for dir in path.ancestors().skip(1) {
+                // ancestors of an already collected directory are collected as well
+                if !dirs.insert(dir.to_path_buf())
+                    || dir == self.pool.pool_dir
+                    || dir == self.pool.link_dir
+                {
+                    break;
+                }
+            }
+        }
+
+        // sub-directories first
+        for dir in dirs.iter().rev() {
+            fsync_path(dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the checksums of the pool file referenced by `path` in `link_dir`.
+    pub(crate) fn checksums_for_link(&self, path: &Path) -> Result<CheckSums, Error> {
+        if !self.path_in_link_dir(path) {
+            bail!("Path {path:?} not inside pool's link directory.");
+        }
+
+        let meta = path.metadata()?;
+        if !meta.is_file() {
+            bail!("Path {path:?} is not a file.");
+        }
+
+        let (inode_map, _link_count) = self.get_inode_csum_map()?;
+        self.lookup_link_csum(path, &meta, &inode_map)?
+            .ok_or_else(|| format_err!("Path {path:?} is not registered with pool."))
+    }
+
+    /// Verify the contents of every checksum file in `pool_dir` against its file name.
+    ///
+    /// If `fix` is set, mismatching files are removed from the pool, so that they are fetched again
+    /// when creating the next snapshot. Existing links in `link_dir` are not touched.
+    pub(crate) fn scrub(&self, fix: bool) -> Result<ScrubReport, Error> {
+        let mut report = ScrubReport::default();
+
+        for pool_entry in WalkDir::new(&self.pool.pool_dir).into_iter() {
+            let path = pool_entry?.into_path();
+            if path == self.lock_path() || !path.metadata()?.is_file() {
+                continue;
+            }
+
+            let parent_dir_name = path
+                .parent()
+                .and_then(|parent_dir| parent_dir.file_name())
+                .and_then(|dir_name| dir_name.to_str());
+
+            let expected = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_lowercase(),
+                None => {
+                    eprintln!("skipping unknown pool path {path:?}");
+                    continue;
+                }
+            };
+
+            let actual = match parent_dir_name {
+                Some("sha256") => hash_file(&path, MessageDigest::sha256())?,
+                Some("sha512") => hash_file(&path, MessageDigest::sha512())?,
+                _ => {
+                    eprintln!("skipping unknown pool path {path:?}");
+                    continue;
+                }
+            };
+
+            report.checked += 1;
+            if actual == expected {
+                report.ok += 1;
+                continue;
+            }
+
+            report.mismatched += 1;
+            eprintln!("Checksum mismatch for {path:?} - got {actual}");
+            if fix {
+                unistd::unlink(&path)
+                    .map_err(|err| format_err!("Failed to remove {path:?} - {err}"))?;
+                report.removed += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Merge SHA-256 and SHA-512 checksum files with identical contents into a single inode.
+    ///
+    /// Such duplicates can be left over from switching the hash preference, or from mirrors
+    /// sharing a pool. Both checksums are verified by reading the files, the SHA-256 path and all
+    /// its links in `link_dir` are then replaced by hardlinks to the SHA-512 file. Duplicates that
+    /// have additional links outside of `link_dir` (e.g. from other mirrors sharing the pool
+    /// directory) are skipped, since those links would otherwise become orphaned.
+    pub(crate) fn compact(&self) -> Result<CompactReport, Error> {
+        let mut report = CompactReport::default();
+        let sha512_dir = self.pool.pool_dir.join("sha512");
+        let sha256_dir = self.pool.pool_dir.join("sha256");
+
+        // inode of SHA-256 file => (SHA-512 file, SHA-256 file, link count, size)
+        let mut candidates: HashMap<u64, (PathBuf, PathBuf, u64, u64)> = HashMap::new();
+
+        if sha512_dir.exists() {
+            for pool_entry in WalkDir::new(&sha512_dir).into_iter() {
+                let path = pool_entry?.into_path();
+                let meta = path.metadata()?;
+                if !meta.is_file() {
+                    continue;
+                }
+
+                let sha256 = hash_file(&path, MessageDigest::sha256())?;
+                let sha256_path = sha256_dir.join(&sha256);
+                let sha256_meta = match sha256_path.metadata() {
+                    Ok(sha256_meta) => sha256_meta,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(err) => bail!("Failed to stat {sha256_path:?} - {err}"),
+                };
+
+                if sha256_meta.st_ino() == meta.st_ino() {
+                    continue;
+                }
+
+                let expected = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map(|name| name.to_lowercase());
+                if expected.as_deref() != Some(&hash_file(&path, MessageDigest::sha512())?) {
+                    eprintln!("Skipping {path:?} - checksum mismatch");
+                    continue;
+                }
+                if hash_file(&sha256_path, MessageDigest::sha256())? != sha256 {
+                    eprintln!("Skipping {sha256_path:?} - checksum mismatch");
+                    continue;
+                }
+
+                candidates.insert(
+                    sha256_meta.st_ino(),
+                    (path, sha256_path, sha256_meta.st_nlink(), sha256_meta.len()),
+                );
+            }
+        }
+
+        if candidates.is_empty() {
+            return Ok(report);
+        }
+
+        // links are plain copies if link_dir is on another file system
+        let mut links: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        if !self.pool.cross_device {
+            for link_entry in WalkDir::new(&self.pool.link_dir).into_iter() {
+                let path = link_entry?.into_path();
+                if self.path_in_pool(&path) {
+                    continue;
+                }
+
+                let meta = path.metadata()?;
+                if meta.is_file() && candidates.contains_key(&meta.st_ino()) {
+                    links.entry(meta.st_ino()).or_default().push(path);
+                }
+            }
+        }
+
+        for (inode, (sha512_path, sha256_path, link_count, size)) in candidates {
+            let links = links.remove(&inode).unwrap_or_default();
+            if link_count != 1 + links.len() as u64 {
+                eprintln!(
+                    "Skipping {sha256_path:?} - referenced from outside of {:?}",
+                    self.pool.link_dir
+                );
+                report.inodes_skipped += 1;
+                continue;
+            }
+
+            for path in std::iter::once(&sha256_path).chain(links.iter()) {
+                replace_with_link(&sha512_path, path)?;
+            }
+
+            report.inodes_merged += 1;
+            report.bytes_saved += size;
+        }
+
+        Ok(report)
+    }
+
+    /// Check that every file in `link_dir` references a checksum file in `pool_dir`.
+    ///
+    /// Files without corresponding checksum file (e.g. left over by an interrupted GC) are
+    /// reported as dangling, and removed if `fix` is set. In contrast to [Self::scrub], file
+    /// contents are only read if `link_dir` and `pool_dir` are on different file systems.
+    pub(crate) fn verify_links(&self, fix: bool) -> Result<VerifyLinksReport, Error> {
+        let (inode_map, _link_count) = self.get_inode_csum_map()?;
+        let mut report = VerifyLinksReport::default();
+
+        for link_entry in WalkDir::new(&self.pool.link_dir).into_iter() {
+            let path = link_entry?.into_path();
+            if self.path_in_pool(&path) {
+                continue;
+            }
+
+            let meta = path.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+
+            report.total_checked += 1;
+            if self.lookup_link_csum(&path, &meta, &inode_map)?.is_none() {
+                eprintln!("Dangling link {path:?}");
+                report.dangling.push(path);
+            }
+        }
+
+        if fix {
+            for path in &report.dangling {
+                unistd::unlink(path)
+                    .map_err(|err| format_err!("Failed to remove {path:?} - {err}"))?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// List checksum files in `pool_dir` that are not referenced by any file in `link_dir`, and
+    /// would thus be removed by the next GC.
+    pub(crate) fn list_unlinked_pool_files(&self) -> Result<Vec<PathBuf>, Error> {
+        let (inode_map, _link_count) = self.get_inode_csum_map()?;
+        let (_copies, copied_inodes) = self.collect_copies(&inode_map)?;
+        let mut unlinked = Vec::new();
+
+        for pool_entry in WalkDir::new(&self.pool.pool_dir).into_iter() {
+            let path = pool_entry?.into_path();
+            let meta = path.metadata()?;
+            if !meta.is_file() || copied_inodes.contains(&meta.st_ino()) {
+                continue;
+            }
+
+            if let Some(csum) = inode_map.get(&meta.st_ino()) {
+                if self.link_count_for(csum)? == 0 {
+                    unlinked.push(path);
+                }
+            }
+        }
+
+        Ok(unlinked)
+    }
+
+    /// Write a CSV index with one row per file in `link_dir` to `dest`, containing the checksums
+    /// of the referenced pool file, the path relative to `link_dir` and the file size.
+    ///
+    /// Returns the number of written rows.
+    pub(crate) fn export_index(&self, dest: &Path) -> Result<usize, Error> {
+        let file =
+            File::create(dest).map_err(|err| format_err!("Failed to create {dest:?} - {err}"))?;
+        self.write_index(file)
+    }
+
+    /// Same as [Self::export_index], but writing to an arbitrary writer.
+    pub(crate) fn write_index<W: Write>(&self, writer: W) -> Result<usize, Error> {
+        let (inode_map, _link_count) = self.get_inode_csum_map()?;
+        let mut writer = csv::Writer::from_writer(writer);
+        let mut rows = 0;
+
+        writer.write_record(["sha256_hex", "sha512_hex", "link_path", "file_size_bytes"])?;
+
+        for link_entry in WalkDir::new(&self.pool.link_dir)
+            .sort_by_file_name()
+            .into_iter()
+        {
+            let path = link_entry?.into_path();
+            if self.path_in_pool(&path) {
+                continue;
+            }
+
+            let meta = path.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+
+            let csum = match self.lookup_link_csum(&path, &meta, &inode_map)? {
+                Some(csum) => csum,
+                None => {
+                    eprintln!("skipping dangling link {path:?}");
+                    continue;
+                }
+            };
+
+            let rel_path = path.strip_prefix(&self.pool.link_dir)?;
+            writer.write_record([
+                csum.sha256.map(hex::encode).unwrap_or_default(),
+                csum.sha512.map(hex::encode).unwrap_or_default(),
+                rel_path.to_string_lossy().into_owned(),
+                meta.st_size().to_string(),
+            ])?;
+            rows += 1;
+        }
+
+        writer.flush()?;
+
+        Ok(rows)
+    }
+
+    /// Collect number and total size of unique files in the pool.
+    pub(crate) fn stats(&self) -> Result<PoolStats, Error> {
+        let mut inodes = HashSet::new();
+        let mut stats = PoolStats::default();
+
+        for pool_entry in WalkDir::new(&self.pool.pool_dir).into_iter() {
+            let path = pool_entry?.into_path();
+            if path == self.lock_path() {
+                continue;
+            }
+
+            let meta = path.metadata()?;
+            if meta.is_file() && inodes.insert(meta.st_ino()) {
+                stats.files += 1;
+                stats.bytes += meta.st_size();
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Compare the size of all files in `link_dir` to the size of the unique files in `pool_dir`.
+    ///
+    /// Note that `pool_dir` can be shared by multiple pools, in which case its files are
+    /// accounted for in full.
+    pub(crate) fn compute_dedup_ratio(&self) -> Result<DeduplicationStats, Error> {
+        let mut stats = DeduplicationStats::default();
+
+        for link_entry in WalkDir::new(&self.pool.link_dir).into_iter() {
+            let path = link_entry?.into_path();
+            if self.path_in_pool(&path) {
+                continue;
+            }
+
+            let meta = path.metadata()?;
+            if meta.is_file() {
+                stats.link_count += 1;
+                stats.logical_bytes += meta.st_size();
+            }
+        }
+
+        let pool_stats = self.stats()?;
+        stats.unique_file_count = pool_stats.files;
+        stats.physical_bytes = pool_stats.bytes;
+        stats.ratio = if stats.physical_bytes == 0 {
+            1.0
+        } else {
+            stats.logical_bytes as f64 / stats.physical_bytes as f64
+        };
+
+        Ok(stats)
+    }
+
     pub(crate) fn list_files(&self) -> Result<Vec<(PathBuf, Metadata)>, Error> {
         let mut file_list = Vec::new();
         WalkDir::new(&self.link_dir)
@@ -721,7 +1705,56 @@ impl PoolLockGuard<'_> {
     }
 }
 
-fn link_file_do(source: &Path, target: &Path) -> Result<bool, Error> {
+// Helper to atomically replace `target` with a hardlink to `source`.
+fn replace_with_link(source: &Path, target: &Path) -> Result<(), Error> {
+    let mut tmp = target.as_os_str().to_owned();
+    tmp.push(".compact.tmp");
+    let tmp = PathBuf::from(tmp);
+
+    hard_link(source, &tmp)
+        .map_err(|err| format_err!("Failed to link {source:?} to {tmp:?} - {err}"))?;
+    if let Err(err) = std::fs::rename(&tmp, target) {
+        let _ = std::fs::remove_file(&tmp);
+        bail!("Failed to replace {target:?} - {err}");
+    }
+
+    Ok(())
+}
+
+// Helper to calculate a checksum over a file's content without reading it into memory at once.
+fn hash_file(path: &Path, digest: MessageDigest) -> Result<String, Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new(digest)?;
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read])?;
+    }
+
+    Ok(hex::encode(hasher.finish()?))
+}
+
+// Helper to check whether `source` and `target` are copies of each other (of same size and
+// content hash).
+fn is_same_content(source: &Path, target: &Path) -> Result<bool, Error> {
+    if source.metadata()?.st_size() != target.metadata()?.st_size() {
+        return Ok(false);
+    }
+
+    Ok(hash_file(source, MessageDigest::sha256())? == hash_file(target, MessageDigest::sha256())?)
+}
+
+// Helper to check whether `link_dir` and `pool_dir` reside on different file systems.
+fn is_cross_device(link_dir: &Path, pool_dir: &Path) -> Result<bool, Error> {
+    Ok(link_dir.metadata()?.st_dev() != pool_dir.metadata()?.st_dev())
+}
+
+// Helper to hardlink `source` to `target`. If `allow_copy` is set, files are copied if hardlinking
+// fails because they are on different file systems.
+fn link_file_do(source: &Path, target: &Path, allow_copy: bool) -> Result<bool, Error> {
     ensure_parent_dir_exists(target)?;
     if !source.exists() {
         bail!("Cannot link file that doesn't exist.");
@@ -730,7 +1763,7 @@ fn link_file_do(source: &Path, target: &Path) -> Result<bool, Error> {
     if target.exists() {
         let source_inode = source.metadata()?.st_ino();
         let target_inode = target.metadata()?.st_ino();
-        if source_inode == target_inode {
+        if source_inode == target_inode || (allow_copy && is_same_content(source, target)?) {
             return Ok(false);
         } else {
             bail!(
@@ -741,8 +1774,16 @@ fn link_file_do(source: &Path, target: &Path) -> Result<bool, Error> {
         }
     }
 
-    hard_link(source, target)
-        .map_err(|err| format_err!("Failed to link {:?} at {:?} - {}", source, target, err))?;
+    match hard_link(source, target) {
+        Err(err) if allow_copy && err.raw_os_error() == Some(libc::EXDEV) => {
+            std::fs::copy(source, target).map_err(|err| {
+                format_err!("Failed to copy {:?} to {:?} - {}", source, target, err)
+            })?;
+        }
+        res => {
+            res.map_err(|err| format_err!("Failed to link {:?} at {:?} - {}", source, target, err))?
+        }
+    }
 
     Ok(true)
 }
@@ -753,6 +1794,12 @@ fn ensure_parent_dir_exists(path: &Path) -> Result<(), Error> {
     create_path(parent, None, None).map(|_| ())
 }
 
+fn fsync_path(path: &Path) -> Result<(), Error> {
+    File::open(path)
+        .and_then(|file| file.sync_all())
+        .map_err(|err| format_err!("Failed to fsync {path:?} - {err}"))
+}
+
 impl Deref for PoolLockGuard<'_> {
     type Target = Pool;
 
@@ -760,3 +1807,49 @@ impl Deref for PoolLockGuard<'_> {
         self.pool
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Helper to get an empty, process-specific directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "proxmox-offline-mirror-test-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn checksums(data: &[u8]) -> CheckSums {
+        CheckSums {
+            sha256: Some(openssl::sha::sha256(data)),
+            sha512: Some(openssl::sha::sha512(data)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn link_count_for() -> Result<(), Error> {
+        let dir = test_dir("link-count");
+        let pool = Pool::create(&dir.join("links"), &dir.join("pool"))?;
+
+        let data = b"link count test";
+        let csums = checksums(data);
+        {
+            let locked = pool.lock()?;
+            locked.add_file(data, &csums, false)?;
+            assert_eq!(pool.link_count_for(&csums)?, 0);
+
+            locked.link_file(&csums, Path::new("a/file"))?;
+            locked.link_file(&csums, Path::new("b/file"))?;
+        }
+        assert_eq!(pool.link_count_for(&csums)?, 2);
+
+        assert!(pool.link_count_for(&checksums(b"missing")).is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
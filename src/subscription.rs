@@ -2,9 +2,8 @@ use anyhow::{Error, bail, format_err};
 
 use proxmox_http::client::sync::Client;
 use proxmox_http::{HttpClient, HttpOptions, ProxyConfig};
-use proxmox_subscription::SubscriptionStatus;
 use proxmox_subscription::{
-    ProductType, SubscriptionInfo,
+    ProductType, SubscriptionInfo, SubscriptionStatus,
     sign::{SignRequest, SignedResponse},
 };
 
@@ -24,51 +23,87 @@ fn client() -> Result<Client, Error> {
 
 pub fn extract_mirror_key(keys: &[SubscriptionKey]) -> Result<SubscriptionKey, Error> {
     keys.iter()
-        .find(|k| {
-            if k.product() != ProductType::Pom {
-                return false;
-            }
-            if let Ok(Some(info)) = k.info() {
-                info.status == SubscriptionStatus::Active
-            } else {
-                false
-            }
-        })
+        .find(|k| k.product().is_ok_and(|p| p == ProductType::Pom) && k.is_active())
         .ok_or_else(|| format_err!("No active mirror subscription key configured!"))
         .cloned()
 }
 
+/// Result of refreshing a batch of offline keys.
+pub struct BatchRefreshResult {
+    /// Signed subscription info of all successfully refreshed keys
+    pub successful: Vec<SubscriptionInfo>,
+    /// Keys that failed to refresh, together with the error
+    pub failed: Vec<(String, Error)>,
+}
+
+impl BatchRefreshResult {
+    /// Returns the successfully refreshed keys, or an error if refreshing any key failed.
+    pub fn into_result(self) -> Result<Vec<SubscriptionInfo>, Error> {
+        if self.failed.is_empty() {
+            return Ok(self.successful);
+        }
+
+        for (key, err) in &self.failed {
+            eprintln!("Failed to refresh subscription key {key} - {err}");
+        }
+        bail!("Refresh error - see above.");
+    }
+}
+
 /// Refresh `offline_keys` using `mirror_key`.
 ///
 /// This consists of two phases:
-/// 1. refresh all the offline keys (so that the info downloaded in step 3 is current)
-/// 2. get updated signed blobs for all offline keys (for transfer to offline systems)
+/// 1. refresh all the offline keys (so that the info downloaded in step 2 is current)
+/// 2. get updated signed blobs for all successfully refreshed offline keys (for transfer to
+///    offline systems)
+///
+/// A failure to refresh a single key doesn't abort the refresh of the remaining keys, but is
+/// recorded in the returned `BatchRefreshResult`. `on_key_refreshed` is called after each key's
+/// refresh, with `SubscriptionStatus::Invalid` if it failed.
 pub fn refresh_offline_keys(
     mirror_key: SubscriptionKey,
     mut offline_keys: Vec<SubscriptionKey>,
     public_key: openssl::pkey::PKey<openssl::pkey::Public>,
-) -> Result<Vec<SubscriptionInfo>, Error> {
-    let mut errors = false;
+    on_key_refreshed: Option<Box<dyn Fn(&str, &SubscriptionStatus)>>,
+) -> Result<BatchRefreshResult, Error> {
+    let mut failed = Vec::new();
 
-    offline_keys.retain(|k| k.product() != ProductType::Pom);
-    if offline_keys.is_empty() {
-        return Ok(vec![]);
-    }
+    offline_keys.retain(|k| !k.product().is_ok_and(|p| p == ProductType::Pom));
 
-    for key in &offline_keys {
-        if let Err(err) = proxmox_subscription::check::check_subscription(
-            key.key.clone(),
-            key.server_id.clone(),
-            PRODUCT_URL.to_string(),
-            client()?,
-        ) {
-            errors = true;
-            eprintln!("Failed to refresh subscription key {} - {}", key.key, err);
+    offline_keys.retain(|key| {
+        let res = client().and_then(|client| {
+            proxmox_subscription::check::check_subscription(
+                key.key.clone(),
+                key.server_id.clone(),
+                PRODUCT_URL.to_string(),
+                client,
+            )
+        });
+
+        let status = match &res {
+            Ok(info) => info.status,
+            Err(_) => SubscriptionStatus::Invalid,
+        };
+        if let Some(on_key_refreshed) = &on_key_refreshed {
+            on_key_refreshed(&key.key, &status);
         }
+
+        match res {
+            Ok(_) => true,
+            Err(err) => {
+                failed.push((key.key.clone(), err));
+                false
+            }
+        }
+    });
+
+    if offline_keys.is_empty() {
+        return Ok(BatchRefreshResult {
+            successful: vec![],
+            failed,
+        });
     }
-    if errors {
-        bail!("Refresh error - see above.");
-    }
+
     let request = SignRequest {
         mirror_key: mirror_key.into(),
         blobs: offline_keys.into_iter().map(|k| k.into()).collect(),
@@ -82,7 +117,10 @@ pub fn refresh_offline_keys(
     if res.status().is_success() {
         let body: Vec<u8> = res.into_body();
         let res: SignedResponse = serde_json::from_slice(&body)?;
-        res.verify(&public_key)
+        Ok(BatchRefreshResult {
+            successful: res.verify(&public_key)?,
+            failed,
+        })
     } else {
         bail!("Refresh failed - {}", res.status());
     }
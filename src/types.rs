@@ -1,9 +1,17 @@
-use std::{fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    ops::{Add, AddAssign},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use anyhow::Error;
+use anyhow::{Error, bail, format_err};
 use proxmox_schema::{ApiStringFormat, Schema, StringSchema, api, const_regex};
 use proxmox_serde::{forward_deserialize_to_from_str, forward_serialize_to_display};
+use proxmox_subscription::ProductType;
 use proxmox_time::{epoch_i64, epoch_to_rfc3339_utc, parse_rfc3339};
+use serde::{Deserialize, Serialize};
 
 #[rustfmt::skip]
 #[macro_export]
@@ -18,6 +26,31 @@ const_regex! {
 pub const PROXMOX_SAFE_ID_FORMAT: ApiStringFormat =
     ApiStringFormat::Pattern(&PROXMOX_SAFE_ID_REGEX);
 
+fn verify_absolute_path(path: &str) -> Result<(), Error> {
+    if !Path::new(path).is_absolute() {
+        bail!("'{path}' is not an absolute path");
+    }
+    Ok(())
+}
+
+pub const ABSOLUTE_PATH_FORMAT: ApiStringFormat = ApiStringFormat::VerifyFn(verify_absolute_path);
+
+/// Package priorities defined by the Debian policy, from most to least important.
+pub const PACKAGE_PRIORITIES: &[&str] = &["required", "important", "standard", "optional", "extra"];
+
+fn verify_package_priority(priority: &str) -> Result<(), Error> {
+    if !PACKAGE_PRIORITIES.contains(&priority) {
+        bail!(
+            "unknown package priority '{priority}', expected one of: {}",
+            PACKAGE_PRIORITIES.join(", ")
+        );
+    }
+    Ok(())
+}
+
+pub const PACKAGE_PRIORITY_FORMAT: ApiStringFormat =
+    ApiStringFormat::VerifyFn(verify_package_priority);
+
 /// Schema for config IDs
 pub const MIRROR_ID_SCHEMA: Schema = StringSchema::new("Mirror name.")
     .format(&PROXMOX_SAFE_ID_FORMAT)
@@ -46,6 +79,30 @@ pub const PROXMOX_SUBSCRIPTION_KEY_SCHEMA: Schema = StringSchema::new("Subscript
     .format(&PROXMOX_SUBSCRIPTION_KEY_FORMAT)
     .schema();
 
+/// Parse a product type, either from its short name (`pve`) or the full product name
+/// (`proxmox-ve`), ignoring case.
+pub fn parse_product_type(value: &str) -> Result<ProductType, Error> {
+    match value.to_ascii_lowercase().as_str() {
+        "pve" | "proxmox-ve" => Ok(ProductType::Pve),
+        "pmg" | "proxmox-mail-gateway" => Ok(ProductType::Pmg),
+        "pbs" | "proxmox-backup" | "proxmox-backup-server" => Ok(ProductType::Pbs),
+        "pom" | "proxmox-offline-mirror" => Ok(ProductType::Pom),
+        _ => bail!("Unknown product type '{value}'"),
+    }
+}
+
+fn verify_product_type(value: &str) -> Result<(), Error> {
+    parse_product_type(value).map(|_| ())
+}
+
+pub const PRODUCT_TYPE_FORMAT: ApiStringFormat = ApiStringFormat::VerifyFn(verify_product_type);
+
+pub const PRODUCT_TYPE_SCHEMA: Schema = StringSchema::new(
+    "Proxmox product, either by short name (e.g. 'pve') or full name (e.g. 'proxmox-ve').",
+)
+.format(&PRODUCT_TYPE_FORMAT)
+.schema();
+
 #[rustfmt::skip]
 #[macro_export]
 macro_rules! PROXMOX_SERVER_ID_REGEX_STR { () => { r"[a-fA-F0-9]{32}" }; }
@@ -60,28 +117,79 @@ pub const PROXMOX_SERVER_ID_SCHEMA: Schema = StringSchema::new("Server ID.")
     .format(&PROXMOX_SERVER_ID_FORMAT)
     .schema();
 
+#[rustfmt::skip]
+macro_rules! TEMPLATE_VAR_RE { () => { r"[A-Za-z_][A-Za-z0-9_]*=[^,=\s]+" }; }
+
+const_regex! {
+    TEMPLATE_VARS_REGEX = concat!(r"^", TEMPLATE_VAR_RE!(), r"(?:,", TEMPLATE_VAR_RE!(), r")*$");
+}
+
+/// Comma-separated list of `NAME=VALUE` template variables.
+pub const TEMPLATE_VARS_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&TEMPLATE_VARS_REGEX);
+
 #[rustfmt::skip]
 #[macro_export]
 macro_rules! SNAPSHOT_RE { () => (r"[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9]{2}:[0-9]{2}:[0-9]{2}Z") }
 const_regex! {
     pub(crate) SNAPSHOT_REGEX = concat!(r"^", SNAPSHOT_RE!() ,r"$");
+    // matches both complete snapshot directories and leftover temporary ones
+    pub(crate) SNAPSHOT_DIR_REGEX = concat!(r"^", SNAPSHOT_RE!() ,r"(\.tmp)?$");
 }
 
 #[api(
     type: String,
     format: &ApiStringFormat::Pattern(&SNAPSHOT_REGEX),
 )]
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
 /// Mirror snapshot
+///
+/// Named after its creation time as RFC 3339 UTC timestamp of the form `YYYY-MM-DDTHH:MM:SSZ`,
+/// for example `2024-01-31T12:00:00Z`.
+///
+/// (De)serialized as its name, so it can also be used as key of maps serialized as JSON objects.
 pub struct Snapshot(i64);
 
 forward_serialize_to_display!(Snapshot);
 forward_deserialize_to_from_str!(Snapshot);
 
 impl Snapshot {
+    // range of epochs with a four digit year, 0000-01-01T00:00:00Z to 9999-12-31T23:59:59Z
+    const MIN_EPOCH: i64 = -62_167_219_200;
+    const MAX_EPOCH: i64 = 253_402_300_799;
+
     pub fn now() -> Self {
         Self(epoch_i64())
     }
+
+    /// Create a snapshot for the given epoch, failing if it can't be represented as snapshot name.
+    pub fn from_epoch(secs: i64) -> Result<Self, Error> {
+        if !(Self::MIN_EPOCH..=Self::MAX_EPOCH).contains(&secs) {
+            bail!("epoch {secs} can't be represented as snapshot name");
+        }
+        Ok(Self(secs))
+    }
+
+    /// Whether `s` is a valid snapshot name of the form `YYYY-MM-DDTHH:MM:SSZ`.
+    pub fn is_valid_name(s: &str) -> bool {
+        SNAPSHOT_REGEX.is_match(s)
+    }
+
+    /// Parse a snapshot name, rejecting anything not of the form `YYYY-MM-DDTHH:MM:SSZ`.
+    ///
+    /// Unlike the `FromStr` implementation, this doesn't accept other RFC 3339 timestamps (for
+    /// example with a non-UTC offset).
+    pub fn from_str_strict(s: &str) -> Result<Self, Error> {
+        if !Self::is_valid_name(s) {
+            bail!("invalid snapshot name '{s}' - expected 'YYYY-MM-DDTHH:MM:SSZ'");
+        }
+        s.parse()
+            .map_err(|err| format_err!("invalid snapshot name '{s}' - {err}"))
+    }
+
+    /// Epoch encoded in the snapshot name.
+    pub fn to_epoch(&self) -> Result<i64, Error> {
+        Ok(self.0)
+    }
 }
 
 impl Display for Snapshot {
@@ -99,10 +207,202 @@ impl FromStr for Snapshot {
     }
 }
 
+/// Result of scrubbing a pool
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScrubReport {
+    /// Number of checked pool files
+    pub checked: usize,
+    /// Number of files whose contents match their checksum file name
+    pub ok: usize,
+    /// Number of files whose contents don't match their checksum file name
+    pub mismatched: usize,
+    /// Number of mismatched files removed from the pool
+    pub removed: usize,
+}
+
+/// Result of compacting a pool
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CompactReport {
+    /// Number of SHA-256 checksum files merged with the SHA-512 checksum file of the same content
+    pub inodes_merged: usize,
+    /// Number of bytes freed by merging
+    pub bytes_saved: u64,
+    /// Number of duplicates skipped since they are referenced from outside the link directory
+    pub inodes_skipped: usize,
+}
+
+/// Estimated size of a new snapshot, based on the repository's indices only
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SizeEstimate {
+    /// Total size of all referenced index files
+    pub index_bytes: u64,
+    /// Total size of all packages listed in the indices, ignoring skip filters
+    pub package_bytes_upper_bound: u64,
+    /// Size of listed packages already contained in the pool
+    pub already_in_pool_estimate: u64,
+    /// Issues encountered while fetching the indices
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Result of checking the links of a pool
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VerifyLinksReport {
+    /// Number of checked files in the link directory
+    pub total_checked: usize,
+    /// Files in the link directory not referencing any file in the pool
+    pub dangling: Vec<PathBuf>,
+}
+
+/// Result of checking a pool for inconsistencies between links and checksum files
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PoolCheckReport {
+    /// Files in the link directory not referencing any file in the pool
+    pub orphaned_links: Vec<PathBuf>,
+    /// Checksum files in the pool not referenced by any file in the link directory
+    pub unlinked_pool_files: Vec<PathBuf>,
+}
+
+/// Package found in a snapshot's package index
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackageMatch {
+    /// Snapshot containing the package
+    pub snapshot: Snapshot,
+    /// Package version
+    pub version: String,
+    /// Package architecture
+    pub architecture: String,
+    /// Path of the package file relative to the repository root
+    pub file: String,
+}
+
+/// State of a snapshot directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotState {
+    /// Snapshot was created successfully
+    Complete,
+    /// Leftover temporary directory of an interrupted snapshot creation
+    Incomplete,
+}
+
+/// Snapshot with additional metadata
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SnapshotSummary {
+    /// The snapshot
+    pub snapshot: Snapshot,
+    /// Creation time as RFC 3339 / ISO 8601 string
+    pub created_at: String,
+    /// Whether the snapshot is pinned
+    pub is_pinned: bool,
+    /// Whether the snapshot only contains some of the mirror's components
+    pub is_partial: bool,
+    /// Whether the snapshot is complete
+    pub state: SnapshotState,
+    /// Number of files in the snapshot (only computed on request)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_count: Option<usize>,
+    /// Total size of files in the snapshot, counting hardlinked files once (only computed on
+    /// request)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+}
+
 /// Entries of Diff
 #[derive(Default)]
 pub struct DiffMember {
     pub paths: Vec<(PathBuf, u64)>,
+    /// Total size of distinct files, counting files linked at multiple paths once
+    pub unique_bytes: u64,
+    // inodes of the entries already accounted for in `unique_bytes`
+    inodes: HashSet<u64>,
+}
+
+impl DiffMember {
+    /// Add an entry for a file with the given inode. Its size only counts towards `unique_bytes`
+    /// if there is no other entry for the same inode.
+    pub(crate) fn push(&mut self, path: PathBuf, size: u64, inode: u64) {
+        if self.inodes.insert(inode) {
+            self.unique_bytes += size;
+        }
+        self.paths.push((path, size));
+    }
+
+    /// Sum of the sizes of all entries.
+    pub fn total_bytes(&self) -> u64 {
+        self.paths.iter().map(|(_path, size)| size).sum()
+    }
+
+    /// Sort entries by path.
+    pub fn sort(&mut self) {
+        self.paths
+            .sort_unstable_by(|(path, _), (other_path, _)| path.cmp(other_path));
+    }
+
+    /// Number of binary (`.deb`) and source (`.dsc`) packages.
+    pub fn package_count(&self) -> usize {
+        self.paths
+            .iter()
+            .filter(|(path, _)| {
+                path.extension()
+                    .is_some_and(|ext| ext == "deb" || ext == "dsc")
+            })
+            .count()
+    }
+
+    /// Number of release files and (compressed) package and source indices.
+    pub fn index_file_count(&self) -> usize {
+        self.paths
+            .iter()
+            .filter(|(path, _)| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name == "InRelease"
+                            || name == "Release"
+                            || name.starts_with("Packages")
+                            || name.starts_with("Sources")
+                    })
+            })
+            .count()
+    }
+}
+
+impl Add for DiffMember {
+    type Output = DiffMember;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for DiffMember {
+    fn add_assign(&mut self, rhs: Self) {
+        // members of different pools, inodes are not comparable
+        self.paths.extend(rhs.paths);
+        self.unique_bytes += rhs.unique_bytes;
+    }
+}
+
+impl Display for DiffMember {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} packages, {} index files ({} files total) totalling {} bytes",
+            self.package_count(),
+            self.index_file_count(),
+            self.paths.len(),
+            self.total_bytes()
+        )
+    }
 }
 
 /// Differences between two pools or pool directories
@@ -112,3 +412,397 @@ pub struct Diff {
     pub changed: DiffMember,
     pub removed: DiffMember,
 }
+
+impl Diff {
+    /// Total size of added files.
+    pub fn total_bytes_added(&self) -> u64 {
+        self.added.total_bytes()
+    }
+
+    /// Total size of removed files.
+    pub fn total_bytes_removed(&self) -> u64 {
+        self.removed.total_bytes()
+    }
+
+    /// Whether there are no differences at all.
+    pub fn is_empty(&self) -> bool {
+        self.added.paths.is_empty()
+            && self.changed.paths.is_empty()
+            && self.removed.paths.is_empty()
+    }
+
+    /// Sort entries of all members by path.
+    pub fn sort(&mut self) {
+        self.added.sort();
+        self.changed.sort();
+        self.removed.sort();
+    }
+
+    /// Summarize the sizes of this diff between source (old) and medium (new).
+    pub fn summary(&self) -> DiffSummary {
+        let source_to_medium_bytes = self.removed.unique_bytes;
+        let changed_bytes = self.changed.unique_bytes;
+        DiffSummary {
+            source_to_medium_bytes,
+            medium_to_source_bytes: self.added.unique_bytes,
+            changed_bytes,
+            estimated_sync_bytes: source_to_medium_bytes + changed_bytes,
+        }
+    }
+}
+
+/// Sizes of the differences between source and medium, e.g. to estimate the transfer of a sync
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DiffSummary {
+    /// Total size of files missing on the medium, counting hardlinked files once
+    pub source_to_medium_bytes: u64,
+    /// Total size of files only on the medium, counting hardlinked files once
+    pub medium_to_source_bytes: u64,
+    /// Total size of the source version of files differing between source and medium, counting
+    /// hardlinked files once
+    pub changed_bytes: u64,
+    /// Estimated amount of data written by a sync
+    pub estimated_sync_bytes: u64,
+}
+
+impl DiffSummary {
+    /// Add the sizes of `other` to this summary, e.g. to summarize multiple mirrors.
+    pub fn merge(&mut self, other: &DiffSummary) {
+        self.source_to_medium_bytes += other.source_to_medium_bytes;
+        self.medium_to_source_bytes += other.medium_to_source_bytes;
+        self.changed_bytes += other.changed_bytes;
+        self.estimated_sync_bytes += other.estimated_sync_bytes;
+    }
+}
+
+impl Add for Diff {
+    type Output = Diff;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Diff {
+    fn add_assign(&mut self, rhs: Self) {
+        self.added += rhs.added;
+        self.changed += rhs.changed;
+        self.removed += rhs.removed;
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Added: {}; Removed: {}; Changed: {} files",
+            self.added,
+            self.removed,
+            self.changed.paths.len()
+        )
+    }
+}
+
+/// Errors returned by mirror operations.
+///
+/// Errors not covered by a dedicated variant are wrapped as `Other`.
+#[derive(Debug)]
+pub enum MirrorError {
+    /// Timeout while waiting for the pool lock
+    PoolLockTimeout,
+    /// Signature of a release file could not be verified
+    SignatureVerificationFailed(String),
+    /// Fetched data doesn't match expected checksums
+    ChecksumMismatch { url: String, source: Error },
+    /// Failed to fetch data from the repository
+    NetworkError(String),
+    /// Snapshot already exists
+    SnapshotAlreadyExists(Snapshot),
+    /// Snapshot doesn't exist
+    SnapshotNotFound(Snapshot),
+    /// I/O error
+    Io(std::io::Error),
+    /// Any other error
+    Other(Error),
+}
+
+impl Display for MirrorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorError::PoolLockTimeout => f.write_str("timeout while waiting for pool lock"),
+            MirrorError::SignatureVerificationFailed(err) => {
+                write!(f, "signature verification failed - {err}")
+            }
+            MirrorError::ChecksumMismatch { url, source } => {
+                write!(f, "checksum mismatch for '{url}' - {source}")
+            }
+            MirrorError::NetworkError(err) => write!(f, "network error - {err}"),
+            MirrorError::SnapshotAlreadyExists(snapshot) => {
+                write!(f, "snapshot {snapshot} already exists")
+            }
+            MirrorError::SnapshotNotFound(snapshot) => write!(f, "snapshot {snapshot} not found"),
+            MirrorError::Io(err) => write!(f, "I/O error - {err}"),
+            MirrorError::Other(err) => write!(f, "{err:#}"),
+        }
+    }
+}
+
+impl std::error::Error for MirrorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MirrorError::ChecksumMismatch { source, .. } => Some(source.as_ref()),
+            MirrorError::Io(err) => Some(err),
+            MirrorError::Other(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for MirrorError {
+    fn from(err: Error) -> Self {
+        // errors raised as `MirrorError` by helpers are passed through `anyhow::Error`
+        match err.downcast::<MirrorError>() {
+            Ok(err) => err,
+            Err(err) => match err.downcast::<std::io::Error>() {
+                Ok(err) => MirrorError::Io(err),
+                Err(err) => MirrorError::Other(err),
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for MirrorError {
+    fn from(err: std::io::Error) -> Self {
+        MirrorError::Io(err)
+    }
+}
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Format of generated APT repository definitions.
+pub enum SourcesFormat {
+    /// One-line style format (`.list` files).
+    #[default]
+    OneLine,
+    /// deb822 style format (`.sources` files).
+    Deb822,
+}
+
+impl SourcesFormat {
+    /// File extension used by APT for files in this format.
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            SourcesFormat::OneLine => "list",
+            SourcesFormat::Deb822 => "sources",
+        }
+    }
+
+    /// Separator between repository definitions in a single file.
+    pub fn separator(&self) -> &'static str {
+        match self {
+            SourcesFormat::OneLine => "\n",
+            SourcesFormat::Deb822 => "\n\n",
+        }
+    }
+}
+
+/// Severity of an issue found by a preflight check
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreflightSeverity {
+    /// Might cause problems, but doesn't prevent mirroring
+    Warning,
+    /// Prevents mirroring
+    Error,
+}
+
+impl Display for PreflightSeverity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreflightSeverity::Warning => f.write_str("warning"),
+            PreflightSeverity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// Issue found by a preflight check of a mirror config
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PreflightIssue {
+    /// Severity of the issue
+    pub severity: PreflightSeverity,
+    /// Description of the issue
+    pub message: String,
+}
+
+impl PreflightIssue {
+    fn new(severity: PreflightSeverity, message: String) -> Self {
+        Self { severity, message }
+    }
+
+    pub(crate) fn warning(message: String) -> Self {
+        Self::new(PreflightSeverity::Warning, message)
+    }
+
+    pub(crate) fn error(message: String) -> Self {
+        Self::new(PreflightSeverity::Error, message)
+    }
+}
+
+/// Statistics about the files stored in a pool
+#[derive(Clone, Debug, Default)]
+pub struct PoolStats {
+    /// Number of unique files
+    pub files: usize,
+    /// Total size of unique files
+    pub bytes: u64,
+}
+
+/// Result of a garbage collection run
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GcReport {
+    /// Number of removed files, including orphaned links
+    pub files_removed: usize,
+    /// Total size of removed files
+    pub bytes_freed: u64,
+    /// Number of removed files in the link directory which didn't reference a pool file
+    pub orphaned_links_removed: usize,
+    /// Number of unique files remaining in the pool
+    pub pool_files_remaining: usize,
+}
+
+impl GcReport {
+    /// Add the counts of `other` to this report, e.g. to summarize multiple GC runs.
+    pub fn merge(&mut self, other: &GcReport) {
+        self.files_removed += other.files_removed;
+        self.bytes_freed += other.bytes_freed;
+        self.orphaned_links_removed += other.orphaned_links_removed;
+        self.pool_files_remaining += other.pool_files_remaining;
+    }
+}
+
+/// Result of copying a snapshot to another mirror
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CopyReport {
+    /// Number of files newly added to the target pool
+    pub files_copied: usize,
+    /// Number of files already contained in the target pool
+    pub files_shared: usize,
+    /// Total size of newly added files
+    pub bytes_copied: u64,
+}
+
+/// Result of importing a directory as snapshot
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ImportStats {
+    /// Number of files newly added to the pool
+    pub added_files: usize,
+    /// Total size of newly added files
+    pub added_bytes: u64,
+    /// Number of files already contained in the pool
+    pub reused_files: usize,
+    /// Number of newly created links
+    pub links: usize,
+}
+
+/// Storage efficiency of a pool, comparing the size of all links to the size of unique files
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DeduplicationStats {
+    /// Sum of the sizes of all files in the link directory
+    pub logical_bytes: u64,
+    /// Sum of the sizes of all unique files in the pool directory
+    pub physical_bytes: u64,
+    /// Ratio of logical to physical bytes
+    pub ratio: f64,
+    /// Number of files in the link directory
+    pub link_count: usize,
+    /// Number of unique files in the pool directory
+    pub unique_file_count: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_epoch_round_trip() -> Result<(), Error> {
+        for secs in [
+            0,
+            1,
+            1_706_702_400,
+            -1,
+            Snapshot::MIN_EPOCH,
+            Snapshot::MAX_EPOCH,
+        ] {
+            let snapshot = Snapshot::from_epoch(secs)?;
+            assert_eq!(snapshot.to_epoch()?, secs);
+
+            let name = snapshot.to_string();
+            assert!(Snapshot::is_valid_name(&name), "{name}");
+            assert_eq!(Snapshot::from_str_strict(&name)?, snapshot);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_names() -> Result<(), Error> {
+        assert_eq!(Snapshot::from_epoch(0)?.to_string(), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            Snapshot::from_epoch(1_706_702_400)?.to_string(),
+            "2024-01-31T12:00:00Z"
+        );
+        assert_eq!(
+            Snapshot::from_epoch(Snapshot::MIN_EPOCH)?.to_string(),
+            "0000-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            Snapshot::from_epoch(Snapshot::MAX_EPOCH)?.to_string(),
+            "9999-12-31T23:59:59Z"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_epoch_out_of_range() {
+        assert!(Snapshot::from_epoch(Snapshot::MIN_EPOCH - 1).is_err());
+        assert!(Snapshot::from_epoch(Snapshot::MAX_EPOCH + 1).is_err());
+        assert!(Snapshot::from_epoch(i64::MIN).is_err());
+        assert!(Snapshot::from_epoch(i64::MAX).is_err());
+    }
+
+    #[test]
+    fn snapshot_strict_parsing() {
+        assert!(Snapshot::from_str_strict("2024-01-31T12:00:00Z").is_ok());
+        assert!(Snapshot::from_str_strict("2024-01-31T12:00:00+01:00").is_err());
+        assert!(Snapshot::from_str_strict("2024-01-31").is_err());
+        assert!(Snapshot::from_str_strict("2024-01-31T12:00:00Z.tmp").is_err());
+    }
+
+    #[test]
+    fn snapshot_map_keys() -> Result<(), Error> {
+        let mut map: std::collections::HashMap<Snapshot, Vec<String>> = Default::default();
+        map.insert(Snapshot::from_epoch(0)?, vec!["main".to_string()]);
+        map.insert(Snapshot::from_epoch(1_706_702_400)?, Vec::new());
+
+        let value = serde_json::to_value(&map)?;
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "1970-01-01T00:00:00Z": ["main"],
+                "2024-01-31T12:00:00Z": [],
+            })
+        );
+
+        let parsed: std::collections::HashMap<Snapshot, Vec<String>> =
+            serde_json::from_value(value)?;
+        assert_eq!(parsed, map);
+        Ok(())
+    }
+}